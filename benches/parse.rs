@@ -0,0 +1,76 @@
+// Times parsing a large generated document and stylesheet.
+// Run with `cargo bench`. This uses a plain timing loop rather than a benchmarking crate,
+// so the numbers are only good for comparing runs on the same machine.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use browser_engine::{css, html};
+
+const ITERATIONS: u32 = 20;
+
+fn main() {
+    let html_source = big_document(2000);
+    let css_source = big_stylesheet(2000);
+    println!(
+        "document: {} KiB, stylesheet: {} KiB",
+        html_source.len() / 1024,
+        css_source.len() / 1024
+    );
+
+    report(
+        "html parse",
+        time(|| html::parse(black_box(html_source.clone())).unwrap()),
+    );
+    report(
+        "css parse",
+        time(|| css::parse(black_box(css_source.clone())).unwrap()),
+    );
+}
+
+// A document with `sections` copies of a section of nested, attributed elements and text
+fn big_document(sections: usize) -> String {
+    let mut source = String::from("<html><body>");
+    for i in 0..sections {
+        source.push_str(&format!(
+            "<div class=\"section\" id=\"s{}\">\n    <h1 class=\"title\">Section {}</h1>\n    \
+             <p class=\"text\">Lorem ipsum dolor sit amet, consectetur adipiscing elit, sed do \
+             eiusmod tempor incididunt ut labore et dolore magna aliqua.</p>\n    \
+             <p><span class=\"a b c\">inline</span> text <span>more</span></p>\n</div>\n",
+            i, i
+        ));
+    }
+    source.push_str("</body></html>");
+    source
+}
+
+// A stylesheet with `rules` groups of rules using selectors, shorthands and several value types
+fn big_stylesheet(rules: usize) -> String {
+    let mut source = String::new();
+    for i in 0..rules {
+        source.push_str(&format!(
+            "div.section#s{} + p, .title:first-child {{\n    margin: 0 auto 10px;\n    \
+             padding: 4px 8px;\n    color: #336699;\n    width: calc(50% - {}px);\n    \
+             font-family: \"Helvetica Neue\", Arial, sans-serif;\n}}\n\
+             @media (min-width: {}px) {{ .text {{ border: 1px solid #ccc; }} }}\n",
+            i, i, i
+        ));
+    }
+    source
+}
+
+// The fastest of several runs, which is the least disturbed by everything else going on
+fn time<T, F: FnMut() -> T>(mut f: F) -> Duration {
+    (0..ITERATIONS)
+        .map(|_| {
+            let start = Instant::now();
+            black_box(f());
+            start.elapsed()
+        })
+        .min()
+        .unwrap()
+}
+
+fn report(name: &str, duration: Duration) {
+    println!("{:>12}: {:.3} ms", name, duration.as_secs_f64() * 1000.0);
+}
@@ -0,0 +1,16 @@
+// Feeds arbitrary text to both CSS parsers and to the `style` attribute parser, which must
+// return on any input: the strict ones with a result or an error, and the lossy one with a
+// stylesheet.
+// Run with `cargo +nightly fuzz run css_parse` from the repository root.
+
+#![no_main]
+
+use browser_engine::css;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let source = String::from_utf8_lossy(data).into_owned();
+    let _ = css::parse(source.clone());
+    let _ = css::parse_inline_style(source.clone());
+    css::parse_lossy(source);
+});
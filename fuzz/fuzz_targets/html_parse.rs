@@ -0,0 +1,13 @@
+// Feeds arbitrary bytes to both HTML parsers, which must return on any input: the strict
+// one with a tree or an error, and the lossy one with a tree.
+// Run with `cargo +nightly fuzz run html_parse` from the repository root.
+
+#![no_main]
+
+use browser_engine::html;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = html::parse_bytes(data);
+    html::parse_lossy(data);
+});
@@ -1,5 +1,14 @@
 // A simple parser for a tiny subset of css
 
+use std::error::Error;
+use std::fmt;
+
+use crate::log;
+
+mod shorthand;
+
+pub use self::shorthand::{expand_shorthands, expand_substituted};
+
 // Data structures;
 
 #[derive(Debug)]
@@ -11,40 +20,162 @@ pub struct StyleSheet {
 pub struct Rule {
     pub selectors: Vec<Selector>,
     pub declarations: Vec<Declaration>,
+    // The queries of the `@media` block holding the rule. The rule only applies when one of
+    // them matches, or always if there are none.
+    pub media: Vec<MediaQuery>,
+    pub origin: Origin,
+}
+
+// Where a declaration comes from. Later origins override earlier ones, except that
+// `!important` user agent declarations override everything.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Origin {
+    UserAgent,
+    Author,
+    // The `style` attribute of an element
+    Inline,
+}
+
+// A media query: an optional media type, and features that must all hold
+#[derive(Debug, Clone, PartialEq)]
+pub struct MediaQuery {
+    pub media_type: Option<String>,
+    pub features: Vec<MediaFeature>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MediaFeature {
+    // Viewport widths in px
+    MinWidth(f32),
+    MaxWidth(f32),
 }
 
 #[derive(Debug)]
 pub enum Selector {
     Simple(SimpleSelector),
+    // A simple selector for an element, combined with a selector for one of its preceding
+    // element siblings
+    Combined(Box<Selector>, Combinator, SimpleSelector),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Combinator {
+    // `a + b`: the sibling is the one immediately before the element
+    NextSibling,
+    // `a ~ b`: the sibling is any one before the element
+    SubsequentSibling,
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct SimpleSelector {
     pub tag_name: Option<String>,
     pub id: Option<String>,
     pub class: Vec<String>,
+    pub pseudo: Option<PseudoClass>,
+    // Set when the selector targets content generated before or after the element
+    pub pseudo_element: Option<PseudoElement>,
+}
+
+// Structural pseudo-classes
+#[derive(Debug, Clone, PartialEq)]
+pub enum PseudoClass {
+    FirstChild,
+    LastChild,
+    OnlyChild,
+    // `:nth-child(an+b)`, stored as `(a, b)`
+    NthChild(i32, i32),
+    // `:not(...)` with a single simple selector argument
+    Not(Box<SimpleSelector>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PseudoElement {
+    Before,
+    After,
 }
 
 #[derive(Debug)]
 pub struct Declaration {
     pub name: String,
     pub value: Value,
+    // Set by a trailing `!important`
+    pub important: bool,
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Value {
     Keyword(String),
     Length(f32, Unit),
+    // A number without a unit, like `flex-grow: 1`
+    Number(f32),
+    // A quoted string, like `content: "x"`
+    String(String),
     ColorValue(Color),
+    Calc(Calc),
+    // A top-to-bottom `linear-gradient(...)` through evenly spaced color stops
+    LinearGradient { stops: Vec<Color> },
+    // Space-separated component values, like `outline: 2px solid #00f`
+    List(Vec<Value>),
+    // Comma-separated values, like `transition-property: opacity, color`. Each may be a
+    // space-separated `List`.
+    CommaList(Vec<Value>),
+    // The comma-separated families of `font-family`, like `"Helvetica", Arial, sans-serif`
+    FontFamilies(Vec<String>),
+    // A `url(...)` reference, like `background-image: url("logo.png")`
+    Url(String),
+    // A `var(--name, fallback)` reference to a custom property, replaced by the property's
+    // value when styles are computed
+    Var(String, Option<Box<Value>>),
+    // The value of a longhand set by a shorthand holding `var()`: the shorthand's name and
+    // value, which are expanded once the `var()` is substituted
+    PendingSubstitution(String, Box<Value>),
+    // A `width / height` ratio, like `aspect-ratio: 16 / 9`
+    Ratio(f32, f32),
+    // One function of a `transform`, like `translate(10px, 20px)`. Several functions are a
+    // space-separated `List`.
+    Transform(TransformFunction),
+    // The source text of a value the engine doesn't interpret, for a property it recognizes
+    // but doesn't implement
+    Unparsed(String),
     // Insert more values Here
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Unit {
     Px,
+    Percent,
+    // Percentages of the viewport's width and height
+    Vw,
+    Vh,
     // Inst more units here
 }
 
+// The size of the viewport, which `vw` and `vh` lengths are relative to
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Viewport {
+    pub width: f32,
+    pub height: f32,
+}
+
+// The expression inside `calc()`. Only addition and subtraction are supported.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Calc {
+    Length(f32, Unit),
+    Add(Box<Calc>, Box<Calc>),
+    Sub(Box<Calc>, Box<Calc>),
+}
+
+// A function of the `transform` property
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TransformFunction {
+    // Offsets along x and y in px
+    Translate(f32, f32),
+    // Factors along x and y
+    Scale(f32, f32),
+    // A clockwise angle in radians
+    Rotate(f32),
+}
+
 #[derive(Debug, Clone, PartialEq, Default)]
 pub struct Color {
     pub r: u8,
@@ -55,24 +186,476 @@ pub struct Color {
 
 impl Copy for Color {}
 
+// A string that isn't a hex color
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColorParseError {
+    pub input: String,
+}
+
+impl fmt::Display for ColorParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Invalid hex color {:?}", self.input)
+    }
+}
+
+impl Error for ColorParseError {}
+
+impl Color {
+    pub fn from_rgba(r: u8, g: u8, b: u8, a: u8) -> Color {
+        Color { r, g, b, a }
+    }
+
+    // Parse a `#rgb`, `#rgba`, `#rrggbb` or `#rrggbbaa` color
+    pub fn from_hex(s: &str) -> Result<Color, ColorParseError> {
+        let invalid = || ColorParseError {
+            input: s.to_string(),
+        };
+        let digits = s.strip_prefix('#').ok_or_else(invalid)?;
+        if !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(invalid());
+        }
+        let channel = |i: usize, len: usize| {
+            let value = u8::from_str_radix(&digits[i * len..(i + 1) * len], 16).unwrap();
+            // A single digit is repeated, so `#f80` is `#ff8800`
+            if len == 1 {
+                value * 17
+            } else {
+                value
+            }
+        };
+
+        match digits.len() {
+            3 => Ok(Color::from_rgba(
+                channel(0, 1),
+                channel(1, 1),
+                channel(2, 1),
+                255,
+            )),
+            4 => Ok(Color::from_rgba(
+                channel(0, 1),
+                channel(1, 1),
+                channel(2, 1),
+                channel(3, 1),
+            )),
+            6 => Ok(Color::from_rgba(
+                channel(0, 2),
+                channel(1, 2),
+                channel(2, 2),
+                255,
+            )),
+            8 => Ok(Color::from_rgba(
+                channel(0, 2),
+                channel(1, 2),
+                channel(2, 2),
+                channel(3, 2),
+            )),
+            _ => Err(invalid()),
+        }
+    }
+
+    // Blending works on the sRGB channel values directly, without converting to linear
+    // light, so it isn't gamma-correct: mixes come out a little darker than they should.
+
+    // Interpolate each channel, alpha included, from this color at `t = 0` to `other` at
+    // `t = 1`. `t` is clamped to that range.
+    pub fn mix(&self, other: &Color, t: f32) -> Color {
+        let t = t.clamp(0.0, 1.0);
+        let mix = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * t).round() as u8;
+        Color {
+            r: mix(self.r, other.r),
+            g: mix(self.g, other.g),
+            b: mix(self.b, other.b),
+            a: mix(self.a, other.a),
+        }
+    }
+
+    // Move the color toward white by `amount`, from 0 (unchanged) to 1 (white). Alpha is
+    // kept.
+    pub fn lighten(&self, amount: f32) -> Color {
+        self.mix(&Color::from_rgba(255, 255, 255, self.a), amount)
+    }
+
+    // Move the color toward black by `amount`, from 0 (unchanged) to 1 (black). Alpha is
+    // kept.
+    pub fn darken(&self, amount: f32) -> Color {
+        self.mix(&Color::from_rgba(0, 0, 0, self.a), amount)
+    }
+
+    pub fn with_alpha(&self, a: u8) -> Color {
+        Color { a, ..*self }
+    }
+
+    // Format as `#rrggbb`, or `#rrggbbaa` if the color isn't opaque
+    pub fn to_hex(&self) -> String {
+        if self.a == 255 {
+            format!("#{:02x}{:02x}{:02x}", self.r, self.g, self.b)
+        } else {
+            format!("#{:02x}{:02x}{:02x}{:02x}", self.r, self.g, self.b, self.a)
+        }
+    }
+}
+
+// Default styles for HTML elements, applied beneath the author's stylesheets
+pub const USER_AGENT_CSS: &str = include_str!("user_agent.css");
+
+// Parse the built-in user agent stylesheet
+pub fn user_agent_stylesheet() -> StyleSheet {
+    let mut stylesheet = parse(USER_AGENT_CSS.to_string()).expect("invalid user agent stylesheet");
+    for rule in &mut stylesheet.rules {
+        rule.origin = Origin::UserAgent;
+    }
+    stylesheet
+}
+
+// A syntax error in a stylesheet, with the 1-based line and column where it was found
+#[derive(Debug, Clone, PartialEq)]
+pub struct CssParseError {
+    pub message: String,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl fmt::Display for CssParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl Error for CssParseError {}
+
+type ParseResult<T> = Result<T, CssParseError>;
+
+// Parse the declarations of a `style` attribute, which aren't enclosed in braces
+pub fn parse_inline_style(source: String) -> ParseResult<Vec<Declaration>> {
+    let mut parser = Parser::new(source);
+
+    let mut declarations = Vec::new();
+    loop {
+        parser.consume_whitespace();
+        if parser.eof() {
+            break;
+        }
+        declarations.push(parser.parse_declaration()?);
+    }
+
+    expand_shorthands(&mut declarations);
+    Ok(declarations)
+}
+
+// Parse a single selector, like `h1 + p.intro`
+pub fn parse_selector(source: &str) -> ParseResult<Selector> {
+    let mut parser = Parser::new(source.to_string());
+    parser.consume_whitespace();
+    let selector = parser.parse_selector()?;
+    if !parser.eof() {
+        let message = format!("Unexpected character {:?} in selector", parser.next_char());
+        return Err(parser.error(message));
+    }
+    Ok(selector)
+}
+
 // Parse a whole CSS stylesheet
-pub fn parse(source: String) -> StyleSheet {
-    let mut parser = Parser {
-        pos: 0,
-        input: source,
-    };
+pub fn parse(source: String) -> ParseResult<StyleSheet> {
+    let mut parser = Parser::new(source);
+    let rules = parser.parse_rules()?;
+    for url in parser.imports {
+        log::note(&format!(
+            "skipping @import of {}: nothing can fetch it",
+            url
+        ));
+    }
+
+    Ok(StyleSheet { rules })
+}
+
+// Parse a whole stylesheet, never failing: a declaration that doesn't parse is dropped, and
+// so is a rule whose selectors or block don't, as in browsers. This suits arbitrary input,
+// like a fuzzer's. What was skipped is noted with `--verbose`.
+pub fn parse_lossy(source: String) -> StyleSheet {
+    let mut parser = Parser::new(source);
+    parser.lossy = true;
+
+    let mut rules = Vec::new();
+    loop {
+        parser.consume_whitespace();
+        if parser.eof() {
+            break;
+        }
+        let start = parser.pos;
+        let result = if parser.next_char() == '@' {
+            parser.parse_at_rule(&mut rules)
+        } else {
+            parser.parse_rule().map(|rule| rules.push(rule))
+        };
+        if let Err(err) = result {
+            parser.note_at(start, &format!("skipping invalid rule: {}", err.message));
+            parser.pos = start;
+            parser.skip_invalid_rule();
+        }
+    }
+    for url in parser.imports {
+        log::note(&format!(
+            "skipping @import of {}: nothing can fetch it",
+            url
+        ));
+    }
+
+    StyleSheet { rules }
+}
+
+// Parse a whole stylesheet like `parse`, fetching the stylesheets it `@import`s with
+// `resolve`, which returns the source of a URL or None if it can't be fetched. Imported rules
+// come before the rules of the stylesheet importing them, so those override them, and
+// imports of imported stylesheets are fetched the same way. `url` is where the stylesheet
+// itself came from, if it's a file rather than a `<style>` block. An import of a stylesheet
+// that is already being imported would never end, so it is skipped, like imports that can't
+// be fetched or parsed.
+pub fn parse_with_imports<F>(
+    source: String,
+    url: Option<&str>,
+    resolve: &F,
+) -> ParseResult<StyleSheet>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    parse_importing(
+        source,
+        resolve,
+        &mut url.map(String::from).into_iter().collect(),
+    )
+}
 
-    StyleSheet {
-        rules: parser.parse_rules(),
+// Parse a stylesheet imported through the URLs in `importing`, outermost first. URLs in an
+// imported stylesheet are relative to it, so they are joined to its URL before they are
+// resolved.
+fn parse_importing<F>(
+    source: String,
+    resolve: &F,
+    importing: &mut Vec<String>,
+) -> ParseResult<StyleSheet>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let mut parser = Parser::new(source);
+    let own_rules = parser.parse_rules()?;
+
+    let mut rules = Vec::new();
+    for url in parser.imports {
+        let url = match importing.last() {
+            Some(base) => join_url(base, &url),
+            None => url,
+        };
+        if importing.contains(&url) {
+            log::warn(&format!("skipping circular @import of {}", url));
+            continue;
+        }
+        let source = match resolve(&url) {
+            Some(source) => source,
+            None => {
+                log::warn(&format!("can't load imported stylesheet {}", url));
+                continue;
+            }
+        };
+        importing.push(url);
+        match parse_importing(source, resolve, importing) {
+            Ok(imported) => rules.extend(imported.rules),
+            Err(err) => log::warn(&format!(
+                "can't parse imported stylesheet {}:{}",
+                importing.last().unwrap(),
+                err
+            )),
+        }
+        importing.pop();
+    }
+    rules.extend(own_rules);
+
+    Ok(StyleSheet { rules })
+}
+
+// Resolve a relative URL against the URL of the stylesheet it appears in, so that
+// `../b.css` imported from `sub/a.css` is `b.css`. URLs with a scheme or starting with `/`
+// are used as they are.
+fn join_url(base: &str, url: &str) -> String {
+    if url.starts_with('/') || url.contains("://") {
+        return url.to_string();
+    }
+    let mut segments: Vec<&str> = base.split('/').collect();
+    segments.pop();
+    for segment in url.split('/') {
+        match segment {
+            "." => {}
+            ".." if matches!(segments.last(), Some(&last) if last != "..") => {
+                segments.pop();
+            }
+            _ => segments.push(segment),
+        }
     }
+    segments.join("/")
+}
+
+// The properties the engine uses, including shorthands. Declarations of other properties
+// are kept, but have no effect.
+const KNOWN_PROPERTIES: &[&str] = &[
+    "aspect-ratio",
+    "background",
+    "background-color",
+    "background-image",
+    "border",
+    "border-bottom-width",
+    "border-color",
+    "border-left-width",
+    "border-radius",
+    "border-right-width",
+    "border-style",
+    "border-top-width",
+    "border-width",
+    "bottom",
+    "box-shadow",
+    "clear",
+    "color",
+    "column-gap",
+    "content",
+    "direction",
+    "display",
+    "empty-cells",
+    "flex-direction",
+    "flex-grow",
+    "flex-wrap",
+    "float",
+    "font",
+    "font-family",
+    "font-size",
+    "font-style",
+    "gap",
+    "height",
+    "justify-content",
+    "left",
+    "letter-spacing",
+    "line-height",
+    "list-style-type",
+    "margin",
+    "margin-bottom",
+    "margin-left",
+    "margin-right",
+    "margin-top",
+    "max-height",
+    "max-width",
+    "min-height",
+    "min-width",
+    "object-fit",
+    "opacity",
+    "outline",
+    "outline-color",
+    "outline-style",
+    "outline-width",
+    "overflow",
+    "overflow-wrap",
+    "padding",
+    "padding-bottom",
+    "padding-left",
+    "padding-right",
+    "padding-top",
+    "position",
+    "right",
+    "row-gap",
+    "tab-size",
+    "text-transform",
+    "top",
+    "transform",
+    "transform-origin",
+    "vertical-align",
+    "visibility",
+    "white-space",
+    "width",
+    "word-spacing",
+    "writing-mode",
+    "z-index",
+];
+
+// Properties documents often set that the engine recognizes but doesn't implement. They
+// have no effect, and aren't noted as unknown. Their values are kept as source text when
+// they don't parse, like `transition: opacity 0.3s`, as long as their brackets balance.
+const UNIMPLEMENTED_PROPERTIES: &[&str] = &[
+    "animation",
+    "box-sizing",
+    "cursor",
+    "font-weight",
+    "list-style",
+    "outline-offset",
+    "pointer-events",
+    "text-align",
+    "text-decoration",
+    "text-shadow",
+    "transition",
+    "transition-delay",
+    "transition-duration",
+    "transition-property",
+    "transition-timing-function",
+    "user-select",
+];
+
+fn is_known_property(name: &str) -> bool {
+    is_custom_property(name)
+        || is_unimplemented_property(name)
+        || KNOWN_PROPERTIES
+            .iter()
+            .any(|known| known.eq_ignore_ascii_case(name))
+}
+
+// Implemented properties that only some forms of value are understood for. Like those of
+// unimplemented properties, values that don't parse are kept as source text, and have no
+// effect.
+const PARTIAL_PROPERTIES: &[&str] = &["box-shadow", "transform"];
+
+// May a value that doesn't parse be kept as source text?
+fn keeps_unparsed_values(name: &str) -> bool {
+    UNIMPLEMENTED_PROPERTIES
+        .iter()
+        .chain(PARTIAL_PROPERTIES)
+        .any(|known| known.eq_ignore_ascii_case(name))
+}
+
+fn is_unimplemented_property(name: &str) -> bool {
+    UNIMPLEMENTED_PROPERTIES
+        .iter()
+        .any(|known| known.eq_ignore_ascii_case(name))
+}
+
+// Custom properties, like `--brand: #f00`, can be given any value and are read with `var()`
+pub fn is_custom_property(name: &str) -> bool {
+    name.starts_with("--")
 }
 
+// How deeply functions, brackets and `:not()` selectors may be nested. Parsing them recurses,
+// so a limit keeps hostile stylesheets from overflowing the stack.
+const MAX_NESTING: usize = 32;
+
 pub struct Parser {
     pub pos: usize,
     pub input: String,
+    // The URLs of the `@import` rules read so far, in order
+    pub imports: Vec<String>,
+    // How many functions, brackets and `:not()`s the parser is inside
+    depth: usize,
+    // Whether invalid declarations are skipped rather than failing the whole parse
+    lossy: bool,
 }
 
 impl Parser {
+    // Create a parser positioned at the start of `input`. Besides `parse` for whole
+    // stylesheets, a parser can be used to read smaller pieces of CSS, like a selector list
+    // or a declaration block. Each `parse_*` method starts at `pos` and leaves it just after
+    // the input it consumed, so calls can be chained over the same input.
+    pub fn new(input: String) -> Parser {
+        Parser {
+            pos: 0,
+            input,
+            imports: Vec::new(),
+            depth: 0,
+            lossy: false,
+        }
+    }
+
     // Read the current character without consuming it
     pub fn next_char(&self) -> char {
         self.input[self.pos..].chars().next().unwrap()
@@ -90,25 +673,27 @@ impl Parser {
 
     // Return the current character and advance self.pos to the next character
     pub fn consume_char(&mut self) -> char {
-        let mut iter = self.input[self.pos..].char_indices();
-        let (_, cur_char) = iter.next().unwrap();
-        let (next_pos, _) = iter.next().unwrap_or((1, ' '));
-        self.pos += next_pos;
+        let cur_char = self.next_char();
+        self.pos += cur_char.len_utf8();
 
-        return cur_char;
+        cur_char
     }
 
-    /// Consume characters until `test` returns false.
-    pub fn consume_while<F>(&mut self, test: F) -> String
+    /// Consume characters until `test` returns false, returning them as a slice of the input
+    /// so that nothing is allocated for characters that are skipped.
+    pub fn consume_while<F>(&mut self, test: F) -> &str
     where
         F: Fn(char) -> bool,
     {
-        let mut result = String::new();
-        while !self.eof() && test(self.next_char()) {
-            result.push(self.consume_char());
-        }
+        let start = self.pos;
+        let rest = &self.input[start..];
+        let len = rest
+            .char_indices()
+            .find(|&(_, c)| !test(c))
+            .map_or(rest.len(), |(i, _)| i);
+        self.pos += len;
 
-        return result;
+        &self.input[start..self.pos]
     }
 
     // Consume and discard zero or more whitespace characters
@@ -116,187 +701,1207 @@ impl Parser {
         self.consume_while(char::is_whitespace);
     }
 
-    /// Parse two hexadecimal digits
-    fn parse_hex_pair(&mut self) -> u8 {
-        let s = &self.input[self.pos..self.pos + 2];
-        self.pos += 2;
-        u8::from_str_radix(s, 16).unwrap()
+    // The 1-based line and column of the current position
+    pub fn line_col(&self) -> (usize, usize) {
+        self.line_col_at(self.pos)
+    }
+
+    fn line_col_at(&self, pos: usize) -> (usize, usize) {
+        let consumed = &self.input[..pos.min(self.input.len())];
+        let line_start = consumed.rfind('\n').map_or(0, |i| i + 1);
+        let line = consumed.matches('\n').count() + 1;
+        let column = consumed[line_start..].chars().count() + 1;
+        (line, column)
+    }
+
+    // Report a note about the input at `pos`. Finding its line and column means scanning
+    // the input up to it, so that is only done when notes are shown.
+    fn note_at(&self, pos: usize, message: &str) {
+        if log::verbosity() == log::Verbosity::Verbose {
+            let (line, column) = self.line_col_at(pos);
+            log::note(&format!("{}:{}: {}", line, column, message));
+        }
+    }
+
+    // Run `parse` on something nested inside a function or brackets, failing if they are
+    // nested too deeply
+    fn nested<T, F>(&mut self, parse: F) -> ParseResult<T>
+    where
+        F: FnOnce(&mut Parser) -> ParseResult<T>,
+    {
+        if self.depth >= MAX_NESTING {
+            return Err(self.error(format!(
+                "Brackets are nested more than {} deep",
+                MAX_NESTING
+            )));
+        }
+        self.depth += 1;
+        let result = parse(self);
+        self.depth -= 1;
+        result
+    }
+
+    // An error at the current position
+    fn error(&self, message: String) -> CssParseError {
+        let (line, column) = self.line_col();
+        CssParseError {
+            message,
+            line,
+            column,
+        }
+    }
+
+    // Consume the character `c`, or fail if the input doesn't continue with it
+    fn expect_char(&mut self, c: char) -> ParseResult<()> {
+        if self.eof() {
+            return Err(self.error(format!("Expected {:?} but found the end of input", c)));
+        }
+        if self.next_char() != c {
+            return Err(self.error(format!("Expected {:?} but found {:?}", c, self.next_char())));
+        }
+        self.consume_char();
+        Ok(())
     }
 
     // Parse a property name or keyword
     fn parse_identifier(&mut self) -> String {
-        self.consume_while(valid_identifier_char)
+        self.consume_while(valid_identifier_char).to_string()
     }
 
-    pub fn parse_simple_selector(&mut self) -> SimpleSelector {
+    pub fn parse_simple_selector(&mut self) -> ParseResult<SimpleSelector> {
         let mut selector = SimpleSelector {
             tag_name: None,
             id: None,
             class: Vec::new(),
+            pseudo: None,
+            pseudo_element: None,
         };
 
         while !self.eof() {
             match self.next_char() {
                 '#' => {
                     self.consume_char();
-                    selector.id = Some(self.parse_identifier());
+                    selector.id = Some(self.parse_selector_name("ID")?);
                 }
                 '.' => {
                     self.consume_char();
-                    selector.class.push(self.parse_identifier());
+                    selector.class.push(self.parse_selector_name("class")?);
                 }
                 '*' => {
                     // Universal selector
                     self.consume_char();
                 }
-                c if valid_identifier_char(c) => {
-                    selector.tag_name = Some(self.parse_identifier());
+                ':' if self.start_with("::") => {
+                    self.consume_char();
+                    self.consume_char();
+                    selector.pseudo_element = Some(self.parse_pseudo_element()?);
+
+                    // A pseudo-element ends the selector
+                    break;
+                }
+                ':' => {
+                    self.consume_char();
+                    selector.pseudo = Some(self.parse_pseudo_class()?);
+                }
+                _ if self.starts_identifier() => {
+                    // Type selectors match HTML elements whatever their case, like tag names
+                    selector.tag_name = Some(self.parse_identifier().to_ascii_lowercase());
                 }
                 _ => break,
             }
         }
 
-        return selector;
+        Ok(selector)
     }
 
-    // Parse a rule set: `<selectors> { <declarations >}`
-    pub fn parse_rule(&mut self) -> Rule {
-        Rule {
-            selectors: self.parse_selectors(),
-            declarations: self.parse_declarations(),
+    // Does the remaining input start with a valid CSS identifier? Identifiers can't start
+    // with a digit, or with a hyphen followed by a digit.
+    // https://www.w3.org/TR/css-syntax-3/#would-start-an-identifier
+    fn starts_identifier(&self) -> bool {
+        let mut chars = self.input[self.pos..].chars();
+        match chars.next() {
+            Some('-') => matches!(chars.next(), Some(c) if valid_identifier_start(c) || c == '-'),
+            Some(c) => valid_identifier_start(c),
+            None => false,
         }
     }
 
-    // Parse a list of rules sets, separated by optional whitespace
-    fn parse_rules(&mut self) -> Vec<Rule> {
-        let mut rules = Vec::new();
-        loop {
-            self.consume_whitespace();
-            if self.eof() {
-                break;
-            }
-            rules.push(self.parse_rule());
+    // Parse the identifier of an ID or class selector, rejecting names like `#1bad` that
+    // aren't identifiers
+    fn parse_selector_name(&mut self, kind: &str) -> ParseResult<String> {
+        if !self.starts_identifier() {
+            let error_pos = self.pos;
+            let rest = self.consume_while(valid_identifier_char).to_string();
+            self.pos = error_pos;
+            return Err(self.error(format!("Invalid {} selector name {:?}", kind, rest)));
         }
-
-        rules
+        Ok(self.parse_identifier())
     }
 
-    // Parse a comma-separated list of selectors.
-    pub fn parse_selectors(&mut self) -> Vec<Selector> {
-        let mut selectors = Vec::new();
-
-        loop {
-            selectors.push(Selector::Simple(self.parse_simple_selector()));
-            self.consume_whitespace();
-            match self.next_char() {
-                ',' => {
-                    self.consume_char();
-                    self.consume_whitespace();
+    // Parse the name of a pseudo-class, after its leading `:`
+    fn parse_pseudo_class(&mut self) -> ParseResult<PseudoClass> {
+        let name_pos = self.pos;
+        match &*self.parse_identifier().to_ascii_lowercase() {
+            "first-child" => Ok(PseudoClass::FirstChild),
+            "last-child" => Ok(PseudoClass::LastChild),
+            "only-child" => Ok(PseudoClass::OnlyChild),
+            "nth-child" => {
+                self.expect_char('(')?;
+                let argument_pos = self.pos;
+                let argument = self.consume_while(|c| c != ')').to_string();
+                self.expect_char(')')?;
+                match parse_an_plus_b(&argument) {
+                    Some((a, b)) => Ok(PseudoClass::NthChild(a, b)),
+                    None => {
+                        self.pos = argument_pos;
+                        Err(self.error(format!("Invalid :nth-child() argument {}", argument)))
+                    }
                 }
-                '{' => break, // start declarations
-                c => panic!("Unexpected character {} in selector list", c),
+            }
+            "not" => {
+                self.expect_char('(')?;
+                self.consume_whitespace();
+                let argument_pos = self.pos;
+                let argument = self.nested(Parser::parse_simple_selector)?;
+                if let Some(PseudoClass::Not(_)) = argument.pseudo {
+                    self.pos = argument_pos;
+                    return Err(self.error("Nested :not() selectors are not supported".into()));
+                }
+                self.consume_whitespace();
+                self.expect_char(')')?;
+                Ok(PseudoClass::Not(Box::new(argument)))
+            }
+            name => {
+                let message = format!("Unsupported pseudo-class :{}", name);
+                self.pos = name_pos;
+                Err(self.error(message))
             }
         }
+    }
 
-        // Return selectors with highest specificity first, for use in matching .
-        selectors.sort_by(|a, b| b.specificity().cmp(&a.specificity()));
-        return selectors;
+    // Parse the name of a pseudo-element, after its leading `::`
+    fn parse_pseudo_element(&mut self) -> ParseResult<PseudoElement> {
+        let name_pos = self.pos;
+        match &*self.parse_identifier().to_ascii_lowercase() {
+            "before" => Ok(PseudoElement::Before),
+            "after" => Ok(PseudoElement::After),
+            name => {
+                let message = format!("Unsupported pseudo-element ::{}", name);
+                self.pos = name_pos;
+                Err(self.error(message))
+            }
+        }
     }
 
-    /// parse a list of declarations enclosed in `{ ... }`
-    fn parse_declarations(&mut self) -> Vec<Declaration> {
-        assert_eq!(self.consume_char(), '{');
-        let mut declarations = Vec::new();
+    // Parse a rule set: `<selectors> { <declarations >}`
+    pub fn parse_rule(&mut self) -> ParseResult<Rule> {
+        Ok(Rule {
+            selectors: self.parse_selectors()?,
+            declarations: self.parse_declarations()?,
+            media: Vec::new(),
+            origin: Origin::Author,
+        })
+    }
 
+    // Parse a list of rules sets and at-rules, separated by optional whitespace
+    fn parse_rules(&mut self) -> ParseResult<Vec<Rule>> {
+        let mut rules = Vec::new();
         loop {
             self.consume_whitespace();
-            if self.next_char() == '}' {
-                self.consume_char();
+            if self.eof() {
                 break;
             }
-            declarations.push(self.parse_declaration());
+            if self.next_char() == '@' {
+                self.parse_at_rule(&mut rules)?;
+            } else {
+                rules.push(self.parse_rule()?);
+            }
         }
 
-        declarations
+        Ok(rules)
     }
 
-    // Parse one `<property>: <value>;` declaration.
-    fn parse_declaration(&mut self) -> Declaration {
-        let property_name = self.parse_identifier();
-        self.consume_whitespace();
-        assert_eq!(self.consume_char(), ':');
+    // Parse an at-rule, adding the rules it contains to `rules`. The URLs of `@import` rules
+    // are collected in `imports`. Other at-rules than those and `@media` are skipped.
+    fn parse_at_rule(&mut self, rules: &mut Vec<Rule>) -> ParseResult<()> {
+        let start = self.pos;
+        self.expect_char('@')?;
+        match &*self.parse_identifier().to_ascii_lowercase() {
+            // Imports must come before any rules
+            "import" if rules.is_empty() => self.parse_import(start),
+            "media" => {
+                let media = self.parse_media_query_list()?;
+                self.expect_char('{')?;
+                loop {
+                    self.consume_whitespace();
+                    if self.eof() {
+                        return Err(self.error("Unterminated @media block".into()));
+                    }
+                    match self.next_char() {
+                        '}' => {
+                            self.consume_char();
+                            break;
+                        }
+                        // Nested at-rules aren't supported
+                        '@' => {
+                            let start = self.pos;
+                            self.consume_char();
+                            let name = self.parse_identifier();
+                            self.skip_at_rule(&name, start)?;
+                        }
+                        _ => {
+                            let mut rule = self.parse_rule()?;
+                            rule.media = media.clone();
+                            rules.push(rule);
+                        }
+                    }
+                }
+                Ok(())
+            }
+            name => self.skip_at_rule(name, start),
+        }
+    }
+
+    // Parse the rest of an `@import` rule: a quoted string or a `url()`, then a `;`. Imports
+    // limited to some media aren't supported and are skipped.
+    fn parse_import(&mut self, start: usize) -> ParseResult<()> {
         self.consume_whitespace();
-        let value = self.parse_value();
+        let url = if self.eof() {
+            return Err(self.error("Expected a URL after @import".into()));
+        } else if matches!(self.next_char(), '"' | '\'') {
+            self.parse_string()?
+        } else if self.parse_identifier().eq_ignore_ascii_case("url") {
+            self.parse_url()?
+        } else {
+            return Err(self.error("Expected a URL after @import".into()));
+        };
         self.consume_whitespace();
-        assert_eq!(self.consume_char(), ';');
-
-        Declaration {
-            name: property_name,
-            value,
+        if !self.eof() && self.next_char() != ';' {
+            return self.skip_at_rule("import", start);
         }
+        if !self.eof() {
+            self.consume_char();
+        }
+        self.imports.push(url);
+        Ok(())
     }
 
-    // Methods for parsing values
-    fn parse_value(&mut self) -> Value {
-        match self.next_char() {
-            '0'..='9' => self.parse_length(),
-            '#' => self.parse_color(),
-            _ => Value::Keyword(self.parse_identifier()),
+    // Skip the rest of an unsupported at-rule: its prelude, then either a balanced `{ ... }`
+    // block or the `;` ending a statement like `@import`. `start` is the position of the `@`.
+    fn skip_at_rule(&mut self, name: &str, start: usize) -> ParseResult<()> {
+        self.note_at(start, &format!("skipping unsupported at-rule @{}", name));
+        let mut depth = 0;
+        loop {
+            if self.eof() {
+                // A statement at-rule may end the stylesheet without its `;`
+                if depth == 0 {
+                    return Ok(());
+                }
+                return Err(self.error("Unterminated at-rule block".into()));
+            }
+            match self.next_char() {
+                '"' | '\'' => {
+                    self.parse_string()?;
+                }
+                '{' => {
+                    self.consume_char();
+                    depth += 1;
+                }
+                '}' if depth > 0 => {
+                    self.consume_char();
+                    depth -= 1;
+                    if depth == 0 {
+                        return Ok(());
+                    }
+                }
+                // A `}` outside the block closes an enclosing `@media` block
+                '}' => return Ok(()),
+                ';' if depth == 0 => {
+                    self.consume_char();
+                    return Ok(());
+                }
+                _ => {
+                    self.consume_char();
+                }
+            }
         }
     }
 
-    fn parse_length(&mut self) -> Value {
-        Value::Length(self.parse_float(), self.parse_unit())
+    // Skip the rest of a declaration that doesn't parse, up to the `;` ending it or the `}`
+    // ending its block. Brackets and strings in the value are skipped whole.
+    fn skip_invalid_declaration(&mut self) {
+        let mut depth: usize = 0;
+        while !self.eof() {
+            match self.next_char() {
+                '}' if depth == 0 => return,
+                ';' if depth == 0 => {
+                    self.consume_char();
+                    return;
+                }
+                quote @ ('"' | '\'') => {
+                    self.skip_string(quote);
+                    continue;
+                }
+                '(' | '[' | '{' => depth += 1,
+                ')' | ']' | '}' => depth = depth.saturating_sub(1),
+                _ => {}
+            }
+            self.consume_char();
+        }
     }
 
-    fn parse_float(&mut self) -> f32 {
-        let s = self.consume_while(|c| match c {
-            '0'..='9' | '.' => true,
-            _ => false,
-        });
+    // Skip the rest of a rule that doesn't parse: up to the end of its `{ ... }` block, or
+    // the `;` of a statement before any block. A stray `}` is skipped on its own.
+    fn skip_invalid_rule(&mut self) {
+        let mut depth = 0;
+        while !self.eof() {
+            match self.next_char() {
+                quote @ ('"' | '\'') => {
+                    self.skip_string(quote);
+                    continue;
+                }
+                '{' => depth += 1,
+                '}' if depth <= 1 => {
+                    self.consume_char();
+                    return;
+                }
+                '}' => depth -= 1,
+                ';' if depth == 0 => {
+                    self.consume_char();
+                    return;
+                }
+                _ => {}
+            }
+            self.consume_char();
+        }
+    }
 
-        s.parse().unwrap()
+    // Skip a string that may be unterminated, starting at its opening quote
+    fn skip_string(&mut self, quote: char) {
+        self.consume_char();
+        while !self.eof() {
+            match self.consume_char() {
+                c if c == quote => return,
+                '\\' if !self.eof() => {
+                    self.consume_char();
+                }
+                _ => {}
+            }
+        }
     }
 
-    pub fn parse_unit(&mut self) -> Unit {
-        match &*self.parse_identifier().to_ascii_lowercase() {
-            "px" => Unit::Px,
-            _ => panic!("unrecognized unit"),
+    // Parse a comma-separated list of media queries, stopping before the `{` of the block
+    fn parse_media_query_list(&mut self) -> ParseResult<Vec<MediaQuery>> {
+        let mut queries = Vec::new();
+        loop {
+            queries.push(self.parse_media_query()?);
+            if self.next_char() != ',' {
+                break;
+            }
+            self.consume_char();
         }
+        Ok(queries)
     }
 
-    pub fn parse_color(&mut self) -> Value {
-        assert_eq!(self.consume_char(), '#');
+    // Parse a media query like `screen and (max-width: 600px)`
+    fn parse_media_query(&mut self) -> ParseResult<MediaQuery> {
+        let mut query = MediaQuery {
+            media_type: None,
+            features: Vec::new(),
+        };
 
-        Value::ColorValue(Color {
-            r: self.parse_hex_pair(),
-            g: self.parse_hex_pair(),
-            b: self.parse_hex_pair(),
-            a: 255,
-        })
-    }
-}
+        loop {
+            self.consume_whitespace();
+            if self.eof() {
+                return Err(self.error("Unterminated media query".into()));
+            }
+            match self.next_char() {
+                '(' => query.features.push(self.parse_media_feature()?),
+                _ if self.starts_identifier() => {
+                    let word_pos = self.pos;
+                    match &*self.parse_identifier().to_ascii_lowercase() {
+                        "and" | "only" => {}
+                        "not" => {
+                            self.pos = word_pos;
+                            return Err(
+                                self.error("Negated media queries are not supported".into())
+                            );
+                        }
+                        media_type => query.media_type = Some(media_type.to_string()),
+                    }
+                }
+                _ => break,
+            }
+        }
 
-pub type Specificity = (usize, usize, usize);
+        Ok(query)
+    }
+
+    // Parse a `(<name>: <length>)` media feature
+    fn parse_media_feature(&mut self) -> ParseResult<MediaFeature> {
+        self.expect_char('(')?;
+        self.consume_whitespace();
+        let name_pos = self.pos;
+        let name = self.parse_identifier().to_ascii_lowercase();
+        self.consume_whitespace();
+        self.expect_char(':')?;
+        self.consume_whitespace();
+        let value_pos = self.pos;
+        let width = match self.parse_value()? {
+            Value::Length(width, Unit::Px) => width,
+            Value::Number(0.0) => 0.0,
+            _ => {
+                self.pos = value_pos;
+                return Err(self.error("Media features must be px lengths".into()));
+            }
+        };
+        self.consume_whitespace();
+        self.expect_char(')')?;
+
+        match &*name {
+            "min-width" => Ok(MediaFeature::MinWidth(width)),
+            "max-width" => Ok(MediaFeature::MaxWidth(width)),
+            _ => {
+                self.pos = name_pos;
+                Err(self.error(format!("Unsupported media feature {}", name)))
+            }
+        }
+    }
+
+    // Parse a comma-separated list of selectors. Parsing stops at the `{` that starts the
+    // declarations, which is not consumed, or at the end of the input.
+    pub fn parse_selectors(&mut self) -> ParseResult<Vec<Selector>> {
+        let mut selectors = Vec::new();
+
+        loop {
+            selectors.push(self.parse_selector()?);
+            if self.eof() {
+                break;
+            }
+            match self.next_char() {
+                ',' => {
+                    self.consume_char();
+                    self.consume_whitespace();
+                }
+                '{' => break, // start declarations
+                c => {
+                    return Err(self.error(format!("Unexpected character {:?} in selector list", c)))
+                }
+            }
+        }
+
+        // Return selectors with highest specificity first, for use in matching .
+        selectors.sort_by_key(|s| std::cmp::Reverse(s.specificity()));
+        Ok(selectors)
+    }
+
+    // Parse a chain of simple selectors joined by combinators, like `h1 + p`, and the
+    // whitespace after it
+    fn parse_selector(&mut self) -> ParseResult<Selector> {
+        let mut selector = Selector::Simple(self.parse_simple_selector()?);
+        self.consume_whitespace();
+
+        while !self.eof() {
+            let combinator = match self.next_char() {
+                '+' => Combinator::NextSibling,
+                '~' => Combinator::SubsequentSibling,
+                _ => break,
+            };
+            if selector.pseudo_element().is_some() {
+                return Err(self.error("A pseudo-element must end its selector".into()));
+            }
+            self.consume_char();
+            self.consume_whitespace();
+            let simple = self.parse_simple_selector()?;
+            selector = Selector::Combined(Box::new(selector), combinator, simple);
+            self.consume_whitespace();
+        }
+
+        Ok(selector)
+    }
+
+    /// parse a list of declarations enclosed in `{ ... }`, leaving `pos` after the `}`
+    pub fn parse_declarations(&mut self) -> ParseResult<Vec<Declaration>> {
+        self.expect_char('{')?;
+        let mut declarations = Vec::new();
+
+        loop {
+            self.consume_whitespace();
+            if self.eof() {
+                if self.lossy {
+                    break;
+                }
+                return Err(self.error("Unterminated declaration block".into()));
+            }
+            if self.next_char() == '}' {
+                self.consume_char();
+                break;
+            }
+            let start = self.pos;
+            match self.parse_declaration() {
+                Ok(declaration) => declarations.push(declaration),
+                Err(err) if self.lossy => {
+                    self.note_at(
+                        start,
+                        &format!("skipping invalid declaration: {}", err.message),
+                    );
+                    self.pos = start;
+                    self.skip_invalid_declaration();
+                }
+                Err(err) => return Err(err),
+            }
+        }
+
+        expand_shorthands(&mut declarations);
+        Ok(declarations)
+    }
+
+    // Parse a comma-separated list of font families. A family is either quoted, or a run of
+    // identifiers that are joined by single spaces, like `Times New Roman`.
+    fn parse_font_families(&mut self) -> ParseResult<Value> {
+        let mut families = Vec::new();
+        loop {
+            self.consume_whitespace();
+            if !self.eof() && matches!(self.next_char(), '"' | '\'') {
+                families.push(self.parse_string()?);
+            } else {
+                let mut words = Vec::new();
+                while self.starts_identifier() {
+                    words.push(self.parse_identifier());
+                    self.consume_whitespace();
+                }
+                if words.is_empty() {
+                    return Err(self.error("Expected a font family name".into()));
+                }
+                families.push(words.join(" "));
+            }
+            self.consume_whitespace();
+            if self.eof() || self.next_char() != ',' {
+                break;
+            }
+            self.consume_char();
+        }
+        Ok(Value::FontFamilies(families))
+    }
+
+    // Parse the value of the `font` shorthand, like `italic bold 16px/1.5 Georgia, serif`:
+    // the values up to and including the size, the line height after a `/`, and the
+    // families, in a list that `expand_shorthands` splits up
+    fn parse_font(&mut self) -> ParseResult<Value> {
+        let mut components = Vec::new();
+        loop {
+            if self.at_declaration_end() {
+                return Err(self.error("Expected a font size".into()));
+            }
+            let component_pos = self.pos;
+            let value = self.parse_value()?;
+            if self.pos == component_pos {
+                return Err(self.error(format!("Unexpected {:?} in value", self.next_char())));
+            }
+            let is_size = matches!(value, Value::Length(..) | Value::Calc(_));
+            components.push(value);
+            self.consume_whitespace();
+            if is_size {
+                break;
+            }
+        }
+        if !self.eof() && self.next_char() == '/' {
+            self.consume_char();
+            self.consume_whitespace();
+            components.push(self.parse_value()?);
+        }
+        components.push(self.parse_font_families()?);
+        Ok(Value::List(components))
+    }
+
+    // Parse the parenthesized part of `url(...)`, after the name, returning the URL. It may
+    // be quoted with either kind of quote or bare, and whitespace around it is dropped.
+    fn parse_url(&mut self) -> ParseResult<String> {
+        self.expect_char('(')?;
+        self.consume_whitespace();
+        let url = if !self.eof() && matches!(self.next_char(), '"' | '\'') {
+            self.parse_string()?
+        } else {
+            self.consume_while(|c| c != ')' && !c.is_whitespace())
+                .to_string()
+        };
+        self.consume_whitespace();
+        self.expect_char(')')?;
+        Ok(url)
+    }
+
+    // Parse the value of `aspect-ratio`: a keyword like `auto`, or a ratio of two numbers
+    // like `16 / 9`. A single number is a ratio to 1.
+    fn parse_aspect_ratio(&mut self) -> ParseResult<Value> {
+        let width = match self.parse_value()? {
+            Value::Number(width) => width,
+            value => return Ok(value),
+        };
+        self.consume_whitespace();
+        let height = if !self.eof() && self.next_char() == '/' {
+            self.consume_char();
+            self.consume_whitespace();
+            match self.parse_value()? {
+                Value::Number(height) => height,
+                _ => return Err(self.error("Expected a number after '/'".into())),
+            }
+        } else {
+            1.0
+        };
+        Ok(Value::Ratio(width, height))
+    }
+
+    // Is the value of a declaration over?
+    fn at_declaration_end(&self) -> bool {
+        self.eof() || matches!(self.next_char(), ';' | '}' | '!')
+    }
+
+    // Parse one `<property>: <value> [!important];` declaration. The semicolon may be left
+    // out after the last declaration of a block.
+    fn parse_declaration(&mut self) -> ParseResult<Declaration> {
+        let start = self.pos;
+        let property_name = self.parse_identifier();
+        if !is_known_property(&property_name) {
+            self.note_at(start, &format!("unknown property `{}`", property_name));
+        }
+        self.consume_whitespace();
+        self.expect_char(':')?;
+        self.consume_whitespace();
+        let value_start = self.pos;
+        let value = match self.parse_declaration_value(&property_name) {
+            Ok(value) => value,
+            Err(_) if keeps_unparsed_values(&property_name) => {
+                self.pos = value_start;
+                self.parse_unparsed_value()?
+            }
+            Err(err) => return Err(err),
+        };
+
+        let important = !self.eof() && self.next_char() == '!';
+        if important {
+            self.consume_char();
+            self.consume_whitespace();
+            let keyword_pos = self.pos;
+            let keyword = self.parse_identifier();
+            if !keyword.eq_ignore_ascii_case("important") {
+                self.pos = keyword_pos;
+                return Err(self.error(format!(
+                    "Expected `important` after `!`, found {:?}",
+                    keyword
+                )));
+            }
+            self.consume_whitespace();
+        }
+
+        if self.eof() || self.next_char() == '}' {
+            // Last declaration in the block
+        } else {
+            self.expect_char(';')?;
+        }
+
+        Ok(Declaration {
+            name: property_name,
+            value,
+            important,
+        })
+    }
+
+    // Parse the value of a declaration, up to its `!important` or end
+    fn parse_declaration_value(&mut self, property_name: &str) -> ParseResult<Value> {
+        let mut group_start = self.pos;
+        let mut value = if property_name.eq_ignore_ascii_case("font-family") {
+            self.parse_font_families()?
+        } else if property_name.eq_ignore_ascii_case("font") {
+            self.parse_font()?
+        } else if property_name.eq_ignore_ascii_case("aspect-ratio") {
+            self.parse_aspect_ratio()?
+        } else {
+            self.parse_value()?
+        };
+        self.consume_whitespace();
+
+        if !self.at_declaration_end() {
+            // Space-separated components, in groups separated by commas
+            let mut groups = Vec::new();
+            let mut components = vec![value];
+            while !self.at_declaration_end() {
+                let component_pos = self.pos;
+                if self.next_char() == ',' {
+                    // Each group needs a value
+                    if component_pos == group_start {
+                        return Err(self.error("Unexpected ',' in value".into()));
+                    }
+                    self.consume_char();
+                    self.consume_whitespace();
+                    if self.at_declaration_end() {
+                        return Err(self.error("Expected a value after ','".into()));
+                    }
+                    groups.push(list_value(std::mem::take(&mut components)));
+                    group_start = self.pos;
+                    continue;
+                }
+                components.push(self.parse_value()?);
+                if self.pos == component_pos {
+                    return Err(self.error(format!("Unexpected {:?} in value", self.next_char())));
+                }
+                self.consume_whitespace();
+            }
+            groups.push(list_value(components));
+            value = match groups.len() {
+                1 => groups.pop().unwrap(),
+                _ => Value::CommaList(groups),
+            };
+        }
+        Ok(value)
+    }
+
+    // Skip over a value without interpreting it, checking that its brackets and quotes are
+    // closed, and return its source text
+    fn parse_unparsed_value(&mut self) -> ParseResult<Value> {
+        let start = self.pos;
+        let mut closers = Vec::new();
+        while !(closers.is_empty() && self.at_declaration_end()) {
+            if self.eof() {
+                return Err(self.error("Unterminated value".into()));
+            }
+            match self.next_char() {
+                '"' | '\'' => {
+                    self.parse_string()?;
+                    continue;
+                }
+                '(' => closers.push(')'),
+                '[' => closers.push(']'),
+                c @ (')' | ']' | '}') => match closers.pop() {
+                    Some(closer) if closer == c => {}
+                    _ => return Err(self.error(format!("Unexpected {:?} in value", c))),
+                },
+                _ => {}
+            }
+            self.consume_char();
+        }
+        Ok(Value::Unparsed(
+            self.input[start..self.pos].trim_end().to_string(),
+        ))
+    }
+
+    // Methods for parsing values
+    fn parse_value(&mut self) -> ParseResult<Value> {
+        if self.eof() {
+            return Err(self.error("Expected a value but found the end of input".into()));
+        }
+        match self.next_char() {
+            '0'..='9' | '.' => self.parse_length(),
+            '-' if self.starts_number() => self.parse_length(),
+            '#' => self.parse_color(),
+            '"' | '\'' => self.parse_string().map(Value::String),
+            _ => {
+                let name = self.parse_identifier();
+                if !self.eof() && self.next_char() == '(' {
+                    self.nested(|parser| parser.parse_function(&name))
+                } else {
+                    Ok(Value::Keyword(name))
+                }
+            }
+        }
+    }
+
+    // Parse a quoted string. A backslash escapes the character after it.
+    fn parse_string(&mut self) -> ParseResult<String> {
+        let quote = self.consume_char();
+        let mut value = String::new();
+        loop {
+            if self.eof() {
+                return Err(self.error("Unterminated string".into()));
+            }
+            match self.consume_char() {
+                c if c == quote => break,
+                '\\' if !self.eof() => value.push(self.consume_char()),
+                c => value.push(c),
+            }
+        }
+        Ok(value)
+    }
+
+    // Parse the arguments of a functional value like `calc(...)`, after its name
+    fn parse_function(&mut self, name: &str) -> ParseResult<Value> {
+        match &*name.to_ascii_lowercase() {
+            "calc" => {
+                self.expect_char('(')?;
+                let calc = self.parse_calc_sum()?;
+                self.expect_char(')')?;
+                Ok(Value::Calc(calc))
+            }
+            "linear-gradient" => {
+                self.expect_char('(')?;
+                let mut stops = Vec::new();
+                loop {
+                    self.consume_whitespace();
+                    let stop_pos = self.pos;
+                    match self.parse_value()? {
+                        Value::ColorValue(color) => stops.push(color),
+                        _ => {
+                            self.pos = stop_pos;
+                            return Err(self.error("Gradient stops must be colors".into()));
+                        }
+                    }
+                    self.consume_whitespace();
+                    if self.eof() || self.next_char() != ',' {
+                        break;
+                    }
+                    self.consume_char();
+                }
+                if stops.len() < 2 {
+                    return Err(self.error("A gradient needs at least two color stops".into()));
+                }
+                self.expect_char(')')?;
+                Ok(Value::LinearGradient { stops })
+            }
+            "url" => Ok(Value::Url(self.parse_url()?)),
+            function @ ("translate" | "translatex" | "translatey" | "scale" | "scalex"
+            | "scaley") => Ok(Value::Transform(self.parse_transform_function(function)?)),
+            "rotate" => {
+                self.expect_char('(')?;
+                self.consume_whitespace();
+                let angle = self.parse_angle()?;
+                self.consume_whitespace();
+                self.expect_char(')')?;
+                Ok(Value::Transform(TransformFunction::Rotate(angle)))
+            }
+            "var" => {
+                self.expect_char('(')?;
+                self.consume_whitespace();
+                let name_pos = self.pos;
+                let name = self.parse_identifier();
+                if !is_custom_property(&name) {
+                    self.pos = name_pos;
+                    return Err(self.error("var() takes a custom property name".into()));
+                }
+                self.consume_whitespace();
+
+                // The fallback is everything after the comma, which may be several components
+                let mut fallback = None;
+                if !self.eof() && self.next_char() == ',' {
+                    self.consume_char();
+                    let mut components = Vec::new();
+                    loop {
+                        self.consume_whitespace();
+                        if self.eof() || self.next_char() == ')' {
+                            break;
+                        }
+                        let component_pos = self.pos;
+                        components.push(self.parse_value()?);
+                        if self.pos == component_pos {
+                            let message = format!("Unexpected {:?} in value", self.next_char());
+                            return Err(self.error(message));
+                        }
+                    }
+                    fallback = match components.len() {
+                        0 => return Err(self.error("Expected a fallback value".into())),
+                        1 => components.pop().map(Box::new),
+                        _ => Some(Box::new(Value::List(components))),
+                    };
+                }
+                self.expect_char(')')?;
+                Ok(Value::Var(name, fallback))
+            }
+            _ => Err(self.error(format!("Unsupported function {}()", name))),
+        }
+    }
+
+    // Parse the arguments of a `transform` function, after its name. Offsets are px lengths,
+    // and a missing offset or factor is 0 for `translate` and the same as the first for `scale`.
+    fn parse_transform_function(&mut self, name: &str) -> ParseResult<TransformFunction> {
+        self.expect_char('(')?;
+        let mut args = Vec::new();
+        loop {
+            self.consume_whitespace();
+            args.push(self.parse_value()?);
+            self.consume_whitespace();
+            if self.eof() || self.next_char() != ',' {
+                break;
+            }
+            self.consume_char();
+        }
+        self.expect_char(')')?;
+
+        let offset = |value: &Value| match *value {
+            Value::Length(length, Unit::Px) => Some(length),
+            Value::Number(0.0) => Some(0.0),
+            _ => None,
+        };
+        let factor = |value: &Value| match *value {
+            Value::Number(number) => Some(number),
+            _ => None,
+        };
+        let function = match (name, &args[..]) {
+            ("translate", [x]) => offset(x).map(|x| TransformFunction::Translate(x, 0.0)),
+            ("translate", [x, y]) => offset(x)
+                .zip(offset(y))
+                .map(|(x, y)| TransformFunction::Translate(x, y)),
+            ("translatex", [x]) => offset(x).map(|x| TransformFunction::Translate(x, 0.0)),
+            ("translatey", [y]) => offset(y).map(|y| TransformFunction::Translate(0.0, y)),
+            ("scale", [s]) => factor(s).map(|s| TransformFunction::Scale(s, s)),
+            ("scale", [x, y]) => factor(x)
+                .zip(factor(y))
+                .map(|(x, y)| TransformFunction::Scale(x, y)),
+            ("scalex", [x]) => factor(x).map(|x| TransformFunction::Scale(x, 1.0)),
+            ("scaley", [y]) => factor(y).map(|y| TransformFunction::Scale(1.0, y)),
+            _ => None,
+        };
+        function.ok_or_else(|| self.error(format!("Invalid arguments to {}()", name)))
+    }
+
+    // Parse an angle in `deg`, `grad`, `rad` or `turn`, returning it in radians. Only 0 may
+    // be written without a unit.
+    fn parse_angle(&mut self) -> ParseResult<f32> {
+        let start = self.pos;
+        if !self.starts_number() {
+            return Err(self.error("Expected an angle".into()));
+        }
+        let negative = self.start_with("-");
+        if negative {
+            self.consume_char();
+        }
+        let value = self.parse_float()?;
+        let value = if negative { -value } else { value };
+        let radians = match &*self.parse_identifier().to_ascii_lowercase() {
+            "" if value == 0.0 => 0.0,
+            "deg" => value.to_radians(),
+            "grad" => value * std::f32::consts::PI / 200.0,
+            "rad" => value,
+            "turn" => value * std::f32::consts::TAU,
+            _ => {
+                let message = format!("Invalid angle {:?}", &self.input[start..self.pos]);
+                self.pos = start;
+                return Err(self.error(message));
+            }
+        };
+        Ok(radians)
+    }
+
+    // Parse a sequence of `calc()` terms joined by `+` or `-`
+    fn parse_calc_sum(&mut self) -> ParseResult<Calc> {
+        self.consume_whitespace();
+        let mut sum = self.parse_calc_term()?;
+
+        loop {
+            self.consume_whitespace();
+            if self.eof() {
+                break;
+            }
+            match self.next_char() {
+                '+' => {
+                    self.consume_char();
+                    sum = Calc::Add(Box::new(sum), Box::new(self.parse_calc_term()?));
+                }
+                '-' => {
+                    self.consume_char();
+                    sum = Calc::Sub(Box::new(sum), Box::new(self.parse_calc_term()?));
+                }
+                '*' | '/' => return Err(self.error("calc() only supports + and - for now".into())),
+                _ => break,
+            }
+        }
+
+        Ok(sum)
+    }
+
+    // Parse a length or a parenthesized sum inside `calc()`
+    fn parse_calc_term(&mut self) -> ParseResult<Calc> {
+        self.consume_whitespace();
+        if self.eof() {
+            return Err(self.error("Unterminated calc()".into()));
+        }
+        match self.next_char() {
+            '(' => {
+                self.consume_char();
+                let sum = self.nested(Parser::parse_calc_sum)?;
+                self.expect_char(')')?;
+                Ok(sum)
+            }
+            '0'..='9' | '.' => Ok(Calc::Length(self.parse_float()?, self.parse_unit()?)),
+            c => Err(self.error(format!("Unexpected character {:?} in calc()", c))),
+        }
+    }
+
+    // Does the remaining input start with a number, possibly with a minus sign?
+    fn starts_number(&self) -> bool {
+        let rest = self.input[self.pos..]
+            .strip_prefix('-')
+            .unwrap_or(&self.input[self.pos..]);
+        matches!(rest.chars().next(), Some('0'..='9' | '.'))
+    }
+
+    // Parse a number, followed by a unit if it is a length
+    fn parse_length(&mut self) -> ParseResult<Value> {
+        let negative = self.start_with("-");
+        if negative {
+            self.consume_char();
+        }
+        let value = if negative {
+            -self.parse_float()?
+        } else {
+            self.parse_float()?
+        };
+        if self.eof() || !(self.next_char() == '%' || self.starts_identifier()) {
+            return Ok(Value::Number(value));
+        }
+        Ok(Value::Length(value, self.parse_unit()?))
+    }
+
+    fn parse_float(&mut self) -> ParseResult<f32> {
+        let start = self.pos;
+        let s = self.consume_while(|c| matches!(c, '0'..='9' | '.'));
+
+        match s.parse() {
+            Ok(value) => Ok(value),
+            Err(_) => {
+                let message = format!("Invalid number {:?}", s);
+                self.pos = start;
+                Err(self.error(message))
+            }
+        }
+    }
+
+    pub fn parse_unit(&mut self) -> ParseResult<Unit> {
+        if !self.eof() && self.next_char() == '%' {
+            self.consume_char();
+            return Ok(Unit::Percent);
+        }
+
+        let start = self.pos;
+        match &*self.parse_identifier().to_ascii_lowercase() {
+            "px" => Ok(Unit::Px),
+            "vw" => Ok(Unit::Vw),
+            "vh" => Ok(Unit::Vh),
+            unit => {
+                let message = format!("Unrecognized unit {:?}", unit);
+                self.pos = start;
+                Err(self.error(message))
+            }
+        }
+    }
+
+    // Parse a `#rgb`, `#rgba`, `#rrggbb` or `#rrggbbaa` color
+    pub fn parse_color(&mut self) -> ParseResult<Value> {
+        self.expect_char('#')?;
+        let start = self.pos;
+        // Take the whole word, not just its hex digits, so the error shows all of a malformed
+        // color like `#fé0`. `from_hex` checks the digits before slicing them, so a
+        // multi-byte character is an error rather than a panic.
+        let digits = self.consume_while(char::is_alphanumeric);
+        match Color::from_hex(&format!("#{}", digits)) {
+            Ok(color) => Ok(Value::ColorValue(color)),
+            Err(err) => {
+                self.pos = start;
+                Err(self.error(err.to_string()))
+            }
+        }
+    }
+}
+
+impl Rule {
+    // Does the rule apply at the given viewport size?
+    pub fn matches_media(&self, viewport: Viewport) -> bool {
+        self.media.is_empty() || self.media.iter().any(|query| query.matches(viewport))
+    }
+}
+
+impl MediaQuery {
+    // Only screen media are rendered, so queries for other media types never match
+    pub fn matches(&self, viewport: Viewport) -> bool {
+        let media_type = self.media_type.as_deref().unwrap_or("all");
+        matches!(media_type, "all" | "screen")
+            && self.features.iter().all(|feature| match *feature {
+                MediaFeature::MinWidth(width) => viewport.width >= width,
+                MediaFeature::MaxWidth(width) => viewport.width <= width,
+            })
+    }
+}
+
+pub type Specificity = (usize, usize, usize);
 
 impl Selector {
+    pub fn specificity(&self) -> Specificity {
+        match *self {
+            Selector::Simple(ref simple) => simple.specificity(),
+            // The specificities of all the simple selectors add up
+            Selector::Combined(ref sibling, _, ref simple) => {
+                let (a, b, c) = sibling.specificity();
+                let (x, y, z) = simple.specificity();
+                (a + x, b + y, c + z)
+            }
+        }
+    }
+
+    // The pseudo-element the selector targets, if any
+    pub fn pseudo_element(&self) -> Option<PseudoElement> {
+        match *self {
+            Selector::Simple(ref simple) | Selector::Combined(_, _, ref simple) => {
+                simple.pseudo_element
+            }
+        }
+    }
+}
+
+impl SimpleSelector {
     pub fn specificity(&self) -> Specificity {
         // https://www.w3.org/TR/selectors/#specificity
-        let Selector::Simple(ref simple) = *self;
-        let a = simple.id.iter().count();
-        let b = simple.class.len();
-        let c = simple.tag_name.iter().count();
-        (a, b, c)
+        let a = self.id.iter().count();
+        let b = self.class.len();
+        let c = self.tag_name.iter().count() + self.pseudo_element.iter().count();
+
+        match self.pseudo {
+            // `:not()` counts as its argument
+            Some(PseudoClass::Not(ref argument)) => {
+                let (x, y, z) = argument.specificity();
+                (a + x, b + y, c + z)
+            }
+            // Other pseudo-classes count like classes
+            Some(_) => (a, b + 1, c),
+            None => (a, b, c),
+        }
     }
 }
 
-fn valid_identifier_char(c: char) -> bool {
-    match c {
-        'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_' => true,
-        _ => false,
+// Parse the `an+b` micro-syntax used by `:nth-child()`, including `odd` and `even`
+// https://www.w3.org/TR/css-syntax-3/#anb-microsyntax
+fn parse_an_plus_b(argument: &str) -> Option<(i32, i32)> {
+    let argument: String = argument
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect::<String>()
+        .to_ascii_lowercase();
+
+    match &*argument {
+        "odd" => Some((2, 1)),
+        "even" => Some((2, 0)),
+        _ => match argument.find('n') {
+            Some(n) => {
+                let a = match &argument[..n] {
+                    "" | "+" => 1,
+                    "-" => -1,
+                    a => a.parse().ok()?,
+                };
+                let b = match &argument[n + 1..] {
+                    "" => 0,
+                    b => b.strip_prefix('+').unwrap_or(b).parse().ok()?,
+                };
+                Some((a, b))
+            }
+            None => Some((0, argument.parse().ok()?)),
+        },
     }
 }
 
+fn valid_identifier_start(c: char) -> bool {
+    matches!(c, 'a'..='z' | 'A'..='Z' | '_')
+}
+
+fn valid_identifier_char(c: char) -> bool {
+    matches!(c, 'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_')
+}
+
 impl Value {
     // return the size of a length in px, or zero for non-lengths
     pub fn to_px(&self) -> f32 {
@@ -305,4 +1910,504 @@ impl Value {
             _ => 0.0,
         }
     }
+
+    // Return the size of a length in px, resolving percentages against `reference` and
+    // viewport units against `viewport`
+    pub fn resolve(&self, reference: f32, viewport: Viewport) -> f32 {
+        match *self {
+            Value::Length(f, ref unit) => unit.resolve(f, reference, viewport),
+            Value::Calc(ref calc) => calc.resolve(reference, viewport),
+            _ => 0.0,
+        }
+    }
+}
+
+impl Unit {
+    fn resolve(&self, value: f32, reference: f32, viewport: Viewport) -> f32 {
+        match *self {
+            Unit::Px => value,
+            Unit::Percent => value * reference / 100.0,
+            Unit::Vw => value * viewport.width / 100.0,
+            Unit::Vh => value * viewport.height / 100.0,
+        }
+    }
+}
+
+impl Calc {
+    pub fn resolve(&self, reference: f32, viewport: Viewport) -> f32 {
+        match *self {
+            Calc::Length(f, ref unit) => unit.resolve(f, reference, viewport),
+            Calc::Add(ref a, ref b) => {
+                a.resolve(reference, viewport) + b.resolve(reference, viewport)
+            }
+            Calc::Sub(ref a, ref b) => {
+                a.resolve(reference, viewport) - b.resolve(reference, viewport)
+            }
+        }
+    }
+}
+
+// One value for some space-separated components: the component itself if there is only one
+fn list_value(mut components: Vec<Value>) -> Value {
+    match components.len() {
+        1 => components.pop().unwrap(),
+        _ => Value::List(components),
+    }
+}
+
+// Values are written back out as CSS, for `--dump-style`
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Value::Keyword(ref keyword) => write!(f, "{}", keyword),
+            Value::Length(length, ref unit) => write!(f, "{}{}", length, unit),
+            Value::Number(number) => write!(f, "{}", number),
+            Value::String(ref string) => write_quoted(f, string),
+            Value::ColorValue(color) => write!(f, "{}", color),
+            Value::Calc(ref calc) => write!(f, "calc({})", calc),
+            Value::LinearGradient { ref stops } => {
+                write!(f, "linear-gradient(")?;
+                write_separated(f, stops, ", ")?;
+                write!(f, ")")
+            }
+            Value::List(ref values) => write_separated(f, values, " "),
+            Value::CommaList(ref values) => write_separated(f, values, ", "),
+            Value::FontFamilies(ref families) => {
+                for (i, family) in families.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    if family.contains(char::is_whitespace) {
+                        write_quoted(f, family)?;
+                    } else {
+                        write!(f, "{}", family)?;
+                    }
+                }
+                Ok(())
+            }
+            Value::Url(ref url) => {
+                write!(f, "url(")?;
+                write_quoted(f, url)?;
+                write!(f, ")")
+            }
+            Value::Var(ref name, None) => write!(f, "var({})", name),
+            Value::Var(ref name, Some(ref fallback)) => write!(f, "var({}, {})", name, fallback),
+            Value::Ratio(width, height) => write!(f, "{} / {}", width, height),
+            Value::Transform(ref function) => write!(f, "{}", function),
+            Value::PendingSubstitution(_, ref value) => write!(f, "{}", value),
+            Value::Unparsed(ref source) => write!(f, "{}", source),
+        }
+    }
+}
+
+impl fmt::Display for Unit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let unit = match *self {
+            Unit::Px => "px",
+            Unit::Percent => "%",
+            Unit::Vw => "vw",
+            Unit::Vh => "vh",
+        };
+        write!(f, "{}", unit)
+    }
+}
+
+impl fmt::Display for TransformFunction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TransformFunction::Translate(x, y) => write!(f, "translate({}px, {}px)", x, y),
+            TransformFunction::Scale(x, y) => write!(f, "scale({}, {})", x, y),
+            TransformFunction::Rotate(angle) => write!(f, "rotate({}deg)", angle.to_degrees()),
+        }
+    }
+}
+
+impl fmt::Display for Calc {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Calc::Length(length, ref unit) => write!(f, "{}{}", length, unit),
+            Calc::Add(ref a, ref b) => write!(f, "{} + {}", a, b),
+            Calc::Sub(ref a, ref b) => write!(f, "{} - {}", a, b),
+        }
+    }
+}
+
+// Colors are written as `#rrggbb`, or `#rrggbbaa` when they aren't opaque
+impl fmt::Display for Color {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.to_hex())
+    }
+}
+
+fn write_separated<T: fmt::Display>(
+    f: &mut fmt::Formatter,
+    items: &[T],
+    separator: &str,
+) -> fmt::Result {
+    for (i, item) in items.iter().enumerate() {
+        if i > 0 {
+            write!(f, "{}", separator)?;
+        }
+        write!(f, "{}", item)?;
+    }
+    Ok(())
+}
+
+// Write a string in double quotes, escaping quotes and backslashes inside it
+fn write_quoted(f: &mut fmt::Formatter, string: &str) -> fmt::Result {
+    write!(f, "\"")?;
+    for c in string.chars() {
+        if matches!(c, '"' | '\\') {
+            write!(f, "\\")?;
+        }
+        write!(f, "{}", c)?;
+    }
+    write!(f, "\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The declarations of a rule with the given block, once its shorthands are expanded
+    fn declarations(block: &str) -> Vec<Declaration> {
+        let mut stylesheet = parse(format!("p {{ {} }}", block)).unwrap();
+        stylesheet.rules.remove(0).declarations
+    }
+
+    // The value of the last declaration of `name` in a rule with the given block
+    fn value(block: &str, name: &str) -> Option<Value> {
+        declarations(block)
+            .into_iter()
+            .rev()
+            .find(|declaration| declaration.name == name)
+            .map(|declaration| declaration.value)
+    }
+
+    fn keyword(keyword: &str) -> Value {
+        Value::Keyword(keyword.to_string())
+    }
+
+    #[test]
+    fn rejects_multiplication_in_calc() {
+        assert!(matches!(
+            value("width: calc(100% - 20px);", "width"),
+            Some(Value::Calc(_))
+        ));
+        let err = parse("p { width: calc(100% * 2); }".to_string()).unwrap_err();
+        assert_eq!(err.message, "calc() only supports + and - for now");
+    }
+
+    #[test]
+    fn rejects_id_selectors_starting_with_a_digit() {
+        let selector = parse_selector("#main").unwrap();
+        assert_eq!(selector.specificity(), (1, 0, 0));
+        let err = parse_selector("#1bad").unwrap_err();
+        assert_eq!(err.message, "Invalid ID selector name \"1bad\"");
+        assert!(parse_selector("#-1bad").is_err());
+    }
+
+    #[test]
+    fn parses_a_selector_list_with_a_parser_of_its_own() {
+        let mut parser = Parser::new("h1, .foo { width: 1px; }".to_string());
+        let selectors = parser.parse_selectors().unwrap();
+        let specificities: Vec<_> = selectors.iter().map(Selector::specificity).collect();
+        assert_eq!(specificities, [(0, 1, 0), (0, 0, 1)]);
+        assert_eq!(parser.pos, "h1, .foo ".len());
+
+        let declarations = parser.parse_declarations().unwrap();
+        assert_eq!(declarations.len(), 1);
+        assert!(parser.eof());
+    }
+
+    #[test]
+    fn reports_the_line_and_column_of_an_error() {
+        let err = parse("a { width: 1px }\nb {\n  width 1px }".to_string()).unwrap_err();
+        assert_eq!((err.line, err.column), (3, 9));
+        assert_eq!(err.message, "Expected ':' but found '1'");
+    }
+
+    #[test]
+    fn parses_unitless_numbers() {
+        assert_eq!(value("opacity: 0.5", "opacity"), Some(Value::Number(0.5)));
+        assert_eq!(
+            value("line-height: 1.5", "line-height"),
+            Some(Value::Number(1.5))
+        );
+        assert_eq!(value("flex-grow: 2", "flex-grow"), Some(Value::Number(2.0)));
+    }
+
+    #[test]
+    fn skips_unknown_at_rules() {
+        let source = "@font-face { font-family: X; src: url(x.ttf) }
+            @charset \"utf-8\";
+            @keyframes spin { from { width: 0px } to { width: 10px } }
+            p { width: 1px }";
+        let stylesheet = parse(source.to_string()).unwrap();
+        assert_eq!(stylesheet.rules.len(), 1);
+        assert_eq!(stylesheet.rules[0].declarations[0].name, "width");
+    }
+
+    #[test]
+    fn converts_colors_to_and_from_hex() {
+        for hex in ["#ff8000", "#ff800040", "#000000"] {
+            assert_eq!(Color::from_hex(hex).unwrap().to_hex(), hex);
+        }
+        assert_eq!(
+            Color::from_hex("#f80").unwrap(),
+            Color::from_rgba(255, 136, 0, 255)
+        );
+        assert_eq!(Color::from_hex("#FF8000").unwrap().to_hex(), "#ff8000");
+        assert_eq!(Color::from_hex("#f804").unwrap().to_hex(), "#ff880044");
+        for invalid in ["ff8000", "#ff800", "#ff80000", "#gg8000", "#"] {
+            assert!(Color::from_hex(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn strips_the_quotes_of_font_family_names() {
+        assert_eq!(
+            value(
+                r#"font-family: "Helvetica Neue", 'Arial', serif"#,
+                "font-family"
+            ),
+            Some(Value::FontFamilies(vec![
+                "Helvetica Neue".to_string(),
+                "Arial".to_string(),
+                "serif".to_string()
+            ]))
+        );
+    }
+
+    #[test]
+    fn mixes_lightens_and_darkens_colors() {
+        let white = Color::from_rgba(255, 255, 255, 255);
+        let black = Color::from_rgba(0, 0, 0, 255);
+        assert_eq!(white.mix(&black, 0.5), Color::from_rgba(128, 128, 128, 255));
+        assert_eq!(white.mix(&black, 2.0), black);
+
+        let red = Color::from_rgba(200, 0, 0, 100);
+        assert_eq!(red.lighten(1.0), Color::from_rgba(255, 255, 255, 100));
+        assert_eq!(red.darken(0.5), Color::from_rgba(100, 0, 0, 100));
+        assert_eq!(red.with_alpha(255), Color::from_rgba(200, 0, 0, 255));
+    }
+
+    #[test]
+    fn parses_the_same_rules_whatever_the_whitespace() {
+        let compact = concat!(
+            "h1.title#main,p+a{margin:0 auto;color:#ff0000!important;",
+            "font-family:\"Times New Roman\",serif}"
+        );
+        let spaced = r#"
+            h1.title#main ,
+            p + a {
+                margin : 0  auto ;
+                color: #ff0000 ! important;
+                font-family: "Times New Roman" , serif ;
+            }
+        "#;
+        let parsed = |source: &str| format!("{:?}", parse(source.to_string()).unwrap().rules);
+        assert_eq!(parsed(compact), parsed(spaced));
+    }
+
+    #[test]
+    fn consumes_a_multi_byte_final_character() {
+        let mut parser = Parser::new("aé".to_string());
+        assert_eq!(parser.consume_char(), 'a');
+        assert_eq!(parser.consume_char(), 'é');
+        assert_eq!(parser.pos, 3);
+        assert!(parser.eof());
+
+        // The error is at the end of the input, just after the é
+        let err = parse("p { content: \"café".to_string()).unwrap_err();
+        assert_eq!(err.message, "Unterminated string");
+        assert_eq!((err.line, err.column), (1, 19));
+    }
+
+    #[test]
+    fn rejects_truncated_and_non_ascii_hex_colors() {
+        for (source, color) in [
+            ("p { color: #ff }", "#ff"),
+            ("p { color: #ff", "#ff"),
+            ("p { color: #fé000000 }", "#fé000000"),
+        ] {
+            let err = parse(source.to_string()).unwrap_err();
+            assert_eq!(err.message, format!("Invalid hex color {:?}", color));
+            // At the digits after the `#`
+            assert_eq!(err.column, 13);
+        }
+    }
+
+    #[test]
+    fn knows_unimplemented_properties() {
+        let source = "p { cursor: pointer; transition: opacity 1s; frobnicate: 1px }";
+        let stylesheet = parse(source.to_string()).unwrap();
+        let unknown: Vec<&str> = stylesheet.rules[0]
+            .declarations
+            .iter()
+            .map(|declaration| declaration.name.as_str())
+            .filter(|name| !is_known_property(name))
+            .collect();
+        assert_eq!(unknown, ["frobnicate"]);
+        assert_eq!(value("cursor: pointer", "cursor"), Some(keyword("pointer")));
+    }
+
+    #[test]
+    fn colors_print_as_their_hex_form() {
+        let opaque = Color::from_rgba(255, 0, 128, 255);
+        let translucent = Color::from_rgba(255, 0, 128, 64);
+        assert_eq!(opaque.to_string(), "#ff0080");
+        assert_eq!(translucent.to_string(), "#ff008040");
+        assert_eq!(translucent.to_string(), translucent.to_hex());
+    }
+
+    #[test]
+    fn parses_urls_in_every_quoting_style() {
+        let expected = Some(Value::Url("images/logo.png".to_string()));
+        for url in [
+            r#"url("images/logo.png")"#,
+            "url('images/logo.png')",
+            "url(images/logo.png)",
+            "url( images/logo.png )",
+        ] {
+            let block = format!("background-image: {}", url);
+            assert_eq!(value(&block, "background-image"), expected, "{}", url);
+        }
+        // A quoted URL keeps its spaces
+        assert_eq!(
+            value(r#"background-image: url(" a b.png")"#, "background-image"),
+            Some(Value::Url(" a b.png".to_string()))
+        );
+    }
+
+    // Parse the stylesheet at `url` in `files`, with imports resolved from `files`, and
+    // return the value of the first declaration of each rule
+    fn imported_values(files: &[(&str, &str)], url: &str) -> Vec<String> {
+        let resolve = |url: &str| {
+            files
+                .iter()
+                .find(|(name, _)| *name == url)
+                .map(|(_, source)| source.to_string())
+        };
+        let source = resolve(url).unwrap();
+        let stylesheet = parse_with_imports(source, Some(url), &resolve).unwrap();
+        stylesheet
+            .rules
+            .iter()
+            .map(|rule| rule.declarations[0].value.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn puts_imported_rules_before_the_importing_ones() {
+        let files = [
+            ("a.css", "@import \"sub/b.css\"; a { width: 1px }"),
+            ("sub/b.css", "@import \"../c.css\"; b { width: 2px }"),
+            ("c.css", "c { width: 3px }"),
+        ];
+        assert_eq!(imported_values(&files, "a.css"), ["3px", "2px", "1px"]);
+    }
+
+    #[test]
+    fn imports_each_stylesheet_of_a_cycle_once() {
+        let files = [
+            ("a.css", "@import \"b.css\"; a { width: 1px }"),
+            ("b.css", "@import \"a.css\"; b { width: 2px }"),
+        ];
+        assert_eq!(imported_values(&files, "a.css"), ["2px", "1px"]);
+    }
+
+    #[test]
+    fn returns_on_a_corpus_of_malformed_input() {
+        let corpus = [
+            "",
+            "{",
+            "}",
+            "p {",
+            "p { color",
+            "p { color: }",
+            "p { color: #",
+            "p { color: #12 }",
+            "p { width: 10",
+            "p { width: -px }",
+            "p { width: 1e999px }",
+            "p { width: calc( }",
+            "p { width: calc(1px + ) }",
+            "p { width: var( }",
+            "p { background: url( }",
+            "p { background: url(\" }",
+            "p { font-family: \"",
+            "p { content: \"\\",
+            "p:nth-child( {}",
+            "p:nth-child(99999999999999999999n) {}",
+            "p:not( {}",
+            "p::",
+            "p + {}",
+            ", {}",
+            "@",
+            "@media",
+            "@media (",
+            "@media screen { p {",
+            "@import",
+            "@import url(",
+            "@font-face {",
+            "/*",
+            "p { color: red !",
+            "é { color: red }",
+            "p { transform: rotate( }",
+            "p { aspect-ratio: 1 / }",
+        ];
+        for source in corpus {
+            let _ = parse(source.to_string());
+            let _ = parse_inline_style(source.to_string());
+            parse_lossy(source.to_string());
+        }
+    }
+
+    #[test]
+    fn parses_font_families_in_order() {
+        assert_eq!(
+            value("font-family: Arial, sans-serif", "font-family"),
+            Some(Value::FontFamilies(vec![
+                "Arial".to_string(),
+                "sans-serif".to_string()
+            ]))
+        );
+    }
+
+    #[test]
+    fn parses_comma_separated_values_into_a_list() {
+        let list = value("transition-property: opacity, color", "transition-property");
+        assert_eq!(
+            list,
+            Some(Value::CommaList(vec![keyword("opacity"), keyword("color")]))
+        );
+        assert_eq!(list.unwrap().to_string(), "opacity, color");
+
+        let grouped = value(
+            "transition-property: opacity color, width",
+            "transition-property",
+        );
+        assert_eq!(
+            grouped,
+            Some(Value::CommaList(vec![
+                Value::List(vec![keyword("opacity"), keyword("color")]),
+                keyword("width"),
+            ]))
+        );
+    }
+
+    #[test]
+    fn rejects_empty_comma_separated_items() {
+        for block in [
+            "transition-property: opacity,",
+            "transition-property: , color",
+        ] {
+            // Unimplemented properties keep values that don't parse as their source text
+            assert!(matches!(
+                value(block, "transition-property"),
+                Some(Value::Unparsed(_))
+            ));
+        }
+    }
 }
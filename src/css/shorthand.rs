@@ -0,0 +1,536 @@
+// Expansion of shorthand properties into the longhands they set.
+// Layout and painting only read longhands, so shorthands are expanded as soon as a
+// declaration block is parsed, before the cascade.
+
+use super::{Declaration, Value};
+
+// How the value of a shorthand is spread over its longhands
+enum Expansion {
+    // One to four values for the top, right, bottom and left sides, like `margin: 0 auto`.
+    // Missing sides copy the opposite side, or the top if there is only one value.
+    Sides([&'static str; 4]),
+    // Components in any order, each going to the first longhand that accepts it and isn't
+    // set yet. Longhands left unset are reset to the initial keyword, if they have one.
+    Components(&'static [Component]),
+    // One or two values for a pair of longhands, like `gap: 10px 20px`. A single value
+    // sets both.
+    Pair([&'static str; 2]),
+    // The `font` shorthand: style and weight in any order, then the size, maybe followed by
+    // a line height, then the families
+    Font,
+}
+
+struct Component {
+    longhand: &'static str,
+    accepts: fn(&Value) -> bool,
+    initial: Option<&'static str>,
+}
+
+// Shorthands may expand to other shorthands, which are expanded in turn
+const SHORTHANDS: &[(&str, Expansion)] = &[
+    (
+        "margin",
+        Expansion::Sides(["margin-top", "margin-right", "margin-bottom", "margin-left"]),
+    ),
+    (
+        "padding",
+        Expansion::Sides([
+            "padding-top",
+            "padding-right",
+            "padding-bottom",
+            "padding-left",
+        ]),
+    ),
+    (
+        "border-width",
+        Expansion::Sides([
+            "border-top-width",
+            "border-right-width",
+            "border-bottom-width",
+            "border-left-width",
+        ]),
+    ),
+    (
+        "border",
+        Expansion::Components(&[
+            Component {
+                longhand: "border-width",
+                accepts: is_length,
+                initial: None,
+            },
+            Component {
+                longhand: "border-style",
+                accepts: is_style,
+                initial: Some("none"),
+            },
+            Component {
+                longhand: "border-color",
+                accepts: is_color,
+                initial: None,
+            },
+        ]),
+    ),
+    (
+        "outline",
+        Expansion::Components(&[
+            Component {
+                longhand: "outline-width",
+                accepts: is_length,
+                initial: None,
+            },
+            Component {
+                longhand: "outline-style",
+                accepts: is_style,
+                initial: Some("none"),
+            },
+            Component {
+                longhand: "outline-color",
+                accepts: is_color,
+                initial: None,
+            },
+        ]),
+    ),
+    (
+        "background",
+        Expansion::Components(&[
+            Component {
+                longhand: "background-color",
+                accepts: is_color,
+                initial: Some("transparent"),
+            },
+            Component {
+                longhand: "background-image",
+                accepts: is_image,
+                initial: Some("none"),
+            },
+        ]),
+    ),
+    ("font", Expansion::Font),
+    ("gap", Expansion::Pair(["row-gap", "column-gap"])),
+];
+
+// The longhands set by `font`, in the order their values come in
+const FONT_LONGHANDS: [&str; 5] = [
+    "font-style",
+    "font-weight",
+    "font-size",
+    "line-height",
+    "font-family",
+];
+
+// The style and weight that come before the size of a `font`, in any order
+const FONT_PREFIX: &[Component] = &[
+    Component {
+        longhand: "font-style",
+        accepts: is_font_style,
+        initial: Some("normal"),
+    },
+    Component {
+        longhand: "font-weight",
+        accepts: is_font_weight,
+        initial: Some("normal"),
+    },
+];
+
+// Replace every shorthand declaration with declarations of its longhands, in its place so
+// that later declarations still override it. Shorthands with values that don't fit are
+// dropped, like other invalid declarations.
+pub fn expand_shorthands(declarations: &mut Vec<Declaration>) {
+    let mut expanded = Vec::with_capacity(declarations.len());
+    for declaration in declarations.drain(..) {
+        expand(declaration, &mut expanded);
+    }
+    *declarations = expanded;
+}
+
+fn expand(declaration: Declaration, expanded: &mut Vec<Declaration>) {
+    let expansion = match SHORTHANDS
+        .iter()
+        .find(|(name, _)| declaration.name.eq_ignore_ascii_case(name))
+    {
+        Some((_, expansion)) => expansion,
+        None => {
+            expanded.push(declaration);
+            return;
+        }
+    };
+
+    // A value holding `var()` can only be split up once it is substituted, so until then
+    // every longhand gets the whole value
+    if contains_var(&declaration.value) {
+        for longhand in longhands(expansion) {
+            expanded.push(Declaration {
+                name: longhand.to_string(),
+                value: Value::PendingSubstitution(
+                    declaration.name.clone(),
+                    Box::new(declaration.value.clone()),
+                ),
+                important: declaration.important,
+            });
+        }
+        return;
+    }
+
+    let components = match declaration.value {
+        Value::List(ref components) => components.clone(),
+        // None of the shorthands take comma-separated layers, so these set nothing
+        Value::CommaList(_) => return,
+        ref value => vec![value.clone()],
+    };
+    let longhands = match *expansion {
+        Expansion::Sides(sides) => expand_sides(sides, components),
+        Expansion::Pair(pair) => expand_pair(pair, components),
+        Expansion::Components(table) => expand_components(table, components),
+        Expansion::Font => expand_font(components),
+    };
+
+    for (name, value) in longhands.unwrap_or_default() {
+        expand(
+            Declaration {
+                name: name.to_string(),
+                value,
+                important: declaration.important,
+            },
+            expanded,
+        );
+    }
+}
+
+// Expand a shorthand whose `var()` references have been substituted, returning the value it
+// gives `longhand`
+pub fn expand_substituted(shorthand: &str, value: Value, longhand: &str) -> Option<Value> {
+    let mut expanded = Vec::new();
+    expand(
+        Declaration {
+            name: shorthand.to_string(),
+            value,
+            important: false,
+        },
+        &mut expanded,
+    );
+    expanded
+        .into_iter()
+        .find(|declaration| declaration.name == longhand)
+        .map(|declaration| declaration.value)
+}
+
+// Every longhand a shorthand sets, through any shorthands it expands to
+fn longhands(expansion: &Expansion) -> Vec<&'static str> {
+    let names: Vec<&'static str> = match *expansion {
+        Expansion::Sides(sides) => sides.to_vec(),
+        Expansion::Pair(pair) => pair.to_vec(),
+        Expansion::Components(table) => table.iter().map(|component| component.longhand).collect(),
+        Expansion::Font => FONT_LONGHANDS.to_vec(),
+    };
+    names
+        .into_iter()
+        .flat_map(|name| {
+            match SHORTHANDS
+                .iter()
+                .find(|(shorthand, _)| name.eq_ignore_ascii_case(shorthand))
+            {
+                Some((_, expansion)) => longhands(expansion),
+                None => vec![name],
+            }
+        })
+        .collect()
+}
+
+fn contains_var(value: &Value) -> bool {
+    match value {
+        Value::Var(..) => true,
+        Value::List(components) | Value::CommaList(components) => {
+            components.iter().any(contains_var)
+        }
+        _ => false,
+    }
+}
+
+fn expand_sides(
+    sides: [&'static str; 4],
+    components: Vec<Value>,
+) -> Option<Vec<(&'static str, Value)>> {
+    let (top, right, bottom, left) = match &components[..] {
+        [all] => (all, all, all, all),
+        [vertical, horizontal] => (vertical, horizontal, vertical, horizontal),
+        [top, horizontal, bottom] => (top, horizontal, bottom, horizontal),
+        [top, right, bottom, left] => (top, right, bottom, left),
+        _ => return None,
+    };
+    Some(
+        sides
+            .into_iter()
+            .zip([top, right, bottom, left])
+            .map(|(side, value)| (side, value.clone()))
+            .collect(),
+    )
+}
+
+fn expand_pair(
+    pair: [&'static str; 2],
+    components: Vec<Value>,
+) -> Option<Vec<(&'static str, Value)>> {
+    let (first, second) = match &components[..] {
+        [both] => (both, both),
+        [first, second] => (first, second),
+        _ => return None,
+    };
+    Some(vec![(pair[0], first.clone()), (pair[1], second.clone())])
+}
+
+fn expand_components(
+    table: &'static [Component],
+    components: Vec<Value>,
+) -> Option<Vec<(&'static str, Value)>> {
+    let mut values: Vec<Option<Value>> = vec![None; table.len()];
+    for value in components {
+        let slot = table
+            .iter()
+            .zip(&values)
+            .position(|(component, set)| set.is_none() && (component.accepts)(&value))?;
+        values[slot] = Some(value);
+    }
+
+    Some(
+        table
+            .iter()
+            .zip(values)
+            .filter_map(|(component, value)| {
+                let value = value.or_else(|| {
+                    component
+                        .initial
+                        .map(|keyword| Value::Keyword(keyword.to_string()))
+                })?;
+                Some((component.longhand, value))
+            })
+            .collect(),
+    )
+}
+
+// Expand the components of a `font`, as parsed by `parse_font`: the style and weight, the
+// size, an optional line height, and the families. The line height is reset to `normal` if
+// it isn't given.
+fn expand_font(components: Vec<Value>) -> Option<Vec<(&'static str, Value)>> {
+    let (families, rest) = components.split_last()?;
+    if !matches!(families, Value::FontFamilies(families) if !families.is_empty()) {
+        return None;
+    }
+    let size = rest
+        .iter()
+        .position(|value| matches!(value, Value::Length(..) | Value::Calc(..)))?;
+    let (prefix, rest) = rest.split_at(size);
+    let line_height = match rest {
+        [_] => Value::Keyword("normal".to_string()),
+        [_, line_height] => line_height.clone(),
+        _ => return None,
+    };
+
+    let mut longhands = expand_components(FONT_PREFIX, prefix.to_vec())?;
+    longhands.extend([
+        ("font-size", rest[0].clone()),
+        ("line-height", line_height),
+        ("font-family", families.clone()),
+    ]);
+    Some(longhands)
+}
+
+fn is_length(value: &Value) -> bool {
+    matches!(
+        value,
+        Value::Length(..) | Value::Number(0.0) | Value::Calc(..)
+    )
+}
+
+fn is_color(value: &Value) -> bool {
+    match value {
+        Value::ColorValue(..) => true,
+        Value::Keyword(keyword) => {
+            keyword.eq_ignore_ascii_case("currentcolor")
+                || keyword.eq_ignore_ascii_case("transparent")
+        }
+        _ => false,
+    }
+}
+
+fn is_style(value: &Value) -> bool {
+    matches!(value, Value::Keyword(..)) && !is_color(value)
+}
+
+fn is_image(value: &Value) -> bool {
+    match value {
+        Value::Url(..) | Value::LinearGradient { .. } => true,
+        Value::Keyword(keyword) => keyword.eq_ignore_ascii_case("none"),
+        _ => false,
+    }
+}
+
+fn is_font_style(value: &Value) -> bool {
+    matches!(value, Value::Keyword(keyword)
+        if ["normal", "italic", "oblique"].iter().any(|k| keyword.eq_ignore_ascii_case(k)))
+}
+
+fn is_font_weight(value: &Value) -> bool {
+    match value {
+        Value::Number(..) => true,
+        Value::Keyword(keyword) => ["normal", "bold", "bolder", "lighter"]
+            .iter()
+            .any(|k| keyword.eq_ignore_ascii_case(k)),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // The declarations of a rule with the given block, written out as `name: value`
+    fn expanded(block: &str) -> Vec<String> {
+        let stylesheet = crate::css::parse(format!("p {{ {} }}", block)).unwrap();
+        stylesheet.rules[0]
+            .declarations
+            .iter()
+            .map(|declaration| format!("{}: {}", declaration.name, declaration.value))
+            .collect()
+    }
+
+    #[test]
+    fn expands_sides() {
+        assert_eq!(
+            expanded("margin: 1px 2px"),
+            [
+                "margin-top: 1px",
+                "margin-right: 2px",
+                "margin-bottom: 1px",
+                "margin-left: 2px"
+            ]
+        );
+        assert_eq!(
+            expanded("padding: 1px 2px 3px"),
+            [
+                "padding-top: 1px",
+                "padding-right: 2px",
+                "padding-bottom: 3px",
+                "padding-left: 2px"
+            ]
+        );
+        assert_eq!(
+            expanded("border-width: 1px 2px 3px 4px"),
+            [
+                "border-top-width: 1px",
+                "border-right-width: 2px",
+                "border-bottom-width: 3px",
+                "border-left-width: 4px"
+            ]
+        );
+    }
+
+    #[test]
+    fn expands_components_in_any_order() {
+        assert_eq!(
+            expanded("border: #ff0000 solid 2px"),
+            [
+                "border-top-width: 2px",
+                "border-right-width: 2px",
+                "border-bottom-width: 2px",
+                "border-left-width: 2px",
+                "border-style: solid",
+                "border-color: #ff0000"
+            ]
+        );
+        assert_eq!(
+            expanded("outline: dotted 1px"),
+            ["outline-width: 1px", "outline-style: dotted"]
+        );
+        assert_eq!(
+            expanded("outline: #ff0000"),
+            ["outline-style: none", "outline-color: #ff0000"]
+        );
+        assert_eq!(
+            expanded("background: #ff0000"),
+            ["background-color: #ff0000", "background-image: none"]
+        );
+    }
+
+    #[test]
+    fn expands_fonts_and_gaps() {
+        assert_eq!(
+            expanded("font: italic bold 12px/1.5 Arial, serif"),
+            [
+                "font-style: italic",
+                "font-weight: bold",
+                "font-size: 12px",
+                "line-height: 1.5",
+                "font-family: Arial, serif"
+            ]
+        );
+        assert_eq!(
+            expanded("font: 10px serif"),
+            [
+                "font-style: normal",
+                "font-weight: normal",
+                "font-size: 10px",
+                "line-height: normal",
+                "font-family: serif"
+            ]
+        );
+        assert_eq!(
+            expanded("gap: 10px 20px"),
+            ["row-gap: 10px", "column-gap: 20px"]
+        );
+        assert_eq!(expanded("gap: 5px"), ["row-gap: 5px", "column-gap: 5px"]);
+    }
+
+    #[test]
+    fn keeps_longhands_in_source_order_around_shorthands() {
+        assert_eq!(
+            expanded("margin-left: 5px; margin: 1px 2px; margin-top: 3px"),
+            [
+                "margin-left: 5px",
+                "margin-top: 1px",
+                "margin-right: 2px",
+                "margin-bottom: 1px",
+                "margin-left: 2px",
+                "margin-top: 3px"
+            ]
+        );
+    }
+
+    #[test]
+    fn drops_shorthands_with_values_that_dont_fit() {
+        assert!(expanded("margin: 1px 2px 3px 4px 5px").is_empty());
+        assert!(expanded("border: solid dashed").is_empty());
+    }
+
+    #[test]
+    fn expands_shorthands_in_style_attributes() {
+        let declarations = crate::css::parse_inline_style("gap: 5px 6px".to_string()).unwrap();
+        let names: Vec<&str> = declarations.iter().map(|d| d.name.as_str()).collect();
+        assert_eq!(names, ["row-gap", "column-gap"]);
+    }
+
+    #[test]
+    fn font_shorthand_sets_all_five_longhands() {
+        assert_eq!(
+            expanded(r#"font: italic bold 16px/1.5 "Helvetica", sans-serif"#),
+            [
+                "font-style: italic",
+                "font-weight: bold",
+                "font-size: 16px",
+                "line-height: 1.5",
+                "font-family: Helvetica, sans-serif"
+            ]
+        );
+        // The style and weight can come in either order, and a weight can be a number
+        assert_eq!(
+            expanded("font: 700 oblique 24px/20px monospace"),
+            [
+                "font-style: oblique",
+                "font-weight: 700",
+                "font-size: 24px",
+                "line-height: 20px",
+                "font-family: monospace"
+            ]
+        );
+        // The size and family are required
+        assert!(crate::css::parse("p { font: bold serif }".to_string()).is_err());
+        assert!(crate::css::parse("p { font: italic 12px }".to_string()).is_err());
+    }
+}
@@ -1,14 +1,16 @@
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 
-pub type AttrMap = HashMap<String, String>;
+// Attributes are kept sorted by name, so anything that walks them does so in the same order
+// every time
+pub type AttrMap = BTreeMap<String, String>;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ElementData {
     pub tag_name: String,
     pub attributes: AttrMap,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Node {
     // data common to all nodes
     pub children: Vec<Node>,
@@ -16,12 +18,28 @@ pub struct Node {
     pub node_type: NodeType,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum NodeType {
     Text(String),
     Element(ElementData),
 }
 
+impl ElementData {
+    // The value of the attribute `name`, or None if the element doesn't have it
+    pub fn get_attribute(&self, name: &str) -> Option<&str> {
+        self.attributes.get(name).map(String::as_str)
+    }
+
+    // Set the attribute `name` to `value`, replacing any value it had
+    pub fn set_attribute(&mut self, name: &str, value: &str) {
+        self.attributes.insert(name.to_string(), value.to_string());
+    }
+
+    pub fn has_attribute(&self, name: &str) -> bool {
+        self.attributes.contains_key(name)
+    }
+}
+
 pub fn elem(name: String, attrs: AttrMap, children: Vec<Node>) -> Node {
     Node {
         children,
@@ -38,3 +56,81 @@ pub fn text(data: String) -> Node {
         node_type: NodeType::Text(data),
     }
 }
+
+impl Node {
+    // The contents of every `<style>` element in the document, in document order
+    pub fn style_elements(&self) -> Vec<String> {
+        let mut sources = Vec::new();
+        self.collect_style_elements(&mut sources);
+        sources
+    }
+
+    fn collect_style_elements(&self, sources: &mut Vec<String>) {
+        match self.node_type {
+            NodeType::Element(ref elem) if elem.tag_name == "style" => {
+                let text = self
+                    .children
+                    .iter()
+                    .filter_map(|child| match child.node_type {
+                        NodeType::Text(ref text) => Some(text.as_str()),
+                        NodeType::Element(_) => None,
+                    });
+                sources.push(text.collect());
+            }
+            _ => {
+                for child in &self.children {
+                    child.collect_style_elements(sources);
+                }
+            }
+        }
+    }
+
+    // The `href` of every `<link rel="stylesheet">` element, in document order
+    pub fn stylesheet_links(&self) -> Vec<&str> {
+        let mut links = Vec::new();
+        self.collect_stylesheet_links(&mut links);
+        links
+    }
+
+    fn collect_stylesheet_links<'a>(&'a self, links: &mut Vec<&'a str>) {
+        if let NodeType::Element(ref elem) = self.node_type {
+            let is_stylesheet = elem.get_attribute("rel").is_some_and(|rel| {
+                rel.split_whitespace()
+                    .any(|r| r.eq_ignore_ascii_case("stylesheet"))
+            });
+            if elem.tag_name == "link" && is_stylesheet {
+                if let Some(href) = elem.get_attribute("href") {
+                    links.push(href);
+                }
+            }
+        }
+        for child in &self.children {
+            child.collect_stylesheet_links(links);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gets_sets_and_checks_attributes() {
+        let mut attributes = AttrMap::new();
+        attributes.insert("id".to_string(), "main".to_string());
+        let mut elem = ElementData {
+            tag_name: "div".to_string(),
+            attributes,
+        };
+        assert_eq!(elem.get_attribute("id"), Some("main"));
+        assert_eq!(elem.get_attribute("class"), None);
+        assert!(!elem.has_attribute("class"));
+
+        elem.set_attribute("class", "note");
+        elem.set_attribute("id", "other");
+        assert!(elem.has_attribute("class"));
+        assert_eq!(elem.get_attribute("class"), Some("note"));
+        assert_eq!(elem.get_attribute("id"), Some("other"));
+        assert_eq!(elem.attributes.len(), 2);
+    }
+}
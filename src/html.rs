@@ -1,40 +1,148 @@
 // A simple parser for a tiny subset of HTML
-// Can parse basic opening and closing tags, and text nodes.
+// Can parse basic opening and closing tags, and text nodes. Doctypes are skipped.
 // Not yet supported:
 
 //
 // 1. Comments
-// 2. Doctypes and processing instructions
-// 3. Self closing tags
-// 4. Non-well-formed markup
-// 5. Character entities
+// 2. Processing instructions
+// 3. Non-well-formed markup
+// 4. Character entities
 
-use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
 
 use crate::dom;
 
+// Elements that never have contents or a closing tag
+const VOID_ELEMENTS: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "source", "track",
+    "wbr",
+];
+
+// How deeply elements may be nested by default. Styling and layout recurse once per level,
+// so a limit keeps hostile documents from overflowing the stack.
+pub const DEFAULT_MAX_DEPTH: usize = 512;
+
+// The deepest nesting that styling and layout are known to handle on the main thread's stack,
+// in debug builds too. Limits above it would give up the protection.
+pub const MAX_SAFE_DEPTH: usize = 1024;
+
+// A syntax error in an HTML document, with the 1-based line and column where it was found
+#[derive(Debug, Clone, PartialEq)]
+pub struct HtmlParseError {
+    pub message: String,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl fmt::Display for HtmlParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl Error for HtmlParseError {}
+
+type ParseResult<T> = Result<T, HtmlParseError>;
+
 // Parse an HTML document and return the root element
-pub fn parse(source: String) -> dom::Node {
-    let mut nodes = Parser {
-        pos: 0,
-        input: source,
+pub fn parse(source: String) -> ParseResult<dom::Node> {
+    parse_with_max_depth(source, DEFAULT_MAX_DEPTH)
+}
+
+// Parse an HTML document like `parse`, failing if elements are nested more than `max_depth`
+// deep
+pub fn parse_with_max_depth(source: String, max_depth: usize) -> ParseResult<dom::Node> {
+    let nodes = Parser::new(source, max_depth).parse_nodes()?;
+    Ok(document_root(nodes))
+}
+
+// Parse an HTML document from raw bytes, never failing, for input that may be arbitrary,
+// like a fuzzer's. Invalid UTF-8 is replaced, closing tags that don't match an open element
+// are ignored, elements that aren't closed end with their parent, and a `<` that doesn't
+// start a tag is text. Elements nested more than `DEFAULT_MAX_DEPTH` deep are closed
+// straight away, so their contents become their siblings.
+pub fn parse_lossy(input: &[u8]) -> dom::Node {
+    let input = input.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(input);
+    let mut parser = Parser::new(
+        String::from_utf8_lossy(input).into_owned(),
+        DEFAULT_MAX_DEPTH,
+    );
+    parser.lossy = true;
+
+    let mut nodes = Vec::new();
+    while !parser.eof() {
+        match parser.parse_nodes() {
+            Ok(more) => nodes.extend(more),
+            Err(_) => {
+                // Lossy parsing recovers from every error it knows of. Should one get
+                // through, the rest of the document is kept as text.
+                nodes.push(dom::text(parser.input[parser.pos..].to_string()));
+                break;
+            }
+        }
+        // Parsing stops at a closing tag that has no open element
+        parser.skip_to_tag_end();
     }
-    .parse_nodes();
+    document_root(nodes)
+}
 
-    // If the document contains a root element, just return it.Otherwise, create one.
+// If the document contains a root element, just return it. Otherwise, create one.
+fn document_root(mut nodes: Vec<dom::Node>) -> dom::Node {
     if nodes.len() == 1 {
         nodes.swap_remove(0)
     } else {
-        dom::elem("html".to_string(), HashMap::new(), nodes)
+        dom::elem("html".to_string(), dom::AttrMap::new(), nodes)
+    }
+}
+
+// Parse an HTML document from raw bytes, which must be UTF-8. A leading byte order mark is
+// skipped.
+pub fn parse_bytes(input: &[u8]) -> ParseResult<dom::Node> {
+    parse_bytes_with_max_depth(input, DEFAULT_MAX_DEPTH)
+}
+
+// Parse an HTML document from raw bytes like `parse_bytes`, failing if elements are nested
+// more than `max_depth` deep
+pub fn parse_bytes_with_max_depth(input: &[u8], max_depth: usize) -> ParseResult<dom::Node> {
+    let input = input.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(input);
+    match std::str::from_utf8(input) {
+        Ok(source) => parse_with_max_depth(source.to_string(), max_depth),
+        Err(err) => {
+            // Point at the first invalid byte, after the valid text before it
+            let valid = &input[..err.valid_up_to()];
+            let mut parser = Parser::new(String::from_utf8(valid.to_vec()).unwrap(), max_depth);
+            parser.pos = valid.len();
+            Err(parser.error(format!(
+                "Invalid UTF-8 byte 0x{:02x}",
+                input[err.valid_up_to()]
+            )))
+        }
     }
 }
 
 pub struct Parser {
     pos: usize, // "usize" is an unsigned integer, similar to "size_t" in C
     input: String,
+    // The tag names of the elements the parser is inside, outermost first, and how many it
+    // may be inside at most
+    open_elements: Vec<String>,
+    max_depth: usize,
+    // Whether malformed markup is recovered from rather than failing the parse
+    lossy: bool,
 }
 
 impl Parser {
+    fn new(input: String, max_depth: usize) -> Parser {
+        Parser {
+            pos: 0,
+            input,
+            open_elements: Vec::new(),
+            max_depth,
+            lossy: false,
+        }
+    }
+
     // Read the current character without consuming it
     pub fn next_char(&self) -> char {
         self.input[self.pos..].chars().next().unwrap()
@@ -52,25 +160,27 @@ impl Parser {
 
     // Return the current character and advance self.pos to the next character
     pub fn consume_char(&mut self) -> char {
-        let mut iter = self.input[self.pos..].char_indices();
-        let (_, cur_char) = iter.next().unwrap();
-        let (next_pos, _) = iter.next().unwrap_or((1, ' '));
-        self.pos += next_pos;
+        let cur_char = self.next_char();
+        self.pos += cur_char.len_utf8();
 
-        return cur_char;
+        cur_char
     }
 
-    /// Consume characters until `test` returns false.
-    pub fn consume_while<F>(&mut self, test: F) -> String
+    /// Consume characters until `test` returns false, returning them as a slice of the input
+    /// so that nothing is allocated for characters that are skipped.
+    pub fn consume_while<F>(&mut self, test: F) -> &str
     where
         F: Fn(char) -> bool,
     {
-        let mut result = String::new();
-        while !self.eof() && test(self.next_char()) {
-            result.push(self.consume_char());
-        }
-
-        return result;
+        let start = self.pos;
+        let rest = &self.input[start..];
+        let len = rest
+            .char_indices()
+            .find(|&(_, c)| !test(c))
+            .map_or(rest.len(), |(i, _)| i);
+        self.pos += len;
+
+        &self.input[start..self.pos]
     }
 
     // Consume and discard zero or more whitespace characters
@@ -78,113 +188,469 @@ impl Parser {
         self.consume_while(char::is_whitespace);
     }
 
-    // Parse a tag or attribute name.
+    // The 1-based line and column of the current position
+    pub fn line_col(&self) -> (usize, usize) {
+        let consumed = &self.input[..self.pos.min(self.input.len())];
+        let line_start = consumed.rfind('\n').map_or(0, |i| i + 1);
+        let line = consumed.matches('\n').count() + 1;
+        let column = consumed[line_start..].chars().count() + 1;
+        (line, column)
+    }
+
+    // An error at the current position
+    fn error(&self, message: String) -> HtmlParseError {
+        let (line, column) = self.line_col();
+        HtmlParseError {
+            message,
+            line,
+            column,
+        }
+    }
+
+    // Consume the character `c`, or fail if the input doesn't continue with it
+    fn expect_char(&mut self, c: char) -> ParseResult<()> {
+        if self.eof() {
+            return Err(self.error(format!("Expected {:?} but found the end of input", c)));
+        }
+        if self.next_char() != c {
+            return Err(self.error(format!("Expected {:?} but found {:?}", c, self.next_char())));
+        }
+        self.consume_char();
+        Ok(())
+    }
+
+    // Parse a tag or attribute name. Names are case-insensitive in HTML, so they are
+    // lowercased: `<DIV>` is a `div` element, and `</Div>` closes it.
     pub fn parse_tag_name(&mut self) -> String {
-        self.consume_while(|c| match c {
-            'a'..='z' | 'A'..='Z' | '0'..='9' => true,
-            _ => false,
-        })
+        self.consume_while(|c| matches!(c, 'a'..='z' | 'A'..='Z' | '0'..='9'))
+            .to_ascii_lowercase()
     }
 
     // Parse a single node
-    pub fn parse_node(&mut self) -> dom::Node {
+    pub fn parse_node(&mut self) -> ParseResult<dom::Node> {
         match self.next_char() {
+            '<' if self.lossy && !self.starts_tag() => {
+                // A `<` that doesn't start a tag is text, up to the next `<`
+                self.consume_char();
+                let text = self.consume_while(|c| c != '<');
+                Ok(dom::text(format!("<{}", text)))
+            }
             '<' => self.parse_element(),
-            _ => self.parse_text(),
+            _ => Ok(self.parse_text()),
         }
     }
 
+    // Does the input continue with an opening tag, a `<` followed by a letter?
+    fn starts_tag(&self) -> bool {
+        let mut chars = self.input[self.pos..].chars();
+        chars.next() == Some('<') && chars.next().is_some_and(|c| c.is_ascii_alphabetic())
+    }
+
     // Parse a text node
     pub fn parse_text(&mut self) -> dom::Node {
-        dom::text(self.consume_while(|c| c != '<'))
+        dom::text(self.consume_while(|c| c != '<').to_string())
     }
 
     // Parse a single element, including its open tag, contents, and closing tag
-    pub fn parse_element(&mut self) -> dom::Node {
+    pub fn parse_element(&mut self) -> ParseResult<dom::Node> {
+        let too_deep = self.open_elements.len() >= self.max_depth;
+        if too_deep && !self.lossy {
+            return Err(self.error(format!(
+                "Elements are nested more than {} deep",
+                self.max_depth
+            )));
+        }
+
         //  Opening tag
-        assert!(self.consume_char() == '<');
+        self.expect_char('<')?;
         let tag_name = self.parse_tag_name();
-        let attrs = self.parse_attributes();
-        assert!(self.consume_char() == '>');
+        let attrs = self.parse_attributes()?;
+
+        // Self-closing and void elements end with their opening tag, and so do elements
+        // nested too deeply when parsing is lossy
+        let self_closing = self.start_with("/");
+        if self_closing {
+            self.consume_char();
+        }
+        if self.lossy {
+            self.skip_to_tag_end();
+        } else {
+            self.expect_char('>')?;
+        }
+        if self_closing || too_deep || VOID_ELEMENTS.contains(&&*tag_name) {
+            return Ok(dom::elem(tag_name, attrs, Vec::new()));
+        }
 
         // Contents.
-        let children = self.parse_nodes();
+        self.open_elements.push(tag_name.clone());
+        let mut children = self.parse_nodes()?;
 
         // Closing tag
+        loop {
+            if self.eof() {
+                if self.lossy {
+                    break;
+                }
+                return Err(self.error(format!("Unclosed <{}> element", tag_name)));
+            }
+            let close_start = self.pos;
+            self.expect_char('<')?;
+            self.expect_char('/')?;
+            let close_pos = self.pos;
+            let close_name = self.parse_tag_name();
+            if close_name == tag_name {
+                if self.lossy {
+                    self.skip_to_tag_end();
+                } else {
+                    self.expect_char('>')?;
+                }
+                break;
+            }
+            if !self.lossy {
+                self.pos = close_pos;
+                return Err(self.error(format!(
+                    "Closing tag </{}> doesn't match <{}>",
+                    close_name, tag_name
+                )));
+            }
 
-        assert!(self.consume_char() == '<');
-        assert!(self.consume_char() == '/');
-        assert!(self.parse_tag_name() == tag_name);
-        assert!(self.consume_char() == '>');
+            if self.open_elements.contains(&close_name) {
+                // Closing an enclosing element closes this one too
+                self.pos = close_start;
+                break;
+            }
+            // A closing tag without an open element is ignored
+            self.skip_to_tag_end();
+            children.extend(self.parse_nodes()?);
+        }
+        self.open_elements.pop();
 
-        return dom::elem(tag_name, attrs, children);
+        Ok(dom::elem(tag_name, attrs, children))
+    }
+
+    // Skip the rest of a tag, up to and including its `>`
+    fn skip_to_tag_end(&mut self) {
+        self.consume_while(|c| c != '>');
+        if !self.eof() {
+            self.consume_char();
+        }
     }
 
     // Parse a single name="value" pair
-    pub fn parse_attr(&mut self) -> (String, String) {
+    pub fn parse_attr(&mut self) -> ParseResult<(String, String)> {
         let name = self.parse_tag_name();
-        assert!(self.consume_char() == '=');
-        let value = self.parse_attr_value();
+        if self.lossy {
+            return Ok((name, self.parse_lossy_attr_value()));
+        }
+        self.expect_char('=')?;
+        let value = self.parse_attr_value()?;
 
-        return (name, value);
+        Ok((name, value))
     }
 
     // Parse a quoted value
-    pub fn parse_attr_value(&mut self) -> String {
+    pub fn parse_attr_value(&mut self) -> ParseResult<String> {
+        if self.eof() || !matches!(self.next_char(), '"' | '\'') {
+            return Err(self.error("Expected a quoted attribute value".into()));
+        }
         let open_quote = self.consume_char();
-        assert!(open_quote == '"' || open_quote == '\'');
-        let value = self.consume_while(|c| c != open_quote);
-        assert!(self.consume_char() == open_quote);
-        return value;
+        let value = self.consume_while(|c| c != open_quote).to_string();
+        self.expect_char(open_quote)?;
+        Ok(value)
+    }
+
+    // Parse an attribute's value when parsing is lossy: quoted, unquoted, or missing
+    // altogether, which leaves it empty
+    fn parse_lossy_attr_value(&mut self) -> String {
+        if !self.start_with("=") {
+            return String::new();
+        }
+        self.consume_char();
+        if self.eof() {
+            return String::new();
+        }
+        match self.next_char() {
+            quote @ ('"' | '\'') => {
+                self.consume_char();
+                let value = self.consume_while(|c| c != quote).to_string();
+                if !self.eof() {
+                    self.consume_char();
+                }
+                value
+            }
+            _ => self
+                .consume_while(|c| !c.is_whitespace() && c != '>')
+                .to_string(),
+        }
     }
 
     // Parse a list of name="value" pairs, seperated by whitespace
-    pub fn parse_attributes(&mut self) -> dom::AttrMap {
-        let mut attributes = HashMap::new();
+    pub fn parse_attributes(&mut self) -> ParseResult<dom::AttrMap> {
+        let mut attributes = dom::AttrMap::new();
 
         loop {
             self.consume_whitespace();
 
-            if self.next_char() == '>' {
+            if self.eof() {
+                if self.lossy {
+                    break;
+                }
+                return Err(self.error("Unterminated tag".into()));
+            }
+            if self.next_char() == '>' || self.start_with("/>") {
                 break;
             }
 
-            let (name, value) = self.parse_attr();
+            let start = self.pos;
+            let (name, value) = self.parse_attr()?;
+            if self.lossy && name.is_empty() {
+                // Skip what can't start an attribute name
+                if self.pos == start {
+                    self.consume_char();
+                }
+                continue;
+            }
             attributes.insert(name, value);
         }
 
-        return attributes;
+        Ok(attributes)
+    }
+
+    // Skip a declaration like `<!DOCTYPE html>`, which may contain quoted strings
+    pub fn skip_doctype(&mut self) -> ParseResult<()> {
+        self.expect_char('<')?;
+        self.expect_char('!')?;
+        loop {
+            if self.eof() {
+                if self.lossy {
+                    return Ok(());
+                }
+                return Err(self.error("Unterminated doctype".into()));
+            }
+            match self.consume_char() {
+                '>' => return Ok(()),
+                quote @ ('"' | '\'') => {
+                    self.consume_while(|c| c != quote);
+                    if self.eof() && self.lossy {
+                        return Ok(());
+                    }
+                    self.expect_char(quote)?;
+                }
+                _ => {}
+            }
+        }
+    }
+
+    // Skip a comment, which may be unterminated
+    fn skip_comment(&mut self) {
+        let body = self.pos + "<!--".len();
+        self.pos = match self.input[body..].find("-->") {
+            Some(end) => body + end + "-->".len(),
+            None => self.input.len(),
+        };
     }
 
-    // Parse a sequence of sibling nodes
-    pub fn parse_nodes(&mut self) -> Vec<dom::Node> {
+    // Parse a sequence of sibling nodes. Whitespace inside elements is kept in their text,
+    // for layout to collapse or preserve, but whitespace between top-level nodes is skipped.
+    pub fn parse_nodes(&mut self) -> ParseResult<Vec<dom::Node>> {
         let mut nodes = Vec::new();
 
         loop {
-            self.consume_whitespace();
+            if self.open_elements.is_empty() {
+                self.consume_whitespace();
+            }
 
             if self.eof() || self.start_with("</") {
                 break;
             }
-            nodes.push(self.parse_node());
+            if self.start_with("<!--") && self.lossy {
+                self.skip_comment();
+                continue;
+            }
+            if self.start_with("<!") && !self.start_with("<!--") {
+                self.skip_doctype()?;
+                continue;
+            }
+            nodes.push(self.parse_node()?);
         }
 
-        return nodes;
+        Ok(nodes)
     }
 
     // Parse an HTML document and return the root element
-    pub fn parse(source: String) -> dom::Node {
-        let mut nodes = Parser {
-            pos: 0,
-            input: source,
+    pub fn parse(source: String) -> ParseResult<dom::Node> {
+        parse(source)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dom::NodeType;
+
+    fn text_of(node: &dom::Node) -> Vec<String> {
+        node.children
+            .iter()
+            .filter_map(|child| match child.node_type {
+                NodeType::Text(ref text) => Some(text.clone()),
+                NodeType::Element(_) => None,
+            })
+            .collect()
+    }
+
+    fn tag_name(node: &dom::Node) -> &str {
+        match node.node_type {
+            NodeType::Element(ref elem) => &elem.tag_name,
+            NodeType::Text(_) => "#text",
         }
-        .parse_nodes();
+    }
 
-        // if the document contains a root element, just return it. otherwise, create one
-        if nodes.len() == 1 {
-            nodes.swap_remove(0)
-        } else {
-            dom::elem("html".to_string(), HashMap::new(), nodes)
+    #[test]
+    fn keeps_whitespace_inside_elements() {
+        let root = parse("<p>a <b>b</b> c</p>".to_string()).unwrap();
+        assert_eq!(text_of(&root), ["a ", " c"]);
+    }
+
+    #[test]
+    fn skips_whitespace_between_top_level_nodes() {
+        let root = parse("\n  <p>x</p>\n".to_string()).unwrap();
+        assert_eq!(text_of(&root), ["x"]);
+    }
+
+    #[test]
+    fn reports_the_line_and_column_of_an_error() {
+        let err =
+            parse("<div>\n  <p>\n    <a href=x></a>\n  </p>\n</div>".to_string()).unwrap_err();
+        assert_eq!((err.line, err.column), (3, 13));
+        assert_eq!(err.message, "Expected a quoted attribute value");
+    }
+
+    #[test]
+    fn skips_a_byte_order_mark() {
+        let root = parse_bytes(b"\xEF\xBB\xBF<p>Hi</p>").unwrap();
+        assert_eq!(tag_name(&root), "p");
+        assert_eq!(text_of(&root), ["Hi"]);
+    }
+
+    #[test]
+    fn rejects_invalid_utf8() {
+        let err = parse_bytes(b"<p>\nH\xFFi</p>").unwrap_err();
+        assert_eq!(err.message, "Invalid UTF-8 byte 0xff");
+        assert_eq!((err.line, err.column), (2, 2));
+    }
+
+    #[test]
+    fn skips_doctypes() {
+        let root = parse("<!DOCTYPE html>\n<html><p>Hi</p></html>".to_string()).unwrap();
+        assert_eq!(tag_name(&root), "html");
+        let legacy = r#"<!DOCTYPE html PUBLIC "-//W3C//DTD HTML 4.01//EN" "http://www.w3.org/TR/html4/strict.dtd"><p></p>"#;
+        assert_eq!(tag_name(&parse(legacy.to_string()).unwrap()), "p");
+    }
+
+    #[test]
+    fn keeps_attributes_in_name_order() {
+        let attributes = |html: &str| match parse(html.to_string()).unwrap().node_type {
+            NodeType::Element(elem) => elem.attributes.into_iter().collect::<Vec<_>>(),
+            NodeType::Text(_) => Vec::new(),
+        };
+        let written = attributes(r#"<a title="t" href="h" class="c"></a>"#);
+        assert_eq!(
+            written,
+            attributes(r#"<a class="c" title="t" href="h"></a>"#)
+        );
+        let names: Vec<_> = written.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, ["class", "href", "title"]);
+    }
+
+    #[test]
+    fn parses_tokens_into_the_expected_tree() {
+        let source = r#"<div id="main" class='a b'><p>Héllo, wörld</p><br/>tail</div>"#;
+        let mut attributes = dom::AttrMap::new();
+        attributes.insert("id".to_string(), "main".to_string());
+        attributes.insert("class".to_string(), "a b".to_string());
+        let paragraph = dom::elem(
+            "p".to_string(),
+            dom::AttrMap::new(),
+            vec![dom::text("Héllo, wörld".to_string())],
+        );
+        let br = dom::elem("br".to_string(), dom::AttrMap::new(), Vec::new());
+        let expected = dom::elem(
+            "div".to_string(),
+            attributes,
+            vec![paragraph, br, dom::text("tail".to_string())],
+        );
+        let parsed = parse(source.to_string()).unwrap();
+        assert_eq!(format!("{:?}", parsed), format!("{:?}", expected));
+    }
+
+    #[test]
+    fn consumes_a_multi_byte_final_character() {
+        let mut parser = Parser::new("aé".to_string(), DEFAULT_MAX_DEPTH);
+        assert_eq!(parser.consume_char(), 'a');
+        assert_eq!(parser.consume_char(), 'é');
+        assert_eq!(parser.pos, 3);
+        assert!(parser.eof());
+
+        let root = parse("<p>x</p>é".to_string()).unwrap();
+        assert_eq!(text_of(&root), ["é"]);
+    }
+
+    #[test]
+    fn lowercases_tag_and_attribute_names_but_not_values() {
+        let root = parse(r#"<DIV ID="Main"><P></p></Div>"#.to_string()).unwrap();
+        assert_eq!(tag_name(&root), "div");
+        assert_eq!(tag_name(&root.children[0]), "p");
+        match root.node_type {
+            NodeType::Element(ref elem) => assert_eq!(elem.get_attribute("id"), Some("Main")),
+            NodeType::Text(_) => panic!("expected an element"),
+        }
+    }
+
+    #[test]
+    fn rejects_elements_nested_deeper_than_the_limit() {
+        let deep = "<div>".repeat(10_000) + &"</div>".repeat(10_000);
+        let err = parse_with_max_depth(deep, 100).unwrap_err();
+        assert_eq!((err.line, err.column), (1, 501));
+
+        let shallow = "<div>".repeat(100) + &"</div>".repeat(100);
+        assert!(parse_with_max_depth(shallow, 100).is_ok());
+    }
+
+    #[test]
+    fn returns_on_a_corpus_of_malformed_input() {
+        let corpus: &[&[u8]] = &[
+            b"",
+            b"<",
+            b"</",
+            b"<>",
+            b"</>",
+            b"<div",
+            b"<div id",
+            b"<div id=",
+            b"<div id=\"",
+            b"<div id='x>",
+            b"<div></span>",
+            b"</div><div>",
+            b"<div><p>",
+            b"<!--",
+            b"<!-- -- >",
+            b"<!DOCTYPE",
+            b"&",
+            b"&#;",
+            b"&#xffffffff;",
+            b"&#0;",
+            b"\xEF\xBB\xBF",
+            b"\xff\xfe<div>",
+            b"<div>\xc3</div>",
+            b"<\xe2\x82\xac>",
+            b"<script>",
+            b"<pre>\n",
+            b"<div a=1 a=2 a=3>",
+        ];
+        let deep = b"<b>".repeat(DEFAULT_MAX_DEPTH * 4);
+        for input in corpus.iter().copied().chain([&deep[..]]) {
+            let _ = parse_bytes(input);
+            parse_lossy(input);
         }
     }
 }
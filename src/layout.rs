@@ -1,11 +1,26 @@
 // CSS box model. All sizes are in px
 
-use crate::css::Unit::Px;
-use crate::css::Value::{Keyword, Length};
-use crate::style::{Display, StyledNode};
+use crate::css::Unit::{Percent, Px, Vh, Vw};
+use crate::css::Value::{self, Keyword, Length};
+use crate::css::Viewport;
+use crate::dom::NodeType;
+use crate::style::{Clear, Display, Float, Overflow, Position, StyledNode, WritingMode};
+
+use self::replaced::{is_replaced, load_replaced};
 
 pub use self::BoxType::{AnonymousBlock, BlockNode, InlineNode};
 
+mod flex;
+mod inline;
+mod json;
+mod query;
+mod replaced;
+mod table;
+mod vertical;
+
+pub use self::query::find_box_by_selector;
+pub use self::replaced::Replaced;
+
 #[derive(Clone, Copy, Default, Debug)]
 pub struct Dimensions {
     // Position of the content area relative to the document origin
@@ -17,7 +32,7 @@ pub struct Dimensions {
     pub margin: EdgeSizes,
 }
 
-#[derive(Clone, Copy, Default, Debug)]
+#[derive(Clone, Copy, Default, Debug, PartialEq)]
 pub struct Rect {
     pub x: f32,
     pub y: f32,
@@ -37,6 +52,25 @@ pub struct LayoutBox<'a> {
     pub dimensions: Dimensions,
     pub box_type: BoxType<'a>,
     pub children: Vec<LayoutBox<'a>>,
+    // For text boxes, the pieces of text placed on each line
+    pub fragments: Vec<TextFragment>,
+    // For boxes with `overflow: scroll` or `auto`, the area their content covers: the
+    // content box together with the margin boxes of in-flow children
+    pub scroll_size: Option<Rect>,
+    // For replaced elements like `<img>`, the content painted in place of children
+    pub replaced: Option<Replaced>,
+    // The height of the containing block, which percentage heights resolve against, if it
+    // doesn't depend on the content
+    pub containing_height: Option<f32>,
+}
+
+// A run of text laid out on a single line
+#[derive(Clone, Debug, PartialEq)]
+pub struct TextFragment {
+    pub text: String,
+    // Index of the line box holding the text, within its block
+    pub line: usize,
+    pub rect: Rect,
 }
 
 pub enum BoxType<'a> {
@@ -45,32 +79,114 @@ pub enum BoxType<'a> {
     AnonymousBlock,
 }
 
-// Transform a style tree into a layout tree
+// The floats placed so far inside a block, as margin boxes in document coordinates
+#[derive(Default, Debug)]
+pub struct FloatContext {
+    pub left: Vec<Rect>,
+    pub right: Vec<Rect>,
+}
+
+// Transform a style tree into a layout tree, laid out in the viewport `containing_block`.
+// The viewport's height is kept for viewport units and for positioning absolute boxes, but
+// the root box goes at the top of the viewport, not below it.
 pub fn layout_tree<'a>(
     node: &'a StyledNode<'a>,
     mut containing_block: Dimensions,
 ) -> LayoutBox<'a> {
-    // The layout algorithm expects the container height to start at 0
+    let viewport = Viewport {
+        width: containing_block.content.width,
+        height: containing_block.content.height,
+    };
+
+    // Blocks are placed below the height their container has so far, which starts at 0
     containing_block.content.height = 0.0;
 
+    // Percentage heights on the root box are of the viewport
     let mut root_box = build_layout_tree(node);
-    root_box.layout(containing_block);
+    root_box.containing_height = Some(viewport.height);
+    root_box.layout(containing_block, viewport);
+
+    // Boxes without a positioned ancestor are positioned against the viewport
+    if root_box.position() == Position::Static {
+        let initial_containing_block = Rect {
+            height: viewport.height,
+            ..containing_block.content
+        };
+        root_box.layout_absolute_descendants(initial_containing_block, viewport);
+    }
 
     root_box
 }
 
 pub fn build_layout_tree<'a>(style_node: &'a StyledNode) -> LayoutBox<'a> {
     // Create the root box
-    let mut root = LayoutBox::new(match style_node.display() {
-        Display::Block => BlockNode(style_node),
+    let box_type = match style_node.display() {
+        Display::Block
+        | Display::ListItem
+        | Display::InlineBlock
+        | Display::Flex
+        | Display::Table
+        | Display::TableRowGroup
+        | Display::TableRow
+        | Display::TableCell => BlockNode(style_node),
+        // Floated and absolutely positioned inline elements are laid out as blocks, and
+        // inline replaced elements as inline-blocks
+        Display::Inline if is_blockified(style_node) || is_replaced(style_node) => {
+            BlockNode(style_node)
+        }
         Display::Inline => InlineNode(style_node),
-        Display::None => panic!("Root node has display: none."),
-    });
+        // A hidden root generates no boxes, so an empty one stands in for the page
+        Display::None => return LayoutBox::new(AnonymousBlock),
+    };
+
+    build_layout_box(style_node, box_type)
+}
+
+fn build_layout_box<'a>(style_node: &'a StyledNode, box_type: BoxType<'a>) -> LayoutBox<'a> {
+    let mut root = LayoutBox::new(box_type);
+    root.replaced = load_replaced(style_node);
+    let flex_container = style_node.display() == Display::Flex;
+    // Tables, row groups and rows only hold blocks: their rows and cells
+    let table_container = matches!(
+        style_node.display(),
+        Display::Table | Display::TableRowGroup | Display::TableRow
+    );
 
     // Create the descendant boxes
     for child in &style_node.children {
         match child.display() {
-            Display::Block => root.children.push(build_layout_tree(child)),
+            Display::Block
+            | Display::ListItem
+            | Display::Flex
+            | Display::Table
+            | Display::TableRowGroup
+            | Display::TableRow
+            | Display::TableCell => root.children.push(build_layout_tree(child)),
+            Display::Inline | Display::InlineBlock if is_blockified(child) => {
+                root.children.push(build_layout_tree(child))
+            }
+            // Inline-blocks are flex items like any other child of a flex container
+            Display::InlineBlock if flex_container => root.children.push(build_layout_tree(child)),
+            Display::InlineBlock => root
+                .get_inline_container()
+                .children
+                .push(build_layout_tree(child)),
+            Display::Inline if is_replaced(child) && !flex_container && !table_container => root
+                .get_inline_container()
+                .children
+                .push(build_layout_tree(child)),
+            Display::Inline if flex_container || table_container => match child.node.node_type {
+                // The children of a flex container or table are laid out as blocks, and text
+                // between them that is only whitespace isn't rendered
+                NodeType::Element(_) => root
+                    .children
+                    .push(build_layout_box(child, BlockNode(child))),
+                NodeType::Text(ref text) if text.trim().is_empty() => {}
+                NodeType::Text(_) => root
+                    .get_inline_container()
+                    .children
+                    .push(build_layout_tree(child)),
+            },
             Display::Inline => root
                 .get_inline_container()
                 .children
@@ -79,7 +195,23 @@ pub fn build_layout_tree<'a>(style_node: &'a StyledNode) -> LayoutBox<'a> {
         }
     }
 
-    return root;
+    root
+}
+
+// Is this box an inline-block placed on a line, rather than a block on a line of its own?
+fn is_inline_block(layout_box: &LayoutBox) -> bool {
+    match layout_box.box_type {
+        BlockNode(style) => {
+            let display = style.display();
+            (display == Display::InlineBlock || display == Display::Inline && is_replaced(style))
+                && !is_blockified(style)
+        }
+        _ => false,
+    }
+}
+
+fn is_blockified(style_node: &StyledNode) -> bool {
+    style_node.float() != Float::None || style_node.position() == Position::Absolute
 }
 
 impl<'a> LayoutBox<'a> {
@@ -89,6 +221,10 @@ impl<'a> LayoutBox<'a> {
             box_type,
             dimensions: Default::default(),
             children: Vec::new(),
+            fragments: Vec::new(),
+            scroll_size: None,
+            replaced: None,
+            containing_height: None,
         }
     }
 
@@ -119,47 +255,187 @@ impl<'a> LayoutBox<'a> {
     }
 
     // Lay out a box and its descendants.
-    pub fn layout(&mut self, containing_block: Dimensions) {
+    pub fn layout(&mut self, containing_block: Dimensions, viewport: Viewport) {
         match self.box_type {
-            BlockNode(_) => self.layout_block(containing_block),
-            InlineNode(_) => {}  // TODO
-            AnonymousBlock => {} // TODO
+            BlockNode(_) => self.layout_block(containing_block, viewport),
+            InlineNode(_) => self.layout_inline_root(containing_block, viewport),
+            AnonymousBlock => self.layout_anonymous_block(containing_block, viewport),
         }
     }
 
-    pub fn layout_block(&mut self, containing_block: Dimensions) {
+    pub fn layout_block(&mut self, containing_block: Dimensions, viewport: Viewport) {
         // Child width can depend on parent width, so we need to calculate
         // this box's width before laying out its children
-        self.calculate_block_width(containing_block);
+        self.calculate_block_width(containing_block, viewport);
 
         // Determine where the box is located within its container
-        self.calculate_block_position(containing_block);
+        self.calculate_block_position(containing_block, viewport);
+
+        self.layout_block_contents(viewport);
+
+        // A positioned box is the containing block of absolutely positioned descendants
+        if self.position() != Position::Static {
+            let containing_block = self.dimensions.padding_box();
+            self.layout_absolute_descendants(containing_block, viewport);
+        }
+    }
+
+    // Lay out the children of a block whose width and position are known, then its height
+    fn layout_block_contents(&mut self, viewport: Viewport) {
+        let height = self.specified_height(viewport);
+        self.pass_containing_height(height);
 
         // Recurvively lay out the children of this box
-        self.layout_block_children();
+        let floats = match self.get_style_node().display() {
+            Display::Flex => {
+                self.layout_flex_children(viewport);
+                FloatContext::default()
+            }
+            Display::Table => {
+                self.layout_table_children(viewport);
+                FloatContext::default()
+            }
+            _ if self.writing_mode() == WritingMode::VerticalRl => {
+                self.layout_vertical_children(viewport);
+                FloatContext::default()
+            }
+            _ => self.layout_block_children(viewport),
+        };
 
         // Parent height can depend on child height, so `calculate_height`
-        self.calculate_block_height();
+        self.calculate_block_height(&floats, viewport);
+
+        self.calculate_scroll_size();
+    }
+
+    // Tell the children of a block how tall it is, when that doesn't depend on them. Anonymous
+    // and inline boxes pass it on, since the boxes inside them have the same containing block.
+    fn pass_containing_height(&mut self, height: Option<f32>) {
+        for child in &mut self.children {
+            child.containing_height = height;
+            if matches!(child.box_type, AnonymousBlock | InlineNode(_)) {
+                child.pass_containing_height(height);
+            }
+        }
+    }
+
+    // Record how far the children of a scroll container reach, now that it and they have
+    // been laid out
+    fn calculate_scroll_size(&mut self) {
+        if !matches!(
+            self.get_style_node().overflow(),
+            Overflow::Scroll | Overflow::Auto
+        ) {
+            return;
+        }
+        let content = self.dimensions.content;
+        self.scroll_size = Some(
+            self.children
+                .iter()
+                .map(|child| child.dimensions.margin_box())
+                .fold(content, Rect::union),
+        );
+    }
+
+    // Whether the content of a scroll container is wider than its content box
+    pub fn overflows_horizontally(&self) -> bool {
+        self.scroll_size.is_some_and(|scroll| {
+            let content = self.dimensions.content;
+            scroll.x < content.x || scroll.x + scroll.width > content.x + content.width
+        })
+    }
+
+    // Whether the content of a scroll container is taller than its content box
+    pub fn overflows_vertically(&self) -> bool {
+        self.scroll_size.is_some_and(|scroll| {
+            let content = self.dimensions.content;
+            scroll.y < content.y || scroll.y + scroll.height > content.y + content.height
+        })
+    }
+
+    // Move a box and all of its descendants by the given offset
+    pub fn translate(&mut self, dx: f32, dy: f32) {
+        self.dimensions.content.x += dx;
+        self.dimensions.content.y += dy;
+        if let Some(ref mut scroll) = self.scroll_size {
+            scroll.x += dx;
+            scroll.y += dy;
+        }
+        for fragment in &mut self.fragments {
+            fragment.rect.x += dx;
+            fragment.rect.y += dy;
+        }
+        for child in &mut self.children {
+            child.translate(dx, dy);
+        }
+    }
+
+    fn float(&self) -> Float {
+        match self.box_type {
+            BlockNode(style) => style.float(),
+            _ => Float::None,
+        }
+    }
+
+    fn writing_mode(&self) -> WritingMode {
+        match self.box_type {
+            BlockNode(style) | InlineNode(style) => style.writing_mode(),
+            AnonymousBlock => WritingMode::HorizontalTb,
+        }
+    }
+
+    fn position(&self) -> Position {
+        match self.box_type {
+            BlockNode(style) => style.position(),
+            _ => Position::Static,
+        }
+    }
+
+    fn clear(&self) -> Clear {
+        match self.box_type {
+            BlockNode(style) => style.clear(),
+            _ => Clear::None,
+        }
     }
 
-    pub fn calculate_block_width(&mut self, containing_block: Dimensions) {
+    pub fn calculate_block_width(&mut self, containing_block: Dimensions, viewport: Viewport) {
         let style = self.get_style_node();
 
         // `width` has initial value `auto`
         let auto = Keyword("auto".to_string());
-        let mut width = style.value("width").unwrap_or(auto.clone());
+
+        // Percentages and `calc()` resolve against the width of the containing block
+        let resolve = |value: Value| match value {
+            Length(..) | Value::Calc(_) => {
+                Length(value.resolve(containing_block.content.width, viewport), Px)
+            }
+            _ => value,
+        };
+
+        let mut width = resolve(style.value("width").unwrap_or(auto.clone()));
+        // Replaced elements take their width from their content instead of the container
+        if width == auto {
+            if let Some(replaced_width) = self.replaced_width(viewport) {
+                width = Length(replaced_width, Px);
+            }
+        }
 
         // margin, border and padding have initial value 0
         let zero = Length(0.0, Px);
 
-        let mut margin_left = style.lookup("margin-left", "margin", &zero);
-        let mut margin_right = style.lookup("margin-right", "margin", &zero);
+        let mut margin_left = resolve(style.lookup("margin-left", &zero));
+        let mut margin_right = resolve(style.lookup("margin-right", &zero));
 
-        let border_left = style.lookup("border-left-width", "border-width", &zero);
-        let border_right = style.lookup("border-right-width", "border-width", &zero);
+        let border_left = style.lookup("border-left-width", &zero);
+        let border_right = style.lookup("border-right-width", &zero);
 
-        let padding_left = style.lookup("padding-left-width", "padding", &zero);
-        let padding_right = style.lookup("padding-right-width", "padding", &zero);
+        let mut padding_left = resolve(style.lookup("padding-left", &zero));
+        // A list item's marker sits in front of its content, as if it were padding
+        let marker_width = style.marker_width();
+        if marker_width > 0.0 {
+            padding_left = Length(padding_left.to_px() + marker_width, Px);
+        }
+        let padding_right = resolve(style.lookup("padding-right", &zero));
 
         let total = sum([
             &margin_left,
@@ -188,6 +464,29 @@ impl<'a> LayoutBox<'a> {
         let underflow = containing_block.content.width - total;
 
         match (width == auto, margin_left == auto, margin_right == auto) {
+            // Floats and absolutely positioned boxes don't stretch their margins to fill the
+            // container, and an auto width takes whatever space is left
+            _ if is_blockified(style) => {
+                if margin_left == auto {
+                    margin_left = Length(0.0, Px);
+                }
+                if margin_right == auto {
+                    margin_right = Length(0.0, Px);
+                }
+                if width == auto {
+                    // An absolutely positioned box also keeps clear of its left and right offsets
+                    let insets = match style.position() {
+                        Position::Absolute => {
+                            let reference = Some(containing_block.content.width);
+                            inset(style, "left", reference, viewport).unwrap_or(0.0)
+                                + inset(style, "right", reference, viewport).unwrap_or(0.0)
+                        }
+                        _ => 0.0,
+                    };
+                    width = Length((underflow - insets).max(0.0), Px);
+                }
+            }
+
             // If the values are overconstrained, caculate margin_right.
             (false, false, false) => {
                 margin_right = Length(margin_right.to_px() + underflow, Px);
@@ -240,31 +539,39 @@ impl<'a> LayoutBox<'a> {
         d.margin.right = margin_right.to_px();
     }
 
-    fn calculate_block_position(&mut self, containing_block: Dimensions) {
+    fn calculate_block_position(&mut self, containing_block: Dimensions, viewport: Viewport) {
         let style = self.get_style_node();
         let d = &mut self.dimensions;
 
         // margin, border and padding have initial value 0
         let zero = Length(0.0, Px);
 
+        // Vertical percentages also resolve against the containing block's width
+        let reference = containing_block.content.width;
+
         // If margin-top  or margin-bottom is `auto`, the used value is zero
-        d.margin.top = style.lookup("margin-top", "margin", &zero).to_px();
-        d.margin.bottom = style.lookup("margin-bottom", "margin", &zero).to_px();
+        d.margin.top = style
+            .lookup("margin-top", &zero)
+            .resolve(reference, viewport);
+        d.margin.bottom = style
+            .lookup("margin-bottom", &zero)
+            .resolve(reference, viewport);
 
-        d.border.top = style
-            .lookup("border-top-width", "border-width", &zero)
-            .to_px();
-        d.border.bottom = style
-            .lookup("border-bottom-width", "border-width", &zero)
-            .to_px();
+        d.border.top = style.lookup("border-top-width", &zero).to_px();
+        d.border.bottom = style.lookup("border-bottom-width", &zero).to_px();
 
-        d.padding.top = style.lookup("padding-top", "padding", &zero).to_px();
-        d.padding.bottom = style.lookup("padding-bottom", "padding", &zero).to_px();
+        d.padding.top = style
+            .lookup("padding-top", &zero)
+            .resolve(reference, viewport);
+        d.padding.bottom = style
+            .lookup("padding-bottom", &zero)
+            .resolve(reference, viewport);
 
         d.content.x = containing_block.content.x + d.margin.left + d.border.left + d.padding.left;
 
-        // Position the box below all the previous boxes in the container
-
+        // Position the box below all the previous boxes in the container. While its children
+        // are laid out, a container's height is how far they reach so far, so it must start at
+        // 0: `layout_tree` makes sure the root box starts at the top of the viewport.
         d.content.y = containing_block.content.height
             + containing_block.content.y
             + d.margin.top
@@ -272,26 +579,252 @@ impl<'a> LayoutBox<'a> {
             + d.padding.top;
     }
 
-    fn layout_block_children(&mut self) {
+    fn layout_block_children(&mut self, viewport: Viewport) -> FloatContext {
+        let mut floats = FloatContext::default();
+        // Percentage offsets of relatively positioned children resolve against this box's
+        // size, but vertical ones only if its height doesn't depend on them
+        let height = self.specified_height(viewport);
         let d = &mut self.dimensions;
         for child in &mut self.children {
-            child.layout(*d);
+            if child.position() == Position::Absolute {
+                // Absolutely positioned boxes are laid out later by their containing block.
+                // Until then, remember where the box would have been in the flow.
+                child.dimensions.content.x = d.content.x;
+                child.dimensions.content.y = d.content.y + d.content.height;
+                continue;
+            }
+
+            // A cleared box starts below the floats on the sides it clears
+            let mut containing_block = *d;
+            if let Some(bottom) = floats.clearance(child.clear()) {
+                let cleared_height = bottom - containing_block.content.y;
+                containing_block.content.height =
+                    containing_block.content.height.max(cleared_height);
+            }
+            child.layout(containing_block, viewport);
+
+            let side = child.float();
+            if side != Float::None {
+                // Floats are taken out of the flow, so they don't move the cursor
+                floats.place(child, side, d.content);
+            } else {
+                d.content.height = containing_block.content.height;
+
+                // Track the height so each child is laid out below the previous content. A
+                // negative margin shrinks the margin box, pulling the next child up over it.
+                d.content.height += child.dimensions.margin_box().height;
+            }
 
-            // Track the height so each child is laid out below the previous content
-            d.content.height = d.content.height + child.dimensions.margin_box().height;
+            // Relative positioning moves a box without affecting the boxes around it
+            if child.position() == Position::Relative {
+                let style = child.get_style_node();
+                let width = Some(d.content.width);
+                let dx = inset(style, "left", width, viewport)
+                    .or(inset(style, "right", width, viewport).map(|right| -right));
+                let dy = inset(style, "top", height, viewport)
+                    .or(inset(style, "bottom", height, viewport).map(|bottom| -bottom));
+                child.translate(dx.unwrap_or(0.0), dy.unwrap_or(0.0));
+            }
         }
+
+        floats
     }
 
-    fn calculate_block_height(&mut self) {
+    fn calculate_block_height(&mut self, floats: &FloatContext, viewport: Viewport) {
+        let style = self.get_style_node();
+
         // if the height is set to an explicit length,use that exact length.
         // Otherwise, just keep the value set by `layout_block_children`.
-        if let Some(Length(h, Px)) = self.get_style_node().value("height") {
-            self.dimensions.content.height = h;
+        if let Some(height) = self.specified_height(viewport) {
+            self.dimensions.content.height = height;
+        } else if let Some(ratio) = style.aspect_ratio() {
+            // An auto height follows from the width
+            self.dimensions.content.height = self.dimensions.content.width / ratio;
+        } else if let Some(height) = self.replaced_height(self.dimensions.content.width) {
+            self.dimensions.content.height = height;
+        } else if style.overflow() != Overflow::Visible || is_blockified(style) {
+            // These boxes start a new block formatting context, which grows to contain its floats
+            if let Some(bottom) = floats.bottom() {
+                let d = &mut self.dimensions;
+                d.content.height = d.content.height.max(bottom - d.content.y);
+            }
+        }
+
+        // Then clamp it between `min-height` (initially 0) and `max-height` (initially
+        // none). The minimum wins if they conflict.
+        let min_height = self.height_property("min-height", viewport).unwrap_or(0.0);
+        let max_height = self
+            .height_property("max-height", viewport)
+            .unwrap_or(f32::INFINITY);
+        let d = &mut self.dimensions;
+        d.content.height = d.content.height.min(max_height).max(min_height);
+    }
+
+    // Lay out the absolutely positioned boxes whose containing block is `containing_block`:
+    // those below this box that don't have a closer positioned ancestor.
+    fn layout_absolute_descendants(&mut self, containing_block: Rect, viewport: Viewport) {
+        for child in &mut self.children {
+            match child.position() {
+                Position::Absolute => child.layout_absolute(containing_block, viewport),
+                // A relatively positioned box has already laid out its own
+                Position::Relative => {}
+                Position::Static => child.layout_absolute_descendants(containing_block, viewport),
+            }
+        }
+    }
+
+    // Lay out an absolutely positioned box, then move it to its offsets from the containing
+    // block. Without offsets, the box stays where it would have been in the flow.
+    fn layout_absolute(&mut self, containing_block: Rect, viewport: Viewport) {
+        let static_position = self.dimensions.content;
+        self.containing_height = Some(containing_block.height);
+        let cb = Dimensions {
+            content: Rect {
+                height: 0.0,
+                ..containing_block
+            },
+            ..Default::default()
+        };
+        self.layout_block(cb, viewport);
+
+        let style = self.get_style_node();
+        let margin_box = self.dimensions.margin_box();
+        let (width, height) = (Some(containing_block.width), Some(containing_block.height));
+        let x = match (
+            inset(style, "left", width, viewport),
+            inset(style, "right", width, viewport),
+        ) {
+            (Some(left), _) => containing_block.x + left,
+            (None, Some(right)) => {
+                containing_block.x + containing_block.width - right - margin_box.width
+            }
+            (None, None) => static_position.x,
+        };
+        let y = match (
+            inset(style, "top", height, viewport),
+            inset(style, "bottom", height, viewport),
+        ) {
+            (Some(top), _) => containing_block.y + top,
+            (None, Some(bottom)) => {
+                containing_block.y + containing_block.height - bottom - margin_box.height
+            }
+            (None, None) => static_position.y,
+        };
+        self.translate(x - margin_box.x, y - margin_box.y);
+    }
+
+    // The used value of the `height` property, unless it is auto
+    fn specified_height(&self, viewport: Viewport) -> Option<f32> {
+        self.height_property("height", viewport)
+    }
+
+    // A vertical length property in px, or None if it's auto. Percentages are of the height
+    // of the containing block, and are treated as auto when that depends on the content.
+    fn height_property(&self, name: &str, viewport: Viewport) -> Option<f32> {
+        match (self.get_style_node().value(name), self.containing_height) {
+            (Some(height @ Length(_, Px | Vw | Vh)), _) => Some(height.resolve(0.0, viewport)),
+            (Some(height @ Length(_, Percent)), Some(reference)) => {
+                Some(height.resolve(reference, viewport))
+            }
+            _ => None,
+        }
+    }
+}
+
+// The offset given by an inset property like `top` or `left` in px, or None if it is auto.
+// Percentages are of `reference`, the width or height of the containing block, and are
+// treated as auto when that isn't known.
+fn inset(
+    style: &StyledNode,
+    name: &str,
+    reference: Option<f32>,
+    viewport: Viewport,
+) -> Option<f32> {
+    match (style.value(name), reference) {
+        (Some(Length(_, Percent)), None) => None,
+        (Some(value @ (Length(..) | Value::Calc(_))), reference) => {
+            Some(value.resolve(reference.unwrap_or(0.0), viewport))
+        }
+        (Some(Value::Number(0.0)), _) => Some(0.0),
+        _ => None,
+    }
+}
+
+impl FloatContext {
+    // Shift a laid-out float box beside the floats already placed on its side, moving it
+    // down below them when there isn't enough room left on the line.
+    fn place(&mut self, float_box: &mut LayoutBox, side: Float, container: Rect) {
+        let margin_box = float_box.dimensions.margin_box();
+        let mut y = margin_box.y;
+
+        loop {
+            let in_band = |rect: &&Rect| rect.y <= y && y < rect.y + rect.height;
+            let left_edge = self
+                .left
+                .iter()
+                .filter(in_band)
+                .map(|rect| rect.x + rect.width)
+                .fold(container.x, f32::max);
+            let right_edge = self
+                .right
+                .iter()
+                .filter(in_band)
+                .map(|rect| rect.x)
+                .fold(container.x + container.width, f32::min);
+            let next_bottom = self
+                .left
+                .iter()
+                .chain(&self.right)
+                .filter(in_band)
+                .map(|rect| rect.y + rect.height)
+                .reduce(f32::min);
+
+            // A float that is wider than the container is still placed once nothing is beside it
+            if margin_box.width <= right_edge - left_edge || next_bottom.is_none() {
+                let x = match side {
+                    Float::Left => left_edge,
+                    _ => right_edge - margin_box.width,
+                };
+                float_box.translate(x - margin_box.x, y - margin_box.y);
+
+                let rect = float_box.dimensions.margin_box();
+                match side {
+                    Float::Left => self.left.push(rect),
+                    _ => self.right.push(rect),
+                }
+                return;
+            }
+
+            y = next_bottom.unwrap();
         }
     }
+
+    // The lowest bottom edge of any float, if there are floats
+    pub fn bottom(&self) -> Option<f32> {
+        self.clearance(Clear::Both)
+    }
+
+    // The lowest bottom edge of the floats a box with the given `clear` value must move below
+    pub fn clearance(&self, clear: Clear) -> Option<f32> {
+        let (left, right): (&[Rect], &[Rect]) = match clear {
+            Clear::Left => (&self.left, &[]),
+            Clear::Right => (&[], &self.right),
+            Clear::Both => (&self.left, &self.right),
+            Clear::None => (&[], &[]),
+        };
+        left.iter()
+            .chain(right)
+            .map(|rect| rect.y + rect.height)
+            .reduce(f32::max)
+    }
 }
 
 impl Dimensions {
+    // The content area on its own
+    pub fn content_box(self) -> Rect {
+        self.content
+    }
+
     // The area covered by the content area plus its padding.
     pub fn padding_box(self) -> Rect {
         self.content.expanded_by(self.padding)
@@ -309,6 +842,42 @@ impl Dimensions {
 }
 
 impl Rect {
+    // Whether the point is inside the rectangle, counting its top and left edges but not its
+    // bottom and right ones
+    pub fn contains(self, x: f32, y: f32) -> bool {
+        x >= self.x && x < self.x + self.width && y >= self.y && y < self.y + self.height
+    }
+
+    // The area inside both rectangles, or None if they don't overlap. Rectangles that only
+    // touch along an edge share no points, so they don't overlap.
+    pub fn intersect(self, other: Rect) -> Option<Rect> {
+        let x = self.x.max(other.x);
+        let y = self.y.max(other.y);
+        let right = (self.x + self.width).min(other.x + other.width);
+        let bottom = (self.y + self.height).min(other.y + other.height);
+        if right <= x || bottom <= y {
+            return None;
+        }
+        Some(Rect {
+            x,
+            y,
+            width: right - x,
+            height: bottom - y,
+        })
+    }
+
+    // The smallest rectangle containing both rectangles
+    pub fn union(self, other: Rect) -> Rect {
+        let x = self.x.min(other.x);
+        let y = self.y.min(other.y);
+        Rect {
+            x,
+            y,
+            width: (self.x + self.width).max(other.x + other.width) - x,
+            height: (self.y + self.height).max(other.y + other.height) - y,
+        }
+    }
+
     pub fn expanded_by(self, edge: EdgeSizes) -> Rect {
         Rect {
             x: self.x - edge.left,
@@ -325,3 +894,225 @@ where
 {
     iter.fold(0., |a, b| a + b)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::Rect;
+    use crate::testing::fixtures::{border_box, fragments, viewport, with_layout};
+
+    #[test]
+    fn overflow_hidden_container_grows_to_contain_its_floats() {
+        let html = r#"<div id="parent"><div id="float"></div></div>"#;
+        let css = "#parent { display: block; }
+            #float { display: block; float: left; width: 30px; height: 50px; }";
+        let height = |css: &str| {
+            with_layout(html, css, (800.0, 600.0), |root| {
+                root.dimensions.border_box().height
+            })
+        };
+        assert_eq!(height(css), 0.0);
+        assert_eq!(
+            height(&format!("{} #parent {{ overflow: hidden; }}", css)),
+            50.0
+        );
+    }
+
+    #[test]
+    fn clear_both_moves_a_box_below_a_taller_float() {
+        let html =
+            r#"<div><div id="float"></div><div id="short"></div><div id="clear"></div></div>"#;
+        let css = "div { display: block; } #float { float: left; width: 30px; height: 50px; }
+            #short { height: 10px; }";
+        assert_eq!(border_box(html, css, "#clear").y, 10.0);
+        let css = format!("{} #clear {{ clear: both; }}", css);
+        assert_eq!(border_box(html, &css, "#clear").y, 50.0);
+    }
+
+    #[test]
+    fn resolves_calc_against_the_containing_width() {
+        let html = r#"<div id="outer"><div id="inner"></div></div>"#;
+        let css = "div { display: block; } #outer { width: 800px; }
+            #inner { width: calc(100% - 20px); }";
+        assert_eq!(border_box(html, css, "#inner").width, 780.0);
+    }
+
+    #[test]
+    fn style_attribute_overrides_a_matching_rule() {
+        let html = r#"<div><div id="box" style="width: 50px"></div></div>"#;
+        assert_eq!(
+            border_box(html, "#box { width: 100px }", "#box").width,
+            50.0
+        );
+    }
+
+    #[test]
+    fn resolves_viewport_units() {
+        let html = r#"<div><div id="box"></div></div>"#;
+        let css = "#box { width: 50vw; height: 10vh }";
+        let rect = border_box(html, css, "#box");
+        assert_eq!((rect.width, rect.height), (400.0, 60.0));
+    }
+
+    #[test]
+    fn generates_a_before_box_ahead_of_the_content() {
+        let html = "<div><p>Hi</p></div>";
+        let css = r#"p::before { content: "X"; }"#;
+        let texts = with_layout(html, css, (800.0, 600.0), |root| {
+            fragments(root)
+                .into_iter()
+                .map(|(text, rect)| (text, rect.x))
+                .collect::<Vec<_>>()
+        });
+        assert_eq!(texts, [("X".to_string(), 0.0), ("Hi".to_string(), 8.0)]);
+    }
+
+    #[test]
+    fn clamps_the_height_between_min_and_max_height() {
+        let html = r#"<div><div id="box"><div id="content"></div></div></div>"#;
+        let css = "#content { height: 50px } #box { min-height: 200px }";
+        assert_eq!(border_box(html, css, "#box").height, 200.0);
+        let css = "#content { height: 50px } #box { max-height: 20px }";
+        assert_eq!(border_box(html, css, "#box").height, 20.0);
+        let css = "#box { height: 300px; max-height: 100px; min-height: 150px }";
+        assert_eq!(border_box(html, css, "#box").height, 150.0);
+    }
+
+    #[test]
+    fn records_the_overflow_of_a_scroll_container() {
+        let html = r#"<div id="parent"><div id="child"></div></div>"#;
+        let css = "#parent { height: 50px; overflow: auto } #child { width: 30px; height: 80px }";
+        let (scroll_size, vertical, horizontal) = with_layout(html, css, (800.0, 600.0), |root| {
+            (
+                root.scroll_size,
+                root.overflows_vertically(),
+                root.overflows_horizontally(),
+            )
+        });
+        assert_eq!(scroll_size.map(|scroll| scroll.height), Some(80.0));
+        assert!(vertical);
+        assert!(!horizontal);
+
+        // Boxes that aren't scroll containers don't record it
+        let css = "#parent { height: 50px } #child { height: 80px }";
+        let scroll_size = with_layout(html, css, (800.0, 600.0), |root| root.scroll_size);
+        assert_eq!(scroll_size, None);
+    }
+
+    #[test]
+    fn resolves_percentage_offsets_against_the_containing_block() {
+        let html = r#"<div><div id="container"><div id="box"></div></div></div>"#;
+        let css = "#container { position: relative; width: 200px; height: 400px }
+            #box { position: absolute; top: 50%; left: 25%; width: 10px; height: 10px }";
+        let rect = border_box(html, css, "#box");
+        assert_eq!((rect.x, rect.y), (50.0, 200.0));
+    }
+
+    #[test]
+    fn indents_the_content_of_list_items_for_their_marker() {
+        let html = r#"<div><div id="item">a</div></div>"#;
+        let text_x =
+            |css: &str| with_layout(html, css, (800.0, 600.0), |root| fragments(root)[0].1.x);
+        let block = text_x("");
+        assert_eq!(text_x("#item { display: list-item }"), block + 16.0);
+        assert_eq!(
+            text_x("#item { display: list-item; list-style-type: none }"),
+            block
+        );
+    }
+
+    #[test]
+    fn lays_out_the_root_box_at_the_viewport_origin() {
+        let root = crate::html::parse("<div></div>".to_string()).unwrap();
+        let stylesheet = crate::css::parse("div { height: 10px }".to_string()).unwrap();
+        let media_viewport = crate::css::Viewport {
+            width: 800.0,
+            height: 600.0,
+        };
+        let style_root = crate::style::style_tree(&root, &[&stylesheet], media_viewport);
+
+        let mut viewport = viewport(800.0, 600.0);
+        viewport.content.x = 20.0;
+        viewport.content.y = 30.0;
+        let layout_root = super::layout_tree(&style_root, viewport);
+        assert_eq!(layout_root.dimensions.content.x, 20.0);
+        assert_eq!(layout_root.dimensions.content.y, 30.0);
+    }
+
+    #[test]
+    fn places_the_top_level_block_at_the_top_of_the_page() {
+        let top = border_box(r#"<div id="top"></div>"#, "#top { height: 10px }", "#top");
+        assert_eq!((top.y, top.height), (0.0, 10.0));
+    }
+
+    #[test]
+    fn resolves_percentage_heights_against_the_containing_block() {
+        let html = r#"<div><div id="container"><div id="box"></div></div></div>"#;
+        let css = "#container { height: 400px } #box { height: 50% }";
+        assert_eq!(border_box(html, css, "#box").height, 200.0);
+
+        // Against an auto height the percentage is treated as auto too
+        let css = "#box { height: 50% }";
+        assert_eq!(border_box(html, css, "#box").height, 0.0);
+    }
+
+    #[test]
+    fn derives_an_auto_height_from_the_aspect_ratio() {
+        let html = r#"<div><div id="box"></div></div>"#;
+        let css = "#box { width: 160px; aspect-ratio: 16 / 9 }";
+        assert_eq!(border_box(html, css, "#box").height, 90.0);
+
+        // A height that is set wins over the ratio
+        let css = "#box { width: 160px; height: 10px; aspect-ratio: 16 / 9 }";
+        assert_eq!(border_box(html, css, "#box").height, 10.0);
+    }
+
+    const PAIR: &str = r#"<div id="parent"><div id="a"></div><div id="b"></div></div>"#;
+    const PAIR_CSS: &str = "#a, #b { height: 20px }";
+
+    #[test]
+    fn negative_margin_pulls_a_box_up() {
+        let normal = border_box(PAIR, PAIR_CSS, "#b");
+        let css = format!("{} #b {{ margin-top: -10px }}", PAIR_CSS);
+        let pulled = border_box(PAIR, &css, "#b");
+        assert_eq!(pulled.y, normal.y - 10.0);
+    }
+
+    #[test]
+    fn negative_margin_shrinks_the_parent_auto_height() {
+        let css = format!("{} #b {{ margin-top: -10px }}", PAIR_CSS);
+        assert_eq!(border_box(PAIR, PAIR_CSS, "#parent").height, 40.0);
+        assert_eq!(border_box(PAIR, &css, "#parent").height, 30.0);
+    }
+
+    #[test]
+    fn intersects_unions_and_tests_points_in_rectangles() {
+        let rect = |x, y, width, height| Rect {
+            x,
+            y,
+            width,
+            height,
+        };
+        let a = rect(0.0, 0.0, 10.0, 10.0);
+
+        // Overlapping
+        let b = rect(5.0, 5.0, 10.0, 10.0);
+        assert_eq!(a.intersect(b), Some(rect(5.0, 5.0, 5.0, 5.0)));
+        assert_eq!(a.union(b), rect(0.0, 0.0, 15.0, 15.0));
+
+        // Touching along an edge
+        let c = rect(10.0, 0.0, 5.0, 10.0);
+        assert_eq!(a.intersect(c), None);
+        assert_eq!(a.union(c), rect(0.0, 0.0, 15.0, 10.0));
+
+        // Disjoint
+        let d = rect(20.0, 30.0, 5.0, 5.0);
+        assert_eq!(a.intersect(d), None);
+        assert_eq!(d.intersect(a), None);
+        assert_eq!(a.union(d), rect(0.0, 0.0, 25.0, 35.0));
+
+        assert!(a.contains(0.0, 0.0));
+        assert!(a.contains(9.5, 9.5));
+        assert!(!a.contains(10.0, 5.0));
+        assert!(!a.contains(5.0, -0.5));
+    }
+}
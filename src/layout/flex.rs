@@ -0,0 +1,350 @@
+// Flex layout: rows or columns of items sharing the space of their container
+
+use super::{sum, AnonymousBlock, BlockNode, Dimensions, InlineNode, LayoutBox, Rect};
+use crate::css::Unit::Px;
+use crate::css::Value::{self, Keyword, Length};
+use crate::css::Viewport;
+use crate::dom::NodeType;
+use crate::style::{FlexDirection, FlexWrap, JustifyContent, WhiteSpace, WritingMode};
+use crate::text;
+
+impl<'a> LayoutBox<'a> {
+    // Lay out the items of a flex container along its main axis, starting a new line when
+    // they don't fit and wrapping is enabled. Items on a line are separated by the main axis
+    // gap, and lines by the cross axis gap. Free space on each line goes to the items that
+    // can grow, and whatever is left is distributed by `justify-content`.
+    pub fn layout_flex_children(&mut self, viewport: Viewport) {
+        let style = self.get_style_node();
+        let container = self.dimensions.content;
+        let direction = style.flex_direction();
+
+        // The space along the main axis, if it is known before laying out the items
+        let main_space = match direction {
+            FlexDirection::Row => Some(container.width),
+            FlexDirection::Column => self.specified_height(viewport),
+        };
+
+        // `column-gap` separates columns and `row-gap` rows, whichever way the items run
+        let column_gap = style.gap("column-gap", container.width, viewport);
+        let row_gap = style.gap(
+            "row-gap",
+            self.specified_height(viewport).unwrap_or(0.0),
+            viewport,
+        );
+        let (main_gap, cross_gap) = match direction {
+            FlexDirection::Row => (column_gap, row_gap),
+            FlexDirection::Column => (row_gap, column_gap),
+        };
+
+        // The size of each item's content along the main axis, before growing
+        let mut sizes = Vec::with_capacity(self.children.len());
+        for item in &mut self.children {
+            item.calculate_shrink_to_fit_edges(container, viewport);
+            let width = item.specified_width(container, viewport);
+            let size = match direction {
+                FlexDirection::Row => width.unwrap_or_else(|| item.max_content_width()),
+                FlexDirection::Column => {
+                    // Items are stretched across the container unless they have a width.
+                    // Their height comes from laying them out, and they are moved into
+                    // place once the lines are known.
+                    let edges = main_edges(item.dimensions, FlexDirection::Row);
+                    let width = width.unwrap_or(container.width - edges).max(0.0);
+                    item.layout_with_width(at(container, 0.0, 0.0), width, viewport);
+                    item.dimensions.content.height
+                }
+            };
+            sizes.push(size);
+        }
+
+        // Break the items into lines that fit in the main space
+        let mut lines = Vec::new();
+        let mut start = 0;
+        let mut used = 0.0;
+        for (i, item) in self.children.iter().enumerate() {
+            let outer = sizes[i] + main_edges(item.dimensions, direction);
+            if let (FlexWrap::Wrap, Some(space)) = (style.flex_wrap(), main_space) {
+                if i > start && used + main_gap + outer > space {
+                    lines.push(start..i);
+                    start = i;
+                    used = 0.0;
+                }
+            }
+            if i > start {
+                used += main_gap;
+            }
+            used += outer;
+        }
+        lines.push(start..self.children.len());
+
+        // Lines are stacked along the cross axis
+        let mut cross_offset = 0.0;
+        let mut main_extent: f32 = 0.0;
+        for (index, line) in lines.into_iter().enumerate() {
+            if index > 0 {
+                cross_offset += cross_gap;
+            }
+            let items = &mut self.children[line.clone()];
+            let sizes = &mut sizes[line];
+
+            let gaps = main_gap * items.len().saturating_sub(1) as f32;
+            let used: f32 = gaps
+                + sum(items
+                    .iter()
+                    .zip(sizes.iter())
+                    .map(|(item, &size)| size + main_edges(item.dimensions, direction)));
+            let mut free_space = main_space.map_or(0.0, |space| space - used);
+
+            let total_grow: f32 = sum(items.iter().map(|item| item.flex_grow()));
+            if free_space > 0.0 && total_grow > 0.0 {
+                for (item, size) in items.iter().zip(sizes.iter_mut()) {
+                    *size += free_space * item.flex_grow() / total_grow;
+                }
+                free_space = 0.0;
+            }
+
+            let (mut main_offset, gap) = match style.justify_content() {
+                JustifyContent::FlexStart => (0.0, 0.0),
+                JustifyContent::Center => (free_space / 2.0, 0.0),
+                JustifyContent::SpaceBetween if items.len() > 1 => {
+                    (0.0, free_space.max(0.0) / (items.len() - 1) as f32)
+                }
+                JustifyContent::SpaceBetween => (0.0, 0.0),
+            };
+
+            let mut cross_size: f32 = 0.0;
+            for (item, &size) in items.iter_mut().zip(sizes.iter()) {
+                match direction {
+                    FlexDirection::Row => {
+                        let containing_block = at(container, main_offset, cross_offset);
+                        item.layout_with_width(containing_block, size, viewport);
+                    }
+                    FlexDirection::Column => {
+                        item.dimensions.content.height = size;
+                        let margin_box = item.dimensions.margin_box();
+                        item.translate(
+                            container.x + cross_offset - margin_box.x,
+                            container.y + main_offset - margin_box.y,
+                        );
+                    }
+                }
+
+                let margin_box = item.dimensions.margin_box();
+                let (item_main, item_cross) = match direction {
+                    FlexDirection::Row => (margin_box.width, margin_box.height),
+                    FlexDirection::Column => (margin_box.height, margin_box.width),
+                };
+                main_extent = main_extent.max(main_offset + item_main);
+                main_offset += item_main + gap + main_gap;
+                cross_size = cross_size.max(item_cross);
+            }
+            cross_offset += cross_size;
+        }
+
+        self.dimensions.content.height = match direction {
+            FlexDirection::Row => cross_offset,
+            FlexDirection::Column => main_extent,
+        };
+    }
+
+    // Resolve the horizontal padding, borders and margins of a box sized like a flex item or
+    // an inline-block
+    pub fn calculate_shrink_to_fit_edges(&mut self, container: Rect, viewport: Viewport) {
+        let style = match self.box_type {
+            BlockNode(style) => style,
+            _ => return,
+        };
+
+        self.calculate_block_width(at(container, 0.0, 0.0), viewport);
+
+        // Auto margins are 0, and items don't stretch their margins to fill the container
+        let zero = Length(0.0, Px);
+        let margin = |name| match style.lookup(name, &zero) {
+            Keyword(_) => 0.0,
+            value => value.resolve(container.width, viewport),
+        };
+        self.dimensions.margin.left = margin("margin-left");
+        self.dimensions.margin.right = margin("margin-right");
+    }
+
+    // The used value of a box's `width` property, unless it is auto
+    pub fn specified_width(&self, container: Rect, viewport: Viewport) -> Option<f32> {
+        match self.box_type {
+            BlockNode(style) => match style.value("width") {
+                Some(width @ (Length(..) | Value::Calc(_))) => {
+                    Some(width.resolve(container.width, viewport))
+                }
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    // Lay out a box with a content width worked out by its container
+    pub fn layout_with_width(
+        &mut self,
+        containing_block: Dimensions,
+        width: f32,
+        viewport: Viewport,
+    ) {
+        match self.box_type {
+            BlockNode(_) => {
+                self.dimensions.content.width = width;
+                self.calculate_block_position(containing_block, viewport);
+                self.layout_block_contents(viewport);
+            }
+            _ => {
+                let mut containing_block = containing_block;
+                containing_block.content.width = width;
+                self.layout(containing_block, viewport);
+            }
+        }
+    }
+
+    fn flex_grow(&self) -> f32 {
+        match self.box_type {
+            BlockNode(style) => style.flex_grow(),
+            _ => 0.0,
+        }
+    }
+
+    // The width of a box's content when nothing wraps, measured from its text
+    pub fn max_content_width(&self) -> f32 {
+        if let Some(width) = self.natural_width() {
+            return width;
+        }
+        match self.box_type {
+            // Vertical writing modes put block children side by side
+            BlockNode(_) if self.writing_mode() == WritingMode::VerticalRl => sum(self
+                .children
+                .iter()
+                .map(|child| child.max_content_width() + child.horizontal_edges())),
+            BlockNode(_) => self
+                .children
+                .iter()
+                .map(|child| child.max_content_width() + child.horizontal_edges())
+                .fold(0.0, f32::max),
+            AnonymousBlock => sum(self
+                .children
+                .iter()
+                .map(|child| child.max_content_width() + child.horizontal_edges())),
+            InlineNode(style) => match style.node.node_type {
+                NodeType::Text(ref text) => {
+                    let text = &style.transform_text(text);
+                    let text_style = style.text_style();
+                    match style.white_space() {
+                        WhiteSpace::Pre => text
+                            .split('\n')
+                            .map(|line| {
+                                let line = text::expand_tabs(line, style.tab_size(), 0);
+                                text::measure_text(&line, text_style)
+                            })
+                            .fold(0.0, f32::max),
+                        WhiteSpace::Normal | WhiteSpace::NoWrap => {
+                            let collapsed = text::collapse_whitespace(text);
+                            text::measure_text(collapsed.trim_matches(' '), text_style)
+                        }
+                    }
+                }
+                NodeType::Element(_) => sum(self
+                    .children
+                    .iter()
+                    .map(|child| child.max_content_width() + child.horizontal_edges())),
+            },
+        }
+    }
+
+    // The total width of a block's horizontal margins, borders and padding in px
+    fn horizontal_edges(&self) -> f32 {
+        match self.box_type {
+            BlockNode(style) => {
+                let zero = Length(0.0, Px);
+                let edge = |name| style.lookup(name, &zero).to_px();
+                edge("margin-left")
+                    + edge("margin-right")
+                    + edge("border-left-width")
+                    + edge("border-right-width")
+                    + edge("padding-left")
+                    + edge("padding-right")
+                    + style.marker_width()
+            }
+            _ => 0.0,
+        }
+    }
+}
+
+// A containing block for a flex item, offset from the top left of the container's content
+// area. Percentages still resolve against the container's width.
+fn at(container: Rect, dx: f32, dy: f32) -> Dimensions {
+    Dimensions {
+        content: Rect {
+            x: container.x + dx,
+            y: container.y + dy,
+            width: container.width,
+            height: 0.0,
+        },
+        ..Default::default()
+    }
+}
+
+// The size of a box's padding, borders and margins along an axis
+fn main_edges(d: Dimensions, direction: FlexDirection) -> f32 {
+    let edges = [d.padding, d.border, d.margin];
+    match direction {
+        FlexDirection::Row => sum(edges.iter().map(|e| e.left + e.right)),
+        FlexDirection::Column => sum(edges.iter().map(|e| e.top + e.bottom)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::fixtures::border_box;
+
+    const ROW: &str = r#"<div id="row"><div id="a"></div><div id="b"></div></div>"#;
+
+    #[test]
+    fn growing_items_split_the_width_evenly() {
+        let css = "#row { display: flex; width: 300px; } #a, #b { flex-grow: 1; height: 10px; }";
+        let (a, b) = (border_box(ROW, css, "#a"), border_box(ROW, css, "#b"));
+        assert_eq!((a.x, a.width), (0.0, 150.0));
+        assert_eq!((b.x, b.width), (150.0, 150.0));
+    }
+
+    #[test]
+    fn justifies_fixed_items_along_the_row() {
+        let css = "#row { display: flex; width: 300px; } #a, #b { width: 50px; height: 10px; }";
+        let centered = format!("{} #row {{ justify-content: center; }}", css);
+        assert_eq!(border_box(ROW, &centered, "#a").x, 100.0);
+        let spread = format!("{} #row {{ justify-content: space-between; }}", css);
+        assert_eq!(border_box(ROW, &spread, "#b").x, 250.0);
+    }
+
+    #[test]
+    fn wraps_a_column_that_is_too_short_into_a_second_column() {
+        let html =
+            r#"<div id="column"><div id="a"></div><div id="b"></div><div id="c"></div></div>"#;
+        let css = "#column { display: flex; flex-direction: column; flex-wrap: wrap; height: 50px }
+            #a, #b, #c { width: 40px; height: 20px }";
+        let boxes = ["#a", "#b", "#c"].map(|selector| border_box(html, css, selector));
+        let positions = boxes.map(|rect| (rect.x, rect.y));
+        assert_eq!(positions, [(0.0, 0.0), (0.0, 20.0), (40.0, 0.0)]);
+    }
+
+    #[test]
+    fn gap_separates_items_and_is_taken_from_the_space_to_grow_into() {
+        let html = r#"<div id="row"><div id="a"></div><div id="b"></div><div id="c"></div></div>"#;
+        let css = "#row { display: flex; width: 320px; gap: 10px }
+            #a, #b, #c { flex-grow: 1; height: 10px }";
+        let boxes = ["#a", "#b", "#c"].map(|selector| border_box(html, css, selector));
+        let spans = boxes.map(|rect| (rect.x, rect.width));
+        assert_eq!(spans, [(0.0, 100.0), (110.0, 100.0), (220.0, 100.0)]);
+    }
+
+    #[test]
+    fn row_gap_separates_wrapped_lines() {
+        let html = r#"<div id="row"><div id="a"></div><div id="b"></div></div>"#;
+        let css =
+            "#row { display: flex; flex-wrap: wrap; width: 50px; row-gap: 5px; column-gap: 20px }
+            #a, #b { width: 40px; height: 10px }";
+        assert_eq!(border_box(html, css, "#b").y, 15.0);
+    }
+}
@@ -0,0 +1,640 @@
+// Inline formatting: flowing text and inline boxes into line boxes
+
+use std::collections::VecDeque;
+
+use super::{
+    is_inline_block, AnonymousBlock, BlockNode, Dimensions, InlineNode, LayoutBox, Rect,
+    TextFragment,
+};
+use crate::css::Viewport;
+use crate::dom::NodeType;
+use crate::style::{Direction, OverflowWrap, StyledNode, VerticalAlign, WhiteSpace};
+use crate::text;
+
+// A single line box. Its position is relative to the top of the block that contains it.
+#[derive(Clone, Copy, Default, Debug)]
+struct Line {
+    y: f32,
+    width: f32,
+    height: f32,
+    // How far the text on the line reaches above and below their shared baseline, with the
+    // leading of each piece of text. The baseline is `ascent` below the top of the line.
+    ascent: f32,
+    descent: f32,
+}
+
+// Words placed next to each other on a line, which become one text fragment
+struct TextRun {
+    text: String,
+    line: usize,
+    // Where the run starts and ends within the line
+    x: f32,
+    end: f32,
+}
+
+// The lines of a block, filled from left to right during the first pass of inline layout
+struct LineBuilder {
+    lines: Vec<Line>,
+
+    // Which edge of the block the lines start from
+    direction: Direction,
+
+    // A collapsed space waiting to be placed in front of the next piece of content
+    pending_space: Option<f32>,
+
+    // Lines holding block-level boxes nested in inline content, in tree order
+    block_lines: VecDeque<usize>,
+
+    // The line and x offset of each inline-block, in tree order
+    inline_blocks: VecDeque<(usize, f32)>,
+}
+
+impl LineBuilder {
+    fn new(direction: Direction) -> LineBuilder {
+        LineBuilder {
+            lines: vec![Line::default()],
+            direction,
+            pending_space: None,
+            block_lines: VecDeque::new(),
+            inline_blocks: VecDeque::new(),
+        }
+    }
+
+    fn current(&self) -> &Line {
+        self.lines.last().unwrap()
+    }
+
+    // Start a new line, dropping any collapsed space at the end of the current one
+    fn break_line(&mut self) {
+        self.lines.push(Line::default());
+        self.pending_space = None;
+    }
+
+    // Queue a collapsible space. Spaces at the start of a line, or following another
+    // collapsible space, disappear.
+    fn add_space(&mut self, width: f32) {
+        if self.current().width > 0.0 && self.pending_space.is_none() {
+            self.pending_space = Some(width);
+        }
+    }
+
+    // Is there room for content of the given width on the current line, after any pending
+    // space? An empty line always has room, so content wider than the line still goes
+    // somewhere.
+    fn fits(&self, width: f32, available_width: f32) -> bool {
+        let line = self.current();
+        let space = self.pending_space.unwrap_or(0.0);
+        line.width == 0.0 || line.width + space + width <= available_width
+    }
+
+    // How many characters `advance` wide would fill the current line up to where the next
+    // content goes
+    fn column(&self, advance: f32) -> usize {
+        if advance <= 0.0 {
+            return 0;
+        }
+        let width = self.current().width + self.pending_space.unwrap_or(0.0);
+        (width / advance).round() as usize
+    }
+
+    // Reserve room for content of the given size on the current line, returning the line
+    // index and the x offset of the content within the line.
+    fn place(&mut self, width: f32, height: f32) -> (usize, f32) {
+        let space = self.pending_space.take().unwrap_or(0.0);
+        let index = self.lines.len() - 1;
+        let line = &mut self.lines[index];
+
+        let x = line.width + space;
+        line.width = x + width;
+        line.height = line.height.max(height);
+
+        (index, x)
+    }
+
+    // Reserve room for a piece of text on the current line, like `place`. The text's line
+    // height is split into `ascent` above the baseline and the rest below, and the line
+    // grows to hold the text of every size on it with their baselines lined up.
+    fn place_text(&mut self, width: f32, height: f32, ascent: f32) -> (usize, f32) {
+        let (index, x) = self.place(width, 0.0);
+        let line = &mut self.lines[index];
+        line.ascent = line.ascent.max(ascent);
+        line.descent = line.descent.max(height - ascent);
+        line.height = line.height.max(line.ascent + line.descent);
+        (index, x)
+    }
+
+    // The x position of content of the given width, placed `x` from the start of its line
+    // in a block whose content area is `container`
+    fn x(&self, container: Rect, x: f32, width: f32) -> f32 {
+        match self.direction {
+            Direction::Ltr => container.x + x,
+            Direction::Rtl => container.x + container.width - x - width,
+        }
+    }
+
+    // Stack the lines on top of each other, returning their total height
+    fn finish(&mut self) -> f32 {
+        let mut y = 0.0;
+        for line in &mut self.lines {
+            line.y = y;
+            y += line.height;
+        }
+        y
+    }
+}
+
+impl<'a> LayoutBox<'a> {
+    // Lay out an anonymous block, which holds the inline children of a block box
+    pub fn layout_anonymous_block(&mut self, containing_block: Dimensions, viewport: Viewport) {
+        // Position the box below all the previous boxes in the container
+        let d = &mut self.dimensions;
+        d.content.x = containing_block.content.x;
+        d.content.y = containing_block.content.y + containing_block.content.height;
+        d.content.width = containing_block.content.width;
+
+        // An anonymous block has no style of its own. Its children inherit the direction of
+        // the block around it, so it takes theirs.
+        let direction = self
+            .children
+            .iter()
+            .find_map(|child| match child.box_type {
+                BlockNode(style) | InlineNode(style) => Some(style.direction()),
+                AnonymousBlock => None,
+            })
+            .unwrap_or(Direction::Ltr);
+
+        let mut lines = LineBuilder::new(direction);
+        for child in &mut self.children {
+            child.flow_inline(&mut lines, d.content.width, viewport);
+        }
+        d.content.height = lines.finish();
+
+        for child in &mut self.children {
+            child.position_inline(d.content, &mut lines);
+        }
+    }
+
+    // Lay out an inline box that has no block container, such as an inline root element
+    pub fn layout_inline_root(&mut self, containing_block: Dimensions, viewport: Viewport) {
+        let mut container = containing_block.content;
+        container.y += container.height;
+
+        let mut lines = LineBuilder::new(self.get_style_node().direction());
+        self.flow_inline(&mut lines, container.width, viewport);
+        lines.finish();
+        self.position_inline(container, &mut lines);
+    }
+
+    // First pass: assign each piece of inline content to a line and an x offset within it
+    fn flow_inline(&mut self, lines: &mut LineBuilder, available_width: f32, viewport: Viewport) {
+        match self.box_type {
+            InlineNode(style) => match style.node.node_type {
+                NodeType::Text(ref text) => self.flow_text(text, style, lines, available_width),
+                NodeType::Element(_) => {
+                    for child in &mut self.children {
+                        child.flow_inline(lines, available_width, viewport);
+                    }
+                }
+            },
+            BlockNode(style) if is_inline_block(self) => {
+                self.flow_inline_block(style, lines, available_width, viewport)
+            }
+            BlockNode(_) | AnonymousBlock => {
+                // Block-level boxes nested in inline content sit on a line of their own
+                if lines.current().width > 0.0 {
+                    lines.break_line();
+                }
+
+                let mut containing_block: Dimensions = Default::default();
+                containing_block.content.width = available_width;
+                self.layout(containing_block, viewport);
+
+                let (index, _) = lines.place(0.0, self.dimensions.margin_box().height);
+                lines.block_lines.push_back(index);
+                lines.break_line();
+            }
+        }
+    }
+
+    // An inline-block is sized like a flex item: its own width if it has one, otherwise the
+    // width of its content, up to the width of the line. It is laid out at the origin and
+    // moved onto its line once the line heights are known.
+    fn flow_inline_block(
+        &mut self,
+        style: &StyledNode,
+        lines: &mut LineBuilder,
+        available_width: f32,
+        viewport: Viewport,
+    ) {
+        let mut containing_block: Dimensions = Default::default();
+        containing_block.content.width = available_width;
+
+        self.calculate_shrink_to_fit_edges(containing_block.content, viewport);
+        let d = self.dimensions;
+        let edges = d.margin_box().width - d.content.width;
+        let width = self
+            .specified_width(containing_block.content, viewport)
+            .or_else(|| self.replaced_width(viewport))
+            .unwrap_or_else(|| {
+                self.max_content_width()
+                    .min(available_width - edges)
+                    .max(0.0)
+            });
+        self.layout_with_width(containing_block, width, viewport);
+
+        let margin_box = self.dimensions.margin_box();
+        if style.white_space() == WhiteSpace::Normal
+            && !lines.fits(margin_box.width, available_width)
+        {
+            lines.break_line();
+        }
+        let (line, x) = lines.place(margin_box.width, margin_box.height);
+        lines.inline_blocks.push_back((line, x));
+    }
+
+    fn flow_text(
+        &mut self,
+        text: &str,
+        style: &StyledNode,
+        lines: &mut LineBuilder,
+        available_width: f32,
+    ) {
+        let text = &style.transform_text(text);
+        let text_style = style.text_style();
+        let line_height = style.line_height();
+        let ascent = style.line_ascent();
+
+        match style.white_space() {
+            WhiteSpace::Pre => {
+                // Preformatted text keeps its spaces and only breaks at newlines. Tab stops
+                // are counted from the start of the line, including any content before the
+                // text.
+                let space_width = text::measure_text(" ", text_style);
+                for (i, segment) in text.split('\n').enumerate() {
+                    if i > 0 {
+                        lines.break_line();
+                    }
+                    let column = lines.column(space_width);
+                    let segment = text::expand_tabs(segment, style.tab_size(), column);
+                    let width = text::measure_text(&segment, text_style);
+                    let (line, x) = lines.place_text(width, line_height, ascent);
+                    self.push_fragment(&segment, line, x, width, line_height);
+                }
+            }
+            white_space @ (WhiteSpace::Normal | WhiteSpace::NoWrap) => {
+                let collapsed = text::collapse_whitespace(text);
+                let space_width = text::measure_text(" ", text_style);
+
+                if collapsed.starts_with(' ') {
+                    lines.add_space(space_width);
+                }
+
+                // Words wrap onto a new line when they don't fit. The words that end up on
+                // the same line share a fragment.
+                let mut run: Option<TextRun> = None;
+                for (i, word) in collapsed.split(' ').filter(|w| !w.is_empty()).enumerate() {
+                    if i > 0 {
+                        lines.add_space(space_width);
+                    }
+                    let width = text::measure_text(word, text_style);
+                    let wraps = white_space == WhiteSpace::Normal;
+                    if wraps && !lines.fits(width, available_width) {
+                        lines.break_line();
+                    }
+
+                    // A word too wide for a line of its own may be broken into pieces that
+                    // go on separate lines
+                    let pieces = if wraps
+                        && width > available_width
+                        && style.overflow_wrap() == OverflowWrap::BreakWord
+                    {
+                        text::break_word(word, available_width, text_style)
+                    } else {
+                        vec![word]
+                    };
+                    for (j, piece) in pieces.into_iter().enumerate() {
+                        if j > 0 {
+                            lines.break_line();
+                        }
+                        let width = text::measure_text(piece, text_style);
+                        let (line, x) = lines.place_text(width, line_height, ascent);
+
+                        match run {
+                            Some(ref mut run) if run.line == line => {
+                                run.text.push(' ');
+                                run.text.push_str(piece);
+                                run.end = x + width;
+                            }
+                            _ => {
+                                if let Some(run) = run.take() {
+                                    self.push_run(run, line_height);
+                                }
+                                run = Some(TextRun {
+                                    text: piece.to_string(),
+                                    line,
+                                    x,
+                                    end: x + width,
+                                });
+                            }
+                        }
+                    }
+                }
+                if let Some(run) = run {
+                    self.push_run(run, line_height);
+                }
+
+                if collapsed.ends_with(' ') {
+                    lines.add_space(space_width);
+                }
+            }
+        }
+    }
+
+    fn push_run(&mut self, run: TextRun, height: f32) {
+        self.push_fragment(&run.text, run.line, run.x, run.end - run.x, height);
+    }
+
+    fn push_fragment(&mut self, text: &str, line: usize, x: f32, width: f32, height: f32) {
+        self.fragments.push(TextFragment {
+            text: text.to_string(),
+            line,
+            rect: Rect {
+                x,
+                y: 0.0,
+                width,
+                height,
+            },
+        });
+    }
+
+    // Second pass: now that line heights are known, move everything to its final position
+    fn position_inline(&mut self, container: Rect, lines: &mut LineBuilder) {
+        match self.box_type {
+            InlineNode(style) => {
+                // Text sits with its baseline on the line's
+                let ascent = style.line_ascent();
+                for fragment in &mut self.fragments {
+                    let line = lines.lines[fragment.line];
+                    fragment.rect.x = lines.x(container, fragment.rect.x, fragment.rect.width);
+                    fragment.rect.y = container.y + line.y + line.ascent - ascent;
+                }
+                for child in &mut self.children {
+                    child.position_inline(container, lines);
+                }
+
+                // An inline box covers all of its content
+                let content = self
+                    .fragments
+                    .iter()
+                    .map(|fragment| fragment.rect)
+                    .chain(
+                        self.children
+                            .iter()
+                            .map(|child| child.dimensions.margin_box()),
+                    )
+                    .reduce(Rect::union);
+                self.dimensions.content = content.unwrap_or(Rect {
+                    x: container.x,
+                    y: container.y,
+                    width: 0.0,
+                    height: 0.0,
+                });
+            }
+            BlockNode(style) if is_inline_block(self) => {
+                let (index, x) = lines.inline_blocks.pop_front().unwrap();
+                let line = lines.lines[index];
+                let margin_box = self.dimensions.margin_box();
+                let offset = match style.vertical_align() {
+                    VerticalAlign::Baseline | VerticalAlign::Top => 0.0,
+                    VerticalAlign::Middle => (line.height - margin_box.height) / 2.0,
+                    VerticalAlign::Bottom => line.height - margin_box.height,
+                };
+                self.translate(
+                    lines.x(container, x, margin_box.width) - margin_box.x,
+                    container.y + line.y + offset - margin_box.y,
+                );
+            }
+            BlockNode(_) | AnonymousBlock => {
+                let index = lines.block_lines.pop_front().unwrap();
+                self.translate(container.x, container.y + lines.lines[index].y);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::fixtures::{border_box, fragments, with_layout};
+
+    const CSS: &str = "p { display: block; }";
+
+    #[test]
+    fn collapses_a_run_of_spaces_to_one() {
+        let placed = with_layout("<p>a    b</p>", CSS, (800.0, 600.0), fragments);
+        assert_eq!(placed.len(), 1);
+        assert_eq!(placed[0].0, "a b");
+        assert_eq!(placed[0].1.width, 24.0);
+    }
+
+    #[test]
+    fn keeps_spaces_between_inline_siblings() {
+        let placed = with_layout("<p>a <b>b</b> c</p>", CSS, (800.0, 600.0), fragments);
+        let text: Vec<_> = placed.iter().map(|(text, _)| text.as_str()).collect();
+        assert_eq!(text, ["a", "b", "c"]);
+        // Each glyph is 8px wide, and so is the space after it
+        assert_eq!(placed[1].1.x - placed[0].1.x, 16.0);
+        assert_eq!(placed[2].1.x - placed[1].1.x, 16.0);
+    }
+
+    #[test]
+    fn advances_a_tab_at_column_0_to_the_tab_stop() {
+        let placed = with_layout(
+            "<pre>\tx</pre>",
+            "pre { tab-size: 4 }",
+            (800.0, 600.0),
+            fragments,
+        );
+        assert_eq!(placed.len(), 1);
+        assert_eq!(placed[0].0, "    x");
+        assert_eq!(placed[0].1.width, 32.0 + 8.0);
+    }
+
+    #[test]
+    fn counts_tab_stops_from_the_start_of_the_line() {
+        let placed = with_layout(
+            "<pre><b>ab</b>\tx</pre>",
+            "pre { tab-size: 4 }",
+            (800.0, 600.0),
+            fragments,
+        );
+        assert_eq!(placed[1].0, "  x");
+        assert_eq!(placed[1].1.x - placed[0].1.x, 16.0);
+    }
+
+    #[test]
+    fn keeps_nowrap_text_on_one_overflowing_line() {
+        let (placed, height) = with_layout(
+            "<div>aaaa bbbb cccc dddd</div>",
+            "div { width: 40px; white-space: nowrap }",
+            (800.0, 600.0),
+            |root| (fragments(root), root.dimensions.content.height),
+        );
+        assert_eq!(placed.len(), 1);
+        assert_eq!(placed[0].0, "aaaa bbbb cccc dddd");
+        assert!(placed[0].1.width > 40.0);
+        assert_eq!(height, placed[0].1.height);
+    }
+
+    #[test]
+    fn wraps_a_sentence_at_spaces() {
+        // Each word and the space after it take 40px, so two fit on an 80px line
+        let placed = with_layout(
+            "<div>aaaa bbbb cccc dddd eeee</div>",
+            "div { width: 80px }",
+            (800.0, 600.0),
+            fragments,
+        );
+        let lines: Vec<_> = placed
+            .iter()
+            .map(|(text, rect)| (text.as_str(), rect.y))
+            .collect();
+        assert_eq!(
+            lines,
+            [("aaaa bbbb", 0.0), ("cccc dddd", 19.2), ("eeee", 38.4)]
+        );
+    }
+
+    #[test]
+    fn places_a_word_wider_than_the_line_on_its_own() {
+        let placed = with_layout(
+            "<div>a bbbbbbbbbbbb c</div>",
+            "div { width: 40px }",
+            (800.0, 600.0),
+            fragments,
+        );
+        let text: Vec<_> = placed.iter().map(|(text, _)| text.as_str()).collect();
+        assert_eq!(text, ["a", "bbbbbbbbbbbb", "c"]);
+    }
+
+    #[test]
+    fn wraps_pre_text_only_at_newlines() {
+        let placed = with_layout(
+            "<pre>aaaa bbbb cccc\ndd</pre>",
+            "pre { width: 40px; margin: 0 }",
+            (800.0, 600.0),
+            fragments,
+        );
+        let text: Vec<_> = placed.iter().map(|(text, _)| text.as_str()).collect();
+        assert_eq!(text, ["aaaa bbbb cccc", "dd"]);
+    }
+
+    #[test]
+    fn aligns_inline_blocks_within_the_line() {
+        let html = r#"<div><span id="tall"></span><span id="short"></span></div>"#;
+        // How far the short box's top is below the tall one's, and its bottom above it
+        let align = |vertical_align: &str| {
+            let css = format!(
+                "span {{ display: inline-block; width: 10px }} #tall {{ height: 50px }}
+                #short {{ height: 10px; vertical-align: {} }}",
+                vertical_align
+            );
+            let (tall, short) = (
+                border_box(html, &css, "#tall"),
+                border_box(html, &css, "#short"),
+            );
+            (
+                short.y - tall.y,
+                tall.y + tall.height - (short.y + short.height),
+            )
+        };
+        assert_eq!(align("top"), (0.0, 40.0));
+        assert_eq!(align("middle"), (20.0, 20.0));
+        assert_eq!(align("bottom"), (40.0, 0.0));
+    }
+
+    #[test]
+    fn lays_out_rtl_inline_boxes_from_the_right() {
+        let html = "<div><b>ab</b><i>c</i></div>";
+        let positions = |css: &str| {
+            with_layout(html, css, (800.0, 600.0), fragments)
+                .into_iter()
+                .map(|(text, rect)| (text, rect.x))
+                .collect::<Vec<_>>()
+        };
+        let text = |text: &str| text.to_string();
+        assert_eq!(positions(""), [(text("ab"), 0.0), (text("c"), 16.0)]);
+        assert_eq!(
+            positions("div { direction: rtl }"),
+            [(text("ab"), 784.0), (text("c"), 776.0)]
+        );
+    }
+
+    #[test]
+    fn inherits_nowrap_from_the_container() {
+        let lines = |css: &str| {
+            let placed = with_layout(
+                "<div><p>aaaa <b>bbbb</b> cccc</p></div>",
+                css,
+                (800.0, 600.0),
+                fragments,
+            );
+            let mut ys: Vec<f32> = placed.iter().map(|(_, rect)| rect.y).collect();
+            ys.dedup();
+            ys.len()
+        };
+        let css = "div { width: 40px; white-space: nowrap }";
+        assert_eq!(lines(css), 1);
+        assert_eq!(lines(&format!("{} p {{ white-space: normal }}", css)), 3);
+    }
+
+    #[test]
+    fn lays_out_transformed_text_but_leaves_the_dom_alone() {
+        let html = "<p>hello wORLD</p>";
+        let laid_out = |css| {
+            with_layout(html, css, (800.0, 600.0), |root| {
+                let dom_text = match root.box_type {
+                    BlockNode(style) => match style.node.children[0].node_type {
+                        NodeType::Text(ref text) => text.clone(),
+                        NodeType::Element(_) => String::new(),
+                    },
+                    _ => String::new(),
+                };
+                (fragments(root)[0].clone(), dom_text)
+            })
+        };
+        let ((text, rect), dom_text) = laid_out("p { text-transform: uppercase }");
+        assert_eq!((text.as_str(), rect.width), ("HELLO WORLD", 88.0));
+        assert_eq!(dom_text, "hello wORLD");
+
+        assert_eq!(
+            laid_out("p { text-transform: lowercase }").0 .0,
+            "hello world"
+        );
+        assert_eq!(
+            laid_out("p { text-transform: capitalize }").0 .0,
+            "Hello WORLD"
+        );
+    }
+
+    #[test]
+    fn break_word_splits_a_long_word_across_lines() {
+        let html = format!("<p>{}</p>", "x".repeat(40));
+        let placed = |css| with_layout(&html, css, (800.0, 600.0), fragments);
+
+        // Without it, the word overflows a single line
+        let overflowing = placed("p { width: 100px }");
+        assert_eq!(overflowing.len(), 1);
+        assert_eq!(overflowing[0].1.width, 320.0);
+
+        // 12 glyphs of 8px fit in each 100px line
+        let broken = placed("p { width: 100px; overflow-wrap: break-word }");
+        let lengths: Vec<_> = broken.iter().map(|(text, _)| text.len()).collect();
+        assert_eq!(lengths, [12, 12, 12, 4]);
+        // Each piece is on the line below the one before
+        for pair in broken.windows(2) {
+            assert!((pair[1].1.y - pair[0].1.y - 19.2).abs() < 0.01);
+        }
+        assert!(broken.iter().all(|(_, rect)| rect.width <= 100.0));
+    }
+}
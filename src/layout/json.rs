@@ -0,0 +1,145 @@
+// A JSON dump of the layout tree, for debugging and external tooling
+
+use std::fmt::Write;
+
+use super::{AnonymousBlock, BlockNode, InlineNode, LayoutBox, Rect};
+use crate::dom::NodeType;
+
+impl<'a> LayoutBox<'a> {
+    // Serialize this box and its descendants. Each box records its type, the tag name of
+    // its element (if any), its content area and the boxes around it, and any text
+    // fragments with the font they are set in.
+    pub fn to_json(&self) -> String {
+        let mut json = String::new();
+        self.write_json(&mut json);
+        json
+    }
+
+    fn write_json(&self, json: &mut String) {
+        let (box_type, style) = match self.box_type {
+            BlockNode(style) => ("block", Some(style)),
+            InlineNode(style) => ("inline", Some(style)),
+            AnonymousBlock => ("anonymous", None),
+        };
+        let tag_name = style.and_then(|style| match style.node.node_type {
+            NodeType::Element(ref elem) => Some(&elem.tag_name),
+            NodeType::Text(_) => None,
+        });
+
+        json.push_str("{\"box_type\":");
+        write_string(json, box_type);
+        json.push_str(",\"tag_name\":");
+        match tag_name {
+            Some(name) => write_string(json, name),
+            None => json.push_str("null"),
+        }
+
+        let d = self.dimensions;
+        json.push_str(",\"dimensions\":{\"content\":");
+        write_rect(json, d.content);
+        json.push_str(",\"padding\":");
+        write_rect(json, d.padding_box());
+        json.push_str(",\"border\":");
+        write_rect(json, d.border_box());
+        json.push_str(",\"margin\":");
+        write_rect(json, d.margin_box());
+        json.push('}');
+
+        // How far the content of a scroll container reaches, and whether it overflows
+        if let Some(scroll) = self.scroll_size {
+            json.push_str(",\"scroll_size\":");
+            write_rect(json, scroll);
+            write!(
+                json,
+                ",\"overflows\":{{\"x\":{},\"y\":{}}}",
+                self.overflows_horizontally(),
+                self.overflows_vertically()
+            )
+            .unwrap();
+        }
+
+        if !self.fragments.is_empty() {
+            json.push_str(",\"fragments\":[");
+            for (i, fragment) in self.fragments.iter().enumerate() {
+                if i > 0 {
+                    json.push(',');
+                }
+                json.push_str("{\"text\":");
+                write_string(json, &fragment.text);
+                json.push_str(",\"rect\":");
+                write_rect(json, fragment.rect);
+                json.push('}');
+            }
+            json.push(']');
+
+            // The font the text is set in
+            if let Some(style) = style {
+                let font = style.font();
+                json.push_str(",\"font\":");
+                write_string(json, &font.family);
+                write!(json, ",\"italic\":{}", font.italic).unwrap();
+            }
+        }
+
+        json.push_str(",\"children\":[");
+        for (i, child) in self.children.iter().enumerate() {
+            if i > 0 {
+                json.push(',');
+            }
+            child.write_json(json);
+        }
+        json.push_str("]}");
+    }
+}
+
+fn write_rect(json: &mut String, rect: Rect) {
+    json.push_str("{\"x\":");
+    write_number(json, rect.x);
+    json.push_str(",\"y\":");
+    write_number(json, rect.y);
+    json.push_str(",\"width\":");
+    write_number(json, rect.width);
+    json.push_str(",\"height\":");
+    write_number(json, rect.height);
+    json.push('}');
+}
+
+// JSON has no representation for NaN or infinities
+fn write_number(json: &mut String, n: f32) {
+    if n.is_finite() {
+        write!(json, "{}", n).unwrap();
+    } else {
+        json.push_str("null");
+    }
+}
+
+fn write_string(json: &mut String, s: &str) {
+    json.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if (c as u32) < 0x20 => write!(json, "\\u{:04x}", c as u32).unwrap(),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::fixtures::with_layout;
+
+    #[test]
+    fn writes_the_root_box_first_with_its_content_area() {
+        let html = r#"<div id="root"><p>"Hi"</p></div>"#;
+        let css = "#root { display: block; width: 300px; height: 20px; }";
+        let json = with_layout(html, css, (800.0, 600.0), |root| root.to_json());
+        let root = r#"{"box_type":"block","tag_name":"div","dimensions":{"content":{"x":0,"y":0,"width":300,"height":20}"#;
+        assert!(json.starts_with(root), "{}", json);
+        assert!(json.contains(r#""text":"\"Hi\"""#), "{}", json);
+    }
+}
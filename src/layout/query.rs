@@ -0,0 +1,128 @@
+// Finding boxes in a laid-out tree, for tools that check where elements ended up
+
+use std::ptr;
+
+use super::{AnonymousBlock, BlockNode, InlineNode, LayoutBox, Rect};
+use crate::css::{self, Selector};
+use crate::dom::NodeType;
+use crate::style::{self, SiblingContext, StyledNode};
+
+// The border box of the first element in tree order that matches `selector`, or None if
+// no box matches or the selector doesn't parse
+pub fn find_box_by_selector(root: &LayoutBox, selector: &str) -> Option<Rect> {
+    let selector = css::parse_selector(selector).ok()?;
+    // Only elements have boxes of their own, not pseudo-elements
+    if selector.pseudo_element().is_some() {
+        return None;
+    }
+    find_box(root, None, &selector).map(|layout_box| layout_box.dimensions.border_box())
+}
+
+impl<'a> LayoutBox<'a> {
+    // The deepest box whose border box contains the point, or None if there isn't one.
+    // Descendants can stick out of their ancestors, so they are searched even when the point
+    // is outside this box. Later boxes are painted over earlier ones, so they are tried first.
+    pub fn hit_test(&self, x: f32, y: f32) -> Option<&LayoutBox<'a>> {
+        self.children
+            .iter()
+            .rev()
+            .find_map(|child| child.hit_test(x, y))
+            .or_else(|| self.dimensions.border_box().contains(x, y).then_some(self))
+    }
+}
+
+// Search `layout_box` and its descendants. `parent` is the style node of the nearest
+// ancestor with one, whose DOM children are the siblings of the element being matched.
+fn find_box<'a>(
+    layout_box: &'a LayoutBox<'a>,
+    parent: Option<&'a StyledNode<'a>>,
+    selector: &Selector,
+) -> Option<&'a LayoutBox<'a>> {
+    let style = match layout_box.box_type {
+        BlockNode(style) | InlineNode(style) => Some(style),
+        AnonymousBlock => None,
+    };
+
+    if let Some(style) = style {
+        if let NodeType::Element(ref elem) = style.node.node_type {
+            if sibling_context(style, parent)
+                .is_some_and(|context| style::matches(elem, context, selector))
+            {
+                return Some(layout_box);
+            }
+        }
+    }
+
+    let parent = style.or(parent);
+    layout_box
+        .children
+        .iter()
+        .find_map(|child| find_box(child, parent, selector))
+}
+
+// Where the element of `style` sits among the children of `parent`. Generated content isn't
+// one of the DOM children, so it has no context.
+fn sibling_context<'a>(
+    style: &'a StyledNode<'a>,
+    parent: Option<&'a StyledNode<'a>>,
+) -> Option<SiblingContext<'a>> {
+    match parent {
+        Some(parent) => {
+            let siblings = &parent.node.children;
+            let index = siblings
+                .iter()
+                .position(|sibling| ptr::eq(sibling, &*style.node))?;
+            Some(SiblingContext { siblings, index })
+        }
+        None => Some(SiblingContext::root(&style.node)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::fixtures::with_layout;
+
+    const DOC: &str = r#"<div><p id="first"></p><p id="second" class="note"></p></div>"#;
+    const CSS: &str = "div { padding: 5px } p { margin: 0; height: 10px; border: 2px solid #000 }";
+
+    #[test]
+    fn finds_the_border_box_of_an_element_by_id() {
+        let found = with_layout(DOC, CSS, (800.0, 600.0), |root| {
+            find_box_by_selector(root, "#second")
+        });
+        let expected = Rect {
+            x: 5.0,
+            y: 5.0 + 14.0,
+            width: 790.0,
+            height: 14.0,
+        };
+        assert_eq!(found, Some(expected));
+    }
+
+    #[test]
+    fn finds_no_box_for_an_unmatched_or_invalid_selector() {
+        with_layout(DOC, CSS, (800.0, 600.0), |root| {
+            assert_eq!(find_box_by_selector(root, "#missing"), None);
+            assert_eq!(find_box_by_selector(root, "p::before"), None);
+            assert_eq!(find_box_by_selector(root, "p >"), None);
+            assert!(find_box_by_selector(root, "p.note").is_some());
+        });
+    }
+
+    #[test]
+    fn hit_test_finds_the_deepest_box_at_a_point() {
+        let html = r#"<div id="outer"><div id="inner"></div></div>"#;
+        let css = "#outer { padding: 20px } #inner { height: 10px }";
+        with_layout(html, css, (800.0, 600.0), |root| {
+            let inner = find_box_by_selector(root, "#inner").unwrap();
+            let hit = root.hit_test(25.0, 25.0).unwrap();
+            assert_eq!(hit.dimensions.border_box(), inner);
+            assert_eq!(hit.dimensions.content_box(), hit.dimensions.content);
+
+            let hit = root.hit_test(5.0, 5.0).unwrap();
+            assert!(ptr::eq(hit, root));
+            assert!(root.hit_test(5.0, 595.0).is_none());
+        });
+    }
+}
@@ -0,0 +1,132 @@
+// Replaced elements: `<img>` boxes, whose content is an image rather than child boxes.
+// Their size comes from the image, unless `width` and `height` are set in CSS or as
+// attributes, and a size that is only set one way keeps the image's aspect ratio.
+
+use std::rc::Rc;
+
+use image::RgbaImage;
+
+use super::{BlockNode, LayoutBox};
+use crate::css::Viewport;
+use crate::dom::NodeType;
+use crate::painting;
+use crate::style::StyledNode;
+
+// The size of the placeholder drawn for an image that can't be loaded
+const PLACEHOLDER_SIZE: f32 = 16.0;
+
+// What a replaced box shows in its content area
+pub enum Replaced {
+    Image(Rc<RgbaImage>),
+    // The image is missing or can't be decoded, so a placeholder is drawn instead
+    BrokenImage,
+}
+
+// Is this an element whose content is replaced, like an image?
+pub fn is_replaced(style_node: &StyledNode) -> bool {
+    style_node.tag_name() == Some("img")
+}
+
+// Load the content of a replaced element, or None for other nodes. Images load relative to
+// the working directory, like background images.
+pub fn load_replaced(style_node: &StyledNode) -> Option<Replaced> {
+    if !is_replaced(style_node) {
+        return None;
+    }
+    let image = match style_node.node.node_type {
+        NodeType::Element(ref elem) => elem.get_attribute("src").and_then(painting::load_image),
+        NodeType::Text(_) => None,
+    };
+    Some(image.map_or(Replaced::BrokenImage, Replaced::Image))
+}
+
+impl Replaced {
+    // The size the content has on its own
+    fn intrinsic_size(&self) -> (f32, f32) {
+        match self {
+            Replaced::Image(image) => (image.width() as f32, image.height() as f32),
+            Replaced::BrokenImage => (PLACEHOLDER_SIZE, PLACEHOLDER_SIZE),
+        }
+    }
+}
+
+impl<'a> LayoutBox<'a> {
+    // The content width of a replaced box whose `width` is auto: its `width` attribute, or
+    // the width that keeps the aspect ratio at its height if that is set, or the width of
+    // the image. None for boxes that aren't replaced.
+    pub fn replaced_width(&self, viewport: Viewport) -> Option<f32> {
+        let (width, height) = self.replaced.as_ref()?.intrinsic_size();
+        match self.specified_height(viewport) {
+            Some(used_height) if self.dimension_attribute("width").is_none() => {
+                Some(used_height * width / height)
+            }
+            _ => self.natural_width(),
+        }
+    }
+
+    // The width a replaced box has from its attributes and image alone, ignoring CSS
+    pub fn natural_width(&self) -> Option<f32> {
+        let (width, height) = self.replaced.as_ref()?.intrinsic_size();
+        let width = self
+            .dimension_attribute("width")
+            .or_else(|| {
+                self.dimension_attribute("height")
+                    .map(|used_height| used_height * width / height)
+            })
+            .unwrap_or(width);
+        Some(width)
+    }
+
+    // The content height of a replaced box laid out at the given width whose `height` is
+    // auto: its `height` attribute, or the height that keeps the aspect ratio. None for
+    // boxes that aren't replaced.
+    pub fn replaced_height(&self, used_width: f32) -> Option<f32> {
+        let (width, height) = self.replaced.as_ref()?.intrinsic_size();
+        let height = self
+            .dimension_attribute("height")
+            .unwrap_or(used_width * height / width);
+        Some(height)
+    }
+
+    // A size given in px by one of the element's attributes, like `width="20"`
+    fn dimension_attribute(&self, name: &str) -> Option<f32> {
+        match self.box_type {
+            BlockNode(style) => match style.node.node_type {
+                NodeType::Element(ref elem) => elem
+                    .get_attribute(name)
+                    .and_then(|value| value.trim().trim_end_matches("px").parse().ok())
+                    .filter(|size: &f32| *size >= 0.0),
+                NodeType::Text(_) => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::fixtures::border_box;
+
+    #[test]
+    fn sizes_an_image_box_by_its_image() {
+        let path = std::env::temp_dir().join(format!("{}-blue-10x10.png", std::process::id()));
+        image::RgbaImage::from_pixel(10, 10, image::Rgba([0, 0, 255, 255]))
+            .save(&path)
+            .unwrap();
+        let html = format!(r#"<div><img id="img" src={:?}></div>"#, path);
+        let rect = border_box(&html, "", "#img");
+        assert_eq!((rect.width, rect.height), (10.0, 10.0));
+
+        // A width attribute scales the height to keep the aspect ratio
+        let html = format!(r#"<div><img id="img" src={:?} width="30"></div>"#, path);
+        let rect = border_box(&html, "", "#img");
+        assert_eq!((rect.width, rect.height), (30.0, 30.0));
+    }
+
+    #[test]
+    fn sizes_a_missing_image_as_a_placeholder() {
+        let html = r#"<div><img id="img" src="missing.png"></div>"#;
+        let rect = border_box(html, "", "#img");
+        assert_eq!((rect.width, rect.height), (16.0, 16.0));
+    }
+}
@@ -0,0 +1,150 @@
+// Table layout: rows of cells in a grid of equally wide columns.
+// Rows are stacked from top to bottom and have no margins, borders or padding of their own.
+// Cells don't have margins either: their border boxes fill the columns of their row, and
+// every cell in a row is stretched to the height of the tallest.
+
+use super::{BlockNode, Dimensions, LayoutBox, Rect};
+use crate::css::Viewport;
+use crate::style::Display;
+
+impl<'a> LayoutBox<'a> {
+    // Lay out the rows of a table, including those in row groups like `<tbody>`. A child
+    // that is neither a row nor a row group is laid out as a block across the table, between
+    // the rows around it.
+    pub fn layout_table_children(&mut self, viewport: Viewport) {
+        let container = self.dimensions.content;
+        let columns = self
+            .children
+            .iter()
+            .flat_map(|child| match table_display(child) {
+                Some(Display::TableRowGroup) => child.children.iter().collect(),
+                _ => vec![child],
+            })
+            .filter(|row| table_display(row) == Some(Display::TableRow))
+            .map(|row| row.children.len())
+            .max()
+            .unwrap_or(0);
+        let column_width = if columns > 0 {
+            container.width / columns as f32
+        } else {
+            0.0
+        };
+
+        self.dimensions.content.height =
+            layout_rows(&mut self.children, container, column_width, viewport);
+    }
+}
+
+// Stack `rows` from the top of `container`, returning their total height
+fn layout_rows(
+    rows: &mut [LayoutBox],
+    container: Rect,
+    column_width: f32,
+    viewport: Viewport,
+) -> f32 {
+    let mut offset = 0.0;
+    for row in rows {
+        let area = Rect {
+            y: container.y + offset,
+            height: 0.0,
+            ..container
+        };
+        match table_display(row) {
+            Some(Display::TableRowGroup) => {
+                row.dimensions = Dimensions {
+                    content: area,
+                    ..Default::default()
+                };
+                let height = row.specified_height(viewport);
+                row.pass_containing_height(height);
+                row.dimensions.content.height =
+                    layout_rows(&mut row.children, area, column_width, viewport);
+            }
+            Some(Display::TableRow) => {
+                row.dimensions = Dimensions {
+                    content: area,
+                    ..Default::default()
+                };
+                let height = row.specified_height(viewport);
+                row.pass_containing_height(height);
+                row.layout_cells(column_width, viewport);
+            }
+            _ => row.layout(
+                Dimensions {
+                    content: area,
+                    ..Default::default()
+                },
+                viewport,
+            ),
+        }
+        offset += row.dimensions.margin_box().height;
+    }
+    offset
+}
+
+impl<'a> LayoutBox<'a> {
+    // Place the cells of a row side by side, one per column, then make the row and all its
+    // cells as tall as the tallest cell, or as the row's own height if that is taller
+    fn layout_cells(&mut self, column_width: f32, viewport: Viewport) {
+        let row = self.dimensions.content;
+        let mut height = self.specified_height(viewport).unwrap_or(0.0);
+        for (i, cell) in self.children.iter_mut().enumerate() {
+            let x = row.x + i as f32 * column_width;
+            let column = Rect {
+                x,
+                width: column_width,
+                ..row
+            };
+            cell.calculate_shrink_to_fit_edges(column, viewport);
+            let d = cell.dimensions;
+            let edges = d.border_box().width - d.content.width;
+            let containing_block = Dimensions {
+                content: column,
+                ..Default::default()
+            };
+            cell.layout_with_width(containing_block, (column_width - edges).max(0.0), viewport);
+
+            let border_box = cell.dimensions.border_box();
+            cell.translate(x - border_box.x, row.y - border_box.y);
+            height = height.max(border_box.height);
+        }
+
+        for cell in &mut self.children {
+            let stretch = height - cell.dimensions.border_box().height;
+            cell.dimensions.content.height += stretch;
+        }
+        self.dimensions.content.height = height;
+    }
+}
+
+// The display of a table part, or None for anonymous and inline boxes
+fn table_display(layout_box: &LayoutBox) -> Option<Display> {
+    match layout_box.box_type {
+        BlockNode(style) => Some(style.display()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::fixtures::border_box;
+
+    #[test]
+    fn lays_a_two_by_two_table_out_in_a_grid() {
+        let html = r#"<table><tr><td id="a"></td><td id="b"></td></tr>
+            <tr><td id="c"></td><td id="d"></td></tr></table>"#;
+        let css = "table { width: 200px } td { padding: 0 } #a, #d { height: 10px } #b { height: 30px } #c { height: 20px }";
+        let boxes = ["#a", "#b", "#c", "#d"].map(|selector| border_box(html, css, selector));
+        let positions = boxes.map(|rect| (rect.x, rect.y, rect.width, rect.height));
+        // Each cell is stretched to the height of the tallest in its row
+        assert_eq!(
+            positions,
+            [
+                (0.0, 0.0, 100.0, 30.0),
+                (100.0, 0.0, 100.0, 30.0),
+                (0.0, 30.0, 100.0, 20.0),
+                (100.0, 30.0, 100.0, 20.0)
+            ]
+        );
+    }
+}
@@ -0,0 +1,84 @@
+// Vertical writing modes: blocks stacked from right to left.
+// In `vertical-rl` the block axis runs from right to left and the inline axis from top to
+// bottom, so a block's width is its block size and its height its inline size. Only the
+// placement of block boxes follows this: their content is still laid out horizontally.
+
+use super::{BlockNode, Dimensions, LayoutBox, Rect};
+use crate::css::Viewport;
+use crate::style::Position;
+
+impl<'a> LayoutBox<'a> {
+    // Lay out the children of a `vertical-rl` block in columns, starting at the right edge
+    // of its content area. Like blocks stretching across a horizontal container, a child
+    // without a height fills the container's height, if it has one. Otherwise the container
+    // is as tall as its tallest child.
+    pub fn layout_vertical_children(&mut self, viewport: Viewport) {
+        let container = self.dimensions.content;
+        let inline_size = self.specified_height(viewport);
+        let containing_block = Dimensions {
+            content: Rect {
+                height: 0.0,
+                ..container
+            },
+            ..Default::default()
+        };
+
+        // How far the columns placed so far reach from the right edge
+        let mut block_offset = 0.0;
+        let mut inline_extent: f32 = 0.0;
+        for child in &mut self.children {
+            let right = container.x + container.width - block_offset;
+            if child.position() == Position::Absolute {
+                // Laid out later by its containing block, from where it would have been
+                child.dimensions.content.x = right;
+                child.dimensions.content.y = container.y;
+                continue;
+            }
+
+            // A column is as wide as its content, unless it has a width
+            child.calculate_shrink_to_fit_edges(container, viewport);
+            let d = child.dimensions;
+            let edges = d.margin_box().width - d.content.width;
+            let width = child
+                .specified_width(container, viewport)
+                .unwrap_or_else(|| child.max_content_width().min(container.width - edges))
+                .max(0.0);
+            child.layout_with_width(containing_block, width, viewport);
+
+            let auto_height = match child.box_type {
+                BlockNode(_) => child.specified_height(viewport).is_none(),
+                _ => true,
+            };
+            if let (Some(inline_size), true) = (inline_size, auto_height) {
+                let d = &mut child.dimensions;
+                let edges = d.margin_box().height - d.content.height;
+                d.content.height = (inline_size - edges).max(0.0);
+            }
+
+            let margin_box = child.dimensions.margin_box();
+            child.translate(
+                right - margin_box.width - margin_box.x,
+                container.y - margin_box.y,
+            );
+            block_offset += margin_box.width;
+            inline_extent = inline_extent.max(margin_box.height);
+        }
+
+        self.dimensions.content.height = inline_extent;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::fixtures::border_box;
+
+    #[test]
+    fn stacks_blocks_from_the_right_edge() {
+        let html = r#"<div id="container"><div id="a"></div><div id="b"></div></div>"#;
+        let css = "#container { writing-mode: vertical-rl; width: 100px; height: 50px }
+            #a { width: 20px } #b { width: 30px }";
+        let (a, b) = (border_box(html, css, "#a"), border_box(html, css, "#b"));
+        assert_eq!((a.x, a.y, a.height), (80.0, 0.0, 50.0));
+        assert_eq!((b.x, b.y, b.height), (50.0, 0.0, 50.0));
+    }
+}
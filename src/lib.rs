@@ -0,0 +1,66 @@
+// A toy browser rendering engine: HTML and CSS in, pixels out.
+
+extern crate image;
+
+pub mod css;
+pub mod dom;
+pub mod html;
+pub mod layout;
+pub mod log;
+pub mod painting;
+pub mod style;
+pub mod testing;
+pub mod text;
+
+// Style and lay out a parsed document. The DOM is only borrowed, so a document can be parsed
+// once and restyled every time its stylesheets change. The layout tree borrows the style
+// tree built here, so it is handed to `f` instead of being returned.
+pub fn restyle_and_layout<T, F>(
+    root: &dom::Node,
+    stylesheets: &[&css::StyleSheet],
+    viewport: layout::Dimensions,
+    f: F,
+) -> T
+where
+    F: FnOnce(&layout::LayoutBox) -> T,
+{
+    let media_viewport = css::Viewport {
+        width: viewport.content.width,
+        height: viewport.content.height,
+    };
+    let style_root = style::style_tree_pruned(root, stylesheets, media_viewport);
+    let layout_root = layout::layout_tree(&style_root, viewport);
+    f(&layout_root)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::fixtures::viewport;
+
+    #[test]
+    fn restyles_the_same_document_with_different_stylesheets() {
+        let root = html::parse("<div><p></p></div>".to_string()).unwrap();
+        let height = |css: &str| {
+            let stylesheet = css::parse(css.to_string()).unwrap();
+            restyle_and_layout(&root, &[&stylesheet], viewport(800.0, 600.0), |layout| {
+                layout.dimensions.content.height
+            })
+        };
+        assert_eq!(height("div, p { display: block } p { height: 10px }"), 10.0);
+        assert_eq!(height("div, p { display: block } p { height: 30px }"), 30.0);
+    }
+
+    #[test]
+    fn lays_out_an_empty_page_when_the_root_is_hidden() {
+        let root = html::parse("<div><p></p></div>".to_string()).unwrap();
+        for css in ["div { display: none }", "* { display: none }"] {
+            let stylesheet = css::parse(css.to_string()).unwrap();
+            let (children, height) =
+                restyle_and_layout(&root, &[&stylesheet], viewport(800.0, 600.0), |layout| {
+                    (layout.children.len(), layout.dimensions.content.height)
+                });
+            assert_eq!((children, height), (0, 0.0), "{}", css);
+        }
+    }
+}
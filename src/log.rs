@@ -0,0 +1,53 @@
+// Diagnostics printed to stderr while rendering.
+// Warnings report input that couldn't be used, like images that fail to load, and are shown
+// unless output is quiet. Notes explain how the input was interpreted, like skipped at-rules
+// and unavailable fonts, and are only shown when output is verbose. Each message is only
+// printed once.
+
+use std::collections::BTreeSet;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::Mutex;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verbosity {
+    Quiet,
+    Normal,
+    Verbose,
+}
+
+static VERBOSITY: AtomicU8 = AtomicU8::new(Verbosity::Normal as u8);
+
+// The messages printed so far. The same message tends to come up over and over, like a
+// missing font for every element that asks for it, but is only printed once.
+static PRINTED: Mutex<BTreeSet<String>> = Mutex::new(BTreeSet::new());
+
+pub fn set_verbosity(verbosity: Verbosity) {
+    VERBOSITY.store(verbosity as u8, Ordering::Relaxed);
+}
+
+pub fn verbosity() -> Verbosity {
+    match VERBOSITY.load(Ordering::Relaxed) {
+        0 => Verbosity::Quiet,
+        1 => Verbosity::Normal,
+        _ => Verbosity::Verbose,
+    }
+}
+
+pub fn warn(message: &str) {
+    if verbosity() >= Verbosity::Normal {
+        print_once("warning", message);
+    }
+}
+
+pub fn note(message: &str) {
+    if verbosity() == Verbosity::Verbose {
+        print_once("note", message);
+    }
+}
+
+fn print_once(kind: &str, message: &str) {
+    let line = format!("{}: {}", kind, message);
+    if PRINTED.lock().unwrap().insert(line.clone()) {
+        eprintln!("{}", line);
+    }
+}
@@ -2,33 +2,111 @@ extern crate getopts;
 extern crate image;
 
 use std::default::Default;
-use std::fs::File;
+use std::fs::{self, File};
 use std::io::Read;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 
-use image::{Rgba, RgbaImage};
-
-pub mod css;
-pub mod dom;
-pub mod html;
-pub mod layout;
-pub mod painting;
-pub mod style;
+use browser_engine::log::{self, Verbosity};
+use browser_engine::{css, html, layout, painting, style, testing, text};
 
 fn main() {
     // Parse command-line options;
     let mut opts = getopts::Options::new();
 
     opts.optopt("h", "html", "HTML document", "FILENAME");
-    opts.optopt("c", "css", "CSS stylesheet", "FILENAME");
+    opts.optmulti("c", "css", "CSS stylesheet (may be repeated)", "FILENAME");
     opts.optopt("o", "output", "Output file", "FILENAME");
     opts.optopt("f", "output", "Output file format", "png | pdf");
+    opts.optflag(
+        "",
+        "no-default-style",
+        "Don't apply the built-in user agent stylesheet",
+    );
+    opts.optflag(
+        "",
+        "dump-layout",
+        "Print the layout tree as JSON instead of rendering",
+    );
+    opts.optflag(
+        "",
+        "dump-style",
+        "Print the specified values of every element instead of rendering",
+    );
+    opts.optflag("", "timing", "Report how long each phase takes on stderr");
+    opts.optflag("q", "quiet", "Only report errors");
+    opts.optflag(
+        "v",
+        "verbose",
+        "Also report skipped rules, unknown properties and unavailable fonts on stderr",
+    );
+    opts.optflag(
+        "",
+        "list-fonts",
+        "Print the family and file of every font that can be used, then exit",
+    );
+    // For CI: render the bundled reference document and compare it with the expected image
+    opts.optflag("", "selftest", "");
+    opts.optopt(
+        "",
+        "background",
+        "Page background color where nothing is painted (default #ffffff)",
+        "#COLOR",
+    );
+    opts.optopt(
+        "",
+        "max-depth",
+        &format!(
+            "Reject documents with elements nested more than N deep (default {}, at most {})",
+            html::DEFAULT_MAX_DEPTH,
+            html::MAX_SAFE_DEPTH
+        ),
+        "N",
+    );
+    opts.optopt(
+        "",
+        "font-dir",
+        "Directory of .ttf fonts to choose font families from",
+        "DIR",
+    );
 
     let matches = opts.parse(std::env::args().skip(1)).unwrap();
     let str_arg = |flag: &str, default: &str| -> String {
         matches.opt_str(flag).unwrap_or(default.to_string())
     };
 
+    if matches.opt_present("verbose") {
+        log::set_verbosity(Verbosity::Verbose);
+    } else if matches.opt_present("quiet") {
+        log::set_verbosity(Verbosity::Quiet);
+    }
+
+    if matches.opt_present("selftest") {
+        selftest(&str_arg("o", "output.png"));
+    }
+
+    let background =
+        css::Color::from_hex(&str_arg("background", "#ffffff")).unwrap_or_else(|err| {
+            eprintln!("--background: {}", err);
+            std::process::exit(1)
+        });
+
+    let max_depth = match matches.opt_str("max-depth") {
+        Some(depth) => depth.parse().unwrap_or_else(|_| {
+            eprintln!("--max-depth: {:?} isn't a number", depth);
+            std::process::exit(1)
+        }),
+        None => html::DEFAULT_MAX_DEPTH,
+    };
+    if max_depth > html::MAX_SAFE_DEPTH {
+        eprintln!(
+            "--max-depth: {} is deeper than styling and layout can handle (at most {})",
+            max_depth,
+            html::MAX_SAFE_DEPTH
+        );
+        std::process::exit(1)
+    }
+
     // Choose a format:
     let png = match &str_arg("f", "png")[..] {
         "png" => true,
@@ -36,9 +114,37 @@ fn main() {
         x => panic!("Unknown output format: {}", x),
     };
 
-    // Read input files;
-    let html = read_source(str_arg("h", "examples/test.html"));
-    let css = read_source(str_arg("c", "examples/test.css"));
+    // Read input files. The example stylesheet is only used with the example document.
+    let html_path = str_arg("h", "examples/test.html");
+    let mut css_paths = matches.opt_strs("c");
+    if css_paths.is_empty() && !matches.opt_present("h") {
+        css_paths.push("examples/test.css".to_string());
+    }
+
+    if let Some(dir) = matches.opt_str("font-dir") {
+        match text::load_font_dir(Path::new(&dir)) {
+            Ok(count) => log::note(&format!("loaded {} fonts from {}", count, dir)),
+            Err(err) => {
+                eprintln!("{}: {}", dir, err);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    if matches.opt_present("list-fonts") {
+        let fonts = text::loaded_fonts();
+        for font in fonts.into_iter().chain([text::Font::builtin()]) {
+            let file = match font.path {
+                Some(path) => path.display().to_string(),
+                None => "built-in".to_string(),
+            };
+            let face = if font.italic { "\titalic" } else { "" };
+            println!("{}\t{}{}", font.family, file, face);
+        }
+        return;
+    }
+
+    let html = read_bytes(&html_path);
 
     // Since we don't have an actual window, hard-code the viewport size
     let mut viewport: layout::Dimensions = Default::default();
@@ -46,10 +152,89 @@ fn main() {
     viewport.content.height = 600.0;
 
     // Parse and rendering
-    let root_node = html::parse(html);
-    let stylesheet = css::parse(css);
-    let style_root = style::style_tree(&root_node, &stylesheet);
-    let layout_root = layout::layout_tree(&style_root, viewport);
+    let mut timings = Timings::new(matches.opt_present("timing"));
+    let root_node = timings.time("html parse", || {
+        html::parse_bytes_with_max_depth(&html, max_depth)
+            .unwrap_or_else(|err| parse_error(&html_path, err))
+    });
+
+    // Author styles come from the command line, then `<link>`ed files, then `<style>` blocks.
+    // Each source is parsed on its own, so errors point into the right file. The files a
+    // source `@import`s are found relative to its own directory, where a file is known by
+    // its file name.
+    let base_dir = Path::new(&html_path).parent().unwrap_or(Path::new(""));
+    let file_name = |path: &Path| {
+        path.file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+    };
+    let mut css_sources: Vec<(String, String, PathBuf, Option<String>)> = css_paths
+        .into_iter()
+        .map(|path| {
+            let dir = Path::new(&path)
+                .parent()
+                .unwrap_or(Path::new(""))
+                .to_path_buf();
+            let url = file_name(Path::new(&path));
+            (read_source(&path), path, dir, url)
+        })
+        .collect();
+    // A broken link leaves the document unstyled by that sheet, as in browsers
+    for href in root_node.stylesheet_links() {
+        let path = base_dir.join(href);
+        match fs::read_to_string(&path) {
+            Ok(source) => {
+                let dir = path.parent().unwrap_or(Path::new("")).to_path_buf();
+                let url = file_name(&path);
+                css_sources.push((source, path.display().to_string(), dir, url));
+            }
+            Err(err) => log::warn(&format!(
+                "can't load stylesheet {}: {}",
+                path.display(),
+                err
+            )),
+        }
+    }
+    for source in root_node.style_elements() {
+        let name = format!("{} <style>", html_path);
+        css_sources.push((source, name, base_dir.to_path_buf(), None));
+    }
+
+    let (user_agent, stylesheet) = timings.time("css parse", || {
+        let mut stylesheet = css::StyleSheet { rules: Vec::new() };
+        for (source, name, dir, url) in css_sources {
+            let resolve = |url: &str| fs::read_to_string(dir.join(url)).ok();
+            let sheet = css::parse_with_imports(source, url.as_deref(), &resolve)
+                .unwrap_or_else(|err| parse_error(&name, err));
+            stylesheet.rules.extend(sheet.rules);
+        }
+        (css::user_agent_stylesheet(), stylesheet)
+    });
+    let stylesheets = if matches.opt_present("no-default-style") {
+        vec![&stylesheet]
+    } else {
+        vec![&user_agent, &stylesheet]
+    };
+    let media_viewport = css::Viewport {
+        width: viewport.content.width,
+        height: viewport.content.height,
+    };
+    let style_root = timings.time("style", || {
+        style::style_tree_pruned(&root_node, &stylesheets, media_viewport)
+    });
+
+    if matches.opt_present("dump-style") {
+        print!("{}", style_root.dump_style());
+        timings.report();
+        return;
+    }
+
+    let layout_root = timings.time("layout", || layout::layout_tree(&style_root, viewport));
+
+    if matches.opt_present("dump-layout") {
+        println!("{}", layout_root.to_json());
+        timings.report();
+        return;
+    }
 
     // Create the output file
     let filename = str_arg("o", if png { "output.png" } else { "output.pdf" });
@@ -58,30 +243,94 @@ fn main() {
     // Write to the file
     let ok = if png {
         // Assuming `layout_root` and `viewport` are defined and initialized
-        let canvas = painting::paint(&layout_root, viewport.content);
-        let (w, h) = (canvas.width as u32, canvas.height as u32);
-        let img = RgbaImage::from_fn(w, h, move |x, y| {
-            let color = canvas.pixels[(y * w + x) as usize];
-            Rgba([color.r, color.g, color.b, color.a])
+        let canvas = timings.time("paint", || {
+            painting::paint(&layout_root, viewport.content, background)
         });
-
-        // Make sure the file is correctly initialized
-        let mut path = Path::new("output.png");
-        image::DynamicImage::ImageRgba8(img)
-            .save_with_format(&mut path, image::ImageFormat::Png)
-            .is_ok()
+        canvas.save_png(Path::new(&filename)).is_ok()
     } else {
         false
     };
 
     if ok {
-        println!("Save output as {}", filename)
+        if log::verbosity() > Verbosity::Quiet {
+            println!("Save output as {}", filename)
+        }
     } else {
         println!("Error saving out as {}", filename)
     }
+    timings.report();
+}
+
+// How long each phase of the pipeline took, when `--timing` is given
+struct Timings {
+    enabled: bool,
+    phases: Vec<(&'static str, Duration)>,
+}
+
+impl Timings {
+    fn new(enabled: bool) -> Timings {
+        Timings {
+            enabled,
+            phases: Vec::new(),
+        }
+    }
+
+    // Run one phase, recording its duration
+    fn time<T, F: FnOnce() -> T>(&mut self, phase: &'static str, f: F) -> T {
+        let start = Instant::now();
+        let result = f();
+        self.phases.push((phase, start.elapsed()));
+        result
+    }
+
+    // Print the durations to stderr
+    fn report(&self) {
+        if !self.enabled {
+            return;
+        }
+        for (phase, duration) in &self.phases {
+            eprintln!("{:>12}: {:.3} ms", phase, duration.as_secs_f64() * 1000.0);
+        }
+        let total: Duration = self.phases.iter().map(|(_, duration)| *duration).sum();
+        eprintln!("{:>12}: {:.3} ms", "total", total.as_secs_f64() * 1000.0);
+    }
+}
+
+// Run the self-test and exit, with status 1 if the rendering doesn't match. The rendering is
+// saved to `filename` on a mismatch, to look at or to replace the expected image with.
+fn selftest(filename: &str) -> ! {
+    let (image, difference) = testing::selftest();
+    if difference == 0.0 {
+        if log::verbosity() > Verbosity::Quiet {
+            println!("selftest passed");
+        }
+        std::process::exit(0);
+    }
+
+    eprintln!("selftest failed: rendering differs by {:.4}", difference);
+    match image.save_with_format(filename, image::ImageFormat::Png) {
+        Ok(()) => eprintln!("saved the rendering as {}", filename),
+        Err(err) => eprintln!("can't save the rendering as {}: {}", filename, err),
+    }
+    std::process::exit(1)
+}
+
+// Report a syntax error in an input file and exit
+fn parse_error<E: std::fmt::Display>(filename: &str, err: E) -> ! {
+    eprintln!("{}:{}", filename, err);
+    std::process::exit(1)
+}
+
+fn read_bytes<P: AsRef<Path>>(filename: P) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    File::open(filename)
+        .unwrap()
+        .read_to_end(&mut bytes)
+        .unwrap();
+    bytes
 }
 
-fn read_source(filename: String) -> String {
+fn read_source<P: AsRef<Path>>(filename: P) -> String {
     let mut str = String::new();
     File::open(filename)
         .unwrap()
@@ -1,54 +1,633 @@
+use std::io;
+use std::path::Path;
+use std::rc::Rc;
+
+use image::RgbaImage;
+
 use crate::{
-    css::{Color, Value},
-    layout::{BoxType, LayoutBox, Rect},
+    css::{Color, TransformFunction, Value},
+    dom::NodeType,
+    layout::{BoxType, LayoutBox, Rect, Replaced},
+    log,
+    style::{Display, EmptyCells, ListStyleType, ObjectFit, Visibility},
 };
 
 pub type DisplayList = Vec<DisplayCommand>;
 
-#[derive()]
+// The size of a list item's marker, as a multiple of the font size
+const MARKER_SIZE: f32 = 0.35;
+
+// The color of the box drawn in place of an image that can't be loaded
+const PLACEHOLDER_COLOR: Color = Color {
+    r: 221,
+    g: 221,
+    b: 221,
+    a: 255,
+};
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum DisplayCommand {
     SolidColor(Color, Rect),
+    // Paint the commands into a separate layer, then blend it in at the given opacity
+    Opacity(f32, DisplayList),
+    // A run of text in the given color, filling the rectangle
+    Text(Color, String, Rect),
+    // A top-to-bottom gradient through evenly spaced colors, filling the rectangle
+    LinearGradient(Vec<Color>, Rect),
+    // Paint the commands into a separate layer, then copy it in where it falls inside the
+    // rectangle with corners rounded to the given radius
+    RoundedClip(Rect, f32, DisplayList),
+    // An image stretched to fill the first rectangle, painted where it falls inside the
+    // second
+    Image(Rc<RgbaImage>, Rect, Rect),
+    // A shadow filling the rectangle with corners rounded to the given radius, its edges
+    // fading out over the given blur radius on either side
+    BoxShadow(Color, Rect, f32, f32),
+    // Paint the commands with their geometry mapped through the transform
+    Transform(Transform, DisplayList),
     // insert more command here
 }
 
+// A 2D affine transform, mapping a point (x, y) to (a x + c y + e, b x + d y + f)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Transform {
+    pub a: f32,
+    pub b: f32,
+    pub c: f32,
+    pub d: f32,
+    pub e: f32,
+    pub f: f32,
+}
+
+impl Transform {
+    pub fn translate(x: f32, y: f32) -> Transform {
+        Transform {
+            a: 1.0,
+            b: 0.0,
+            c: 0.0,
+            d: 1.0,
+            e: x,
+            f: y,
+        }
+    }
+
+    pub fn scale(x: f32, y: f32) -> Transform {
+        Transform {
+            a: x,
+            b: 0.0,
+            c: 0.0,
+            d: y,
+            e: 0.0,
+            f: 0.0,
+        }
+    }
+
+    // A clockwise rotation by an angle in radians. The y axis points down, so this turns it
+    // towards the negative x axis.
+    pub fn rotate(angle: f32) -> Transform {
+        let (sin, cos) = angle.sin_cos();
+        Transform {
+            a: cos,
+            b: sin,
+            c: -sin,
+            d: cos,
+            e: 0.0,
+            f: 0.0,
+        }
+    }
+
+    // The transform that applies `other` first, then this one
+    pub fn compose(self, other: Transform) -> Transform {
+        Transform {
+            a: self.a * other.a + self.c * other.b,
+            b: self.b * other.a + self.d * other.b,
+            c: self.a * other.c + self.c * other.d,
+            d: self.b * other.c + self.d * other.d,
+            e: self.a * other.e + self.c * other.f + self.e,
+            f: self.b * other.e + self.d * other.f + self.f,
+        }
+    }
+
+    pub fn apply(self, x: f32, y: f32) -> (f32, f32) {
+        (
+            self.a * x + self.c * y + self.e,
+            self.b * x + self.d * y + self.f,
+        )
+    }
+
+    // The smallest rectangle holding `rect` once it is transformed. Without a rotation or
+    // skew, that is exactly where `rect` goes.
+    pub fn apply_rect(self, rect: Rect) -> Rect {
+        let corners = [
+            self.apply(rect.x, rect.y),
+            self.apply(rect.x + rect.width, rect.y),
+            self.apply(rect.x, rect.y + rect.height),
+            self.apply(rect.x + rect.width, rect.y + rect.height),
+        ];
+        let (x0, y0) = corners
+            .iter()
+            .fold((f32::INFINITY, f32::INFINITY), |(x, y), &(cx, cy)| {
+                (x.min(cx), y.min(cy))
+            });
+        let (x1, y1) = corners.iter().fold(
+            (f32::NEG_INFINITY, f32::NEG_INFINITY),
+            |(x, y), &(cx, cy)| (x.max(cx), y.max(cy)),
+        );
+        Rect {
+            x: x0,
+            y: y0,
+            width: x1 - x0,
+            height: y1 - y0,
+        }
+    }
+
+    // Does the transform only move and stretch, without turning or mirroring? Rectangles
+    // then stay rectangles, the right way up.
+    pub fn keeps_orientation(self) -> bool {
+        self.b == 0.0 && self.c == 0.0 && self.a > 0.0 && self.d > 0.0
+    }
+
+    // The transform that undoes this one, or None if it flattens the plane onto a line
+    pub fn inverse(self) -> Option<Transform> {
+        let det = self.a * self.d - self.b * self.c;
+        if det == 0.0 {
+            return None;
+        }
+        Some(Transform {
+            a: self.d / det,
+            b: -self.b / det,
+            c: -self.c / det,
+            d: self.a / det,
+            e: (self.c * self.f - self.d * self.e) / det,
+            f: (self.b * self.e - self.a * self.f) / det,
+        })
+    }
+
+    // How much the transform scales lengths like a corner radius, on average over both axes
+    pub fn length_scale(self) -> f32 {
+        (self.a * self.d - self.b * self.c).abs().sqrt()
+    }
+}
+
+impl DisplayCommand {
+    // The command with its geometry mapped through `transform`, which must keep the command's
+    // orientation
+    fn transformed(&self, transform: Transform) -> DisplayCommand {
+        let rect = |rect| transform.apply_rect(rect);
+        let length = |length| length * transform.length_scale();
+        let items = |items: &DisplayList| {
+            items
+                .iter()
+                .map(|item| item.transformed(transform))
+                .collect()
+        };
+        match *self {
+            DisplayCommand::SolidColor(color, r) => DisplayCommand::SolidColor(color, rect(r)),
+            DisplayCommand::Opacity(opacity, ref list) => {
+                DisplayCommand::Opacity(opacity, items(list))
+            }
+            DisplayCommand::Text(color, ref text, r) => {
+                DisplayCommand::Text(color, text.clone(), rect(r))
+            }
+            DisplayCommand::LinearGradient(ref stops, r) => {
+                DisplayCommand::LinearGradient(stops.clone(), rect(r))
+            }
+            DisplayCommand::RoundedClip(r, radius, ref list) => {
+                DisplayCommand::RoundedClip(rect(r), length(radius), items(list))
+            }
+            DisplayCommand::Image(ref image, r, clip) => {
+                DisplayCommand::Image(image.clone(), rect(r), rect(clip))
+            }
+            DisplayCommand::BoxShadow(color, r, radius, blur) => {
+                DisplayCommand::BoxShadow(color, rect(r), length(radius), length(blur))
+            }
+            // Nested transforms apply the inner one first
+            DisplayCommand::Transform(inner, ref list) => {
+                DisplayCommand::Transform(transform.compose(inner), list.clone())
+            }
+        }
+    }
+}
+
+// Build the ordered list of draw commands for a layout tree, without rasterizing it
 pub fn build_display_list(layout_root: &LayoutBox) -> DisplayList {
     let mut list = Vec::new();
 
-    render_layout_box(&mut list, layout_root);
+    render_stacking_context(&mut list, layout_root);
 
     list
 }
 
-pub fn render_layout_box(list: &mut DisplayList, layout_box: &LayoutBox) {
-    render_background(list, layout_box);
-    render_borders(list, layout_box);
-    // TODO: render text
+// Paint a box that starts a stacking context, which its descendants are painted within:
+// first the box's own background and borders, then any positioned descendants with a
+// negative `z-index`, then the rest of its descendants in tree order, and finally the
+// positioned descendants with a `z-index` of zero or more, from lowest to highest.
+fn render_stacking_context(list: &mut DisplayList, layout_box: &LayoutBox) {
+    let opacity = get_opacity(layout_box);
+    if opacity <= 0.0 {
+        // A fully transparent group can't change any pixels
+        return;
+    }
+
+    let mut group = Vec::new();
+    let mut layers = Vec::new();
+    render_box(&mut group, layout_box);
 
+    let mut content = Vec::new();
     for child in &layout_box.children {
-        render_layout_box(list, child);
+        render_layout_box(&mut content, child, &mut layers);
+    }
+
+    // Sorting is stable, so layers with the same `z-index` keep their tree order
+    layers.sort_by_key(|&(z_index, _)| z_index);
+    let (negative, positive): (Vec<_>, Vec<_>) = layers.into_iter().partition(|&(z, _)| z < 0);
+
+    for (_, layer) in negative {
+        render_stacking_context(&mut group, layer);
+    }
+    group.extend(content);
+    for (_, layer) in positive {
+        render_stacking_context(&mut group, layer);
+    }
+
+    if opacity < 1.0 {
+        // The box and its descendants become transparent together, so overlapping
+        // descendants don't show through each other
+        group = vec![DisplayCommand::Opacity(opacity, group)];
+    }
+
+    // Transforms only move the painting, the box keeps its place in the layout
+    match get_transform(layout_box) {
+        Some(transform) => list.push(DisplayCommand::Transform(transform, group)),
+        None => list.extend(group),
+    }
+}
+
+// Paint a box and its descendants in tree order. Positioned boxes with a `z-index` are
+// collected in `layers` instead, to be painted in order by their stacking context.
+pub fn render_layout_box<'a>(
+    list: &mut DisplayList,
+    layout_box: &'a LayoutBox<'a>,
+    layers: &mut Vec<(i32, &'a LayoutBox<'a>)>,
+) {
+    if let Some(z_index) = get_z_index(layout_box) {
+        layers.push((z_index, layout_box));
+    } else if get_opacity(layout_box) < 1.0 || get_transform(layout_box).is_some() {
+        // Transparent and transformed boxes are painted as a group, so they form a stacking
+        // context
+        render_stacking_context(list, layout_box);
+    } else {
+        render_box(list, layout_box);
+        for child in &layout_box.children {
+            render_layout_box(list, child, layers);
+        }
+    }
+}
+
+fn render_box(list: &mut DisplayList, layout_box: &LayoutBox) {
+    // Hidden boxes still take up space, and their descendants may be visible again
+    if is_visible(layout_box) && !is_hidden_empty_cell(layout_box) {
+        let radius = get_border_radius(layout_box);
+        render_box_shadow(list, layout_box, radius);
+        if radius > 0.0 {
+            let mut rounded = Vec::new();
+            render_background(&mut rounded, layout_box);
+            render_borders(&mut rounded, layout_box);
+            list.push(DisplayCommand::RoundedClip(
+                layout_box.dimensions.border_box(),
+                radius,
+                rounded,
+            ));
+        } else {
+            render_background(list, layout_box);
+            render_borders(list, layout_box);
+        }
+        render_marker(list, layout_box);
+        render_text(list, layout_box);
+        render_outline(list, layout_box);
+    }
+}
+
+// Paint the shadow beneath the box: its border box moved by the offsets and grown by the
+// spread on every side, with the corners rounded like the box's
+fn render_box_shadow(list: &mut DisplayList, layout_box: &LayoutBox, radius: f32) {
+    let shadow = match layout_box.box_type {
+        BoxType::BlockNode(style) | BoxType::InlineNode(style) => match style.box_shadow() {
+            Some(shadow) => shadow,
+            None => return,
+        },
+        BoxType::AnonymousBlock => return,
+    };
+
+    let border_box = layout_box.dimensions.border_box();
+    let rect = Rect {
+        x: border_box.x + shadow.offset_x - shadow.spread,
+        y: border_box.y + shadow.offset_y - shadow.spread,
+        width: (border_box.width + 2.0 * shadow.spread).max(0.0),
+        height: (border_box.height + 2.0 * shadow.spread).max(0.0),
+    };
+    let radius = if radius > 0.0 {
+        (radius + shadow.spread).max(0.0)
+    } else {
+        0.0
+    };
+    list.push(DisplayCommand::BoxShadow(
+        shadow.color,
+        rect,
+        radius,
+        shadow.blur,
+    ));
+}
+
+// Paint a list item's marker, centered in the room left for it in front of the content and
+// on the first line
+fn render_marker(list: &mut DisplayList, layout_box: &LayoutBox) {
+    let style = match layout_box.box_type {
+        BoxType::BlockNode(style) => style,
+        _ => return,
+    };
+    let width = style.marker_width();
+    if width <= 0.0 {
+        return;
+    }
+
+    let content = layout_box.dimensions.content;
+    let size = style.font_size() * MARKER_SIZE;
+    let rect = Rect {
+        x: content.x - (width + size) / 2.0,
+        y: content.y + (style.line_height() - size) / 2.0,
+        width: size,
+        height: size,
+    };
+    let color = get_color(layout_box, "color").unwrap_or(Color {
+        r: 0,
+        g: 0,
+        b: 0,
+        a: 255,
+    });
+    match style.list_style_type() {
+        ListStyleType::Disc => list.push(DisplayCommand::RoundedClip(
+            rect,
+            size / 2.0,
+            vec![DisplayCommand::SolidColor(color, rect)],
+        )),
+        ListStyleType::Square => list.push(DisplayCommand::SolidColor(color, rect)),
+        ListStyleType::None => {}
+    }
+}
+
+// Paint the outline just outside the border box, over the box's own painting. It takes
+// no space in the layout.
+fn render_outline(list: &mut DisplayList, layout_box: &LayoutBox) {
+    let (width, color) = match layout_box.box_type {
+        BoxType::BlockNode(style) | BoxType::InlineNode(style) => match style.outline() {
+            Some(outline) => outline,
+            None => return,
+        },
+        BoxType::AnonymousBlock => return,
+    };
+
+    let border_box = layout_box.dimensions.border_box();
+    let outer = Rect {
+        x: border_box.x - width,
+        y: border_box.y - width,
+        width: border_box.width + 2.0 * width,
+        height: border_box.height + 2.0 * width,
+    };
+
+    // Top and bottom edges span the corners, the left and right edges fit between them
+    for y in [outer.y, border_box.y + border_box.height] {
+        list.push(DisplayCommand::SolidColor(
+            color,
+            Rect {
+                x: outer.x,
+                y,
+                width: outer.width,
+                height: width,
+            },
+        ));
+    }
+    for x in [outer.x, border_box.x + border_box.width] {
+        list.push(DisplayCommand::SolidColor(
+            color,
+            Rect {
+                x,
+                y: border_box.y,
+                width,
+                height: border_box.height,
+            },
+        ));
     }
 }
 
 fn render_background(list: &mut DisplayList, layout_box: &LayoutBox) {
-    get_color(layout_box, "background").map(|color| {
+    if let Some(color) = get_color(layout_box, "background-color") {
         list.push(DisplayCommand::SolidColor(
             color,
             layout_box.dimensions.border_box(),
         ))
-    });
+    }
+
+    if let Some(stops) = get_gradient(layout_box, "background-image") {
+        list.push(DisplayCommand::LinearGradient(
+            stops,
+            layout_box.dimensions.content,
+        ))
+    }
+
+    // The image is painted over the color, which shows through if it can't be loaded
+    if let Some(image) = get_background_image(layout_box) {
+        let content = layout_box.dimensions.content;
+        let fit = match layout_box.box_type {
+            BoxType::BlockNode(style) | BoxType::InlineNode(style) => style.object_fit(),
+            BoxType::AnonymousBlock => ObjectFit::Fill,
+        };
+        let rect = fit_image(fit, image.dimensions(), content);
+        list.push(DisplayCommand::Image(image, rect, content));
+    }
+
+    render_replaced(list, layout_box);
 }
 
-// Return the specified color for CSS Property `name`, None if no color was specificied
-fn get_color(layout_box: &LayoutBox, name: &str) -> Option<Color> {
+// Paint the content of a replaced box, like the image of an `<img>`, fitted into its content
+// area. An image that couldn't be loaded leaves a placeholder.
+fn render_replaced(list: &mut DisplayList, layout_box: &LayoutBox) {
+    let content = layout_box.dimensions.content;
+    match layout_box.replaced {
+        Some(Replaced::Image(ref image)) => {
+            let fit = match layout_box.box_type {
+                BoxType::BlockNode(style) | BoxType::InlineNode(style) => style.object_fit(),
+                BoxType::AnonymousBlock => ObjectFit::Fill,
+            };
+            let rect = fit_image(fit, image.dimensions(), content);
+            list.push(DisplayCommand::Image(image.clone(), rect, content));
+        }
+        Some(Replaced::BrokenImage) => {
+            list.push(DisplayCommand::SolidColor(PLACEHOLDER_COLOR, content))
+        }
+        None => {}
+    }
+}
+
+// Where an image of the given size goes to fit into `area`. It is stretched to fill the area,
+// or scaled evenly and centered: to fit inside it with `contain`, or to cover all of it with
+// `cover`, which leaves the parts outside the area to be clipped.
+fn fit_image(fit: ObjectFit, (width, height): (u32, u32), area: Rect) -> Rect {
+    let scale_x = area.width / width as f32;
+    let scale_y = area.height / height as f32;
+    let scale = match fit {
+        ObjectFit::Fill => return area,
+        ObjectFit::Contain => scale_x.min(scale_y),
+        ObjectFit::Cover => scale_x.max(scale_y),
+    };
+    let (fit_width, fit_height) = (width as f32 * scale, height as f32 * scale);
+    Rect {
+        x: area.x + (area.width - fit_width) / 2.0,
+        y: area.y + (area.height - fit_height) / 2.0,
+        width: fit_width,
+        height: fit_height,
+    }
+}
+
+fn get_gradient(layout_box: &LayoutBox, name: &str) -> Option<Vec<Color>> {
     match layout_box.box_type {
         BoxType::BlockNode(style) | BoxType::InlineNode(style) => match style.value(name) {
-            Some(Value::ColorValue(color)) => Some(color),
+            Some(Value::LinearGradient { stops }) => Some(stops),
             _ => None,
         },
         BoxType::AnonymousBlock => None,
     }
 }
 
+fn get_background_image(layout_box: &LayoutBox) -> Option<Rc<RgbaImage>> {
+    match layout_box.box_type {
+        BoxType::BlockNode(style) | BoxType::InlineNode(style) => {
+            match style.value("background-image") {
+                Some(Value::Url(path)) => load_image(&path),
+                _ => None,
+            }
+        }
+        BoxType::AnonymousBlock => None,
+    }
+}
+
+// Load an image, relative to the working directory. Images that can't be loaded are skipped
+// with a warning.
+pub fn load_image(path: &str) -> Option<Rc<RgbaImage>> {
+    match image::open(path) {
+        Ok(image) if image.width() > 0 && image.height() > 0 => Some(Rc::new(image.to_rgba8())),
+        Ok(_) => None,
+        Err(err) => {
+            log::warn(&format!("can't load image {}: {}", path, err));
+            None
+        }
+    }
+}
+
+fn render_text(list: &mut DisplayList, layout_box: &LayoutBox) {
+    if layout_box.fragments.is_empty() {
+        return;
+    }
+
+    // Text is black unless a `color` is specified
+    let color = get_color(layout_box, "color").unwrap_or(Color {
+        r: 0,
+        g: 0,
+        b: 0,
+        a: 255,
+    });
+    for fragment in &layout_box.fragments {
+        list.push(DisplayCommand::Text(
+            color,
+            fragment.text.clone(),
+            fragment.rect,
+        ));
+    }
+}
+
+fn get_opacity(layout_box: &LayoutBox) -> f32 {
+    match layout_box.box_type {
+        BoxType::BlockNode(style) | BoxType::InlineNode(style) => style.opacity(),
+        BoxType::AnonymousBlock => 1.0,
+    }
+}
+
+// The transform of a box's painting, or None if it has none. The functions apply around the
+// `transform-origin`, as if the origin were there.
+fn get_transform(layout_box: &LayoutBox) -> Option<Transform> {
+    let functions = match layout_box.box_type {
+        BoxType::BlockNode(style) | BoxType::InlineNode(style) => style.transform(),
+        BoxType::AnonymousBlock => return None,
+    };
+    if functions.is_empty() {
+        return None;
+    }
+
+    let border_box = layout_box.dimensions.border_box();
+    let (x, y) = match layout_box.box_type {
+        BoxType::BlockNode(style) | BoxType::InlineNode(style) => {
+            style.transform_origin(border_box.width, border_box.height)
+        }
+        BoxType::AnonymousBlock => (0.0, 0.0),
+    };
+    let (x, y) = (border_box.x + x, border_box.y + y);
+    let transform = functions
+        .into_iter()
+        .map(|function| match function {
+            TransformFunction::Translate(x, y) => Transform::translate(x, y),
+            TransformFunction::Scale(x, y) => Transform::scale(x, y),
+            TransformFunction::Rotate(angle) => Transform::rotate(angle),
+        })
+        .fold(Transform::translate(x, y), Transform::compose);
+    Some(transform.compose(Transform::translate(-x, -y)))
+}
+
+fn get_z_index(layout_box: &LayoutBox) -> Option<i32> {
+    match layout_box.box_type {
+        BoxType::BlockNode(style) | BoxType::InlineNode(style) => style.z_index(),
+        BoxType::AnonymousBlock => None,
+    }
+}
+
+fn get_border_radius(layout_box: &LayoutBox) -> f32 {
+    match layout_box.box_type {
+        BoxType::BlockNode(style) | BoxType::InlineNode(style) => style.border_radius(),
+        BoxType::AnonymousBlock => 0.0,
+    }
+}
+
+fn is_visible(layout_box: &LayoutBox) -> bool {
+    match layout_box.box_type {
+        BoxType::BlockNode(style) | BoxType::InlineNode(style) => {
+            style.visibility() == Visibility::Visible
+        }
+        BoxType::AnonymousBlock => true,
+    }
+}
+
+// Is this a table cell without content, whose background and borders `empty-cells: hide`
+// leaves out? Text that is only whitespace doesn't count as content.
+fn is_hidden_empty_cell(layout_box: &LayoutBox) -> bool {
+    match layout_box.box_type {
+        BoxType::BlockNode(style) => {
+            style.display() == Display::TableCell
+                && style.empty_cells() == EmptyCells::Hide
+                && style.children.iter().all(|child| {
+                    matches!(child.node.node_type, NodeType::Text(ref text) if text.trim().is_empty())
+                })
+        }
+        _ => false,
+    }
+}
+
+// Return the specified color for CSS Property `name`, None if no color was specificied
+fn get_color(layout_box: &LayoutBox, name: &str) -> Option<Color> {
+    match layout_box.box_type {
+        BoxType::BlockNode(style) | BoxType::InlineNode(style) => style.computed_color(name),
+        BoxType::AnonymousBlock => None,
+    }
+}
+
 fn render_borders(list: &mut DisplayList, layout_box: &LayoutBox) {
     let color = match get_color(layout_box, "border-color") {
         Some(color) => color,
@@ -73,10 +652,10 @@ fn render_borders(list: &mut DisplayList, layout_box: &LayoutBox) {
     list.push(DisplayCommand::SolidColor(
         color,
         Rect {
-            x: border_box.x,
+            x: border_box.x + border_box.width - d.border.right,
             y: border_box.y,
-            width: border_box.width,
-            height: d.border.top,
+            width: d.border.right,
+            height: border_box.height,
         },
     ));
 
@@ -109,14 +688,41 @@ pub struct Canvas {
     pub height: usize,
 }
 
-pub fn paint(layout_root: &LayoutBox, bounds: Rect) -> Canvas {
+// Paint a layout tree onto a canvas the size of `bounds`. The canvas is first filled with
+// the background of the root element, or of the `<body>` if the root has none, like the
+// page background in browsers, and with `background` if neither has one.
+pub fn paint(layout_root: &LayoutBox, bounds: Rect, background: Color) -> Canvas {
+    let mut canvas = Canvas::filled(0, 0, background);
+    paint_into(&mut canvas, layout_root, bounds, background);
+    canvas
+}
+
+// Paint a layout tree like `paint`, but onto an existing canvas, so that rendering many frames
+// doesn't allocate a new one each time. The canvas is cleared and resized to `bounds`, and
+// keeps its buffer when it is already large enough.
+pub fn paint_into(canvas: &mut Canvas, layout_root: &LayoutBox, bounds: Rect, background: Color) {
     let display_list = build_display_list(layout_root);
-    let mut canvas = Canvas::new(bounds.width as usize, bounds.height as usize);
+    let background = canvas_background(layout_root).unwrap_or(background);
+    canvas.clear(bounds.width as usize, bounds.height as usize, background);
 
     for item in display_list {
         canvas.paint_item(&item)
     }
-    canvas
+}
+
+fn canvas_background(layout_root: &LayoutBox) -> Option<Color> {
+    get_color(layout_root, "background-color").or_else(|| {
+        let body = layout_root
+            .children
+            .iter()
+            .find(|child| match child.box_type {
+                BoxType::BlockNode(style) | BoxType::InlineNode(style) => {
+                    style.tag_name() == Some("body")
+                }
+                BoxType::AnonymousBlock => false,
+            })?;
+        get_color(body, "background-color")
+    })
 }
 
 impl Canvas {
@@ -129,23 +735,48 @@ impl Canvas {
             a: 255,
         };
 
+        Canvas::filled(width, height, white)
+    }
+
+    // The pixels as an image, to encode or compare
+    pub fn to_rgba_image(&self) -> RgbaImage {
+        let (w, h) = (self.width as u32, self.height as u32);
+        RgbaImage::from_fn(w, h, |x, y| {
+            let color = self.pixels[(y * w + x) as usize];
+            image::Rgba([color.r, color.g, color.b, color.a])
+        })
+    }
+
+    // Encode the pixels as a PNG file
+    pub fn save_png(&self, path: &Path) -> io::Result<()> {
+        self.to_rgba_image()
+            .save_with_format(path, image::ImageFormat::Png)
+            .map_err(|err| match err {
+                image::ImageError::IoError(err) => err,
+                err => io::Error::other(err),
+            })
+    }
+
+    fn filled(width: usize, height: usize, color: Color) -> Canvas {
         Canvas {
-            pixels: vec![white; width * height],
-            width: width,
-            height: height,
+            pixels: vec![color; width * height],
+            width,
+            height,
         }
     }
 
+    // Resize the canvas and fill it with one color, reusing its pixel buffer
+    pub fn clear(&mut self, width: usize, height: usize, color: Color) {
+        self.pixels.clear();
+        self.pixels.resize(width * height, color);
+        self.width = width;
+        self.height = height;
+    }
+
     pub fn paint_item(&mut self, item: &DisplayCommand) {
         match *item {
             DisplayCommand::SolidColor(color, rect) => {
-                // Clip the rectangle to the canvas boundaries
-                let x0 = rect.x.clamp(0.0, self.width as f32) as usize;
-                let y0 = rect.y.clamp(0.0, self.height as f32) as usize;
-
-                let x1 = (rect.y + rect.width).clamp(0.0, self.width as f32) as usize;
-                let y1 = (rect.y + rect.height).clamp(0.0, self.height as f32) as usize;
-
+                let (x0, y0, x1, y1) = self.clip(rect);
                 for y in y0..y1 {
                     for x in x0..x1 {
                         // TODO: alpha compositing with existing pixel
@@ -153,16 +784,580 @@ impl Canvas {
                     }
                 }
             }
+            DisplayCommand::Opacity(opacity, ref items) => {
+                // Paint the group onto a transparent layer of the same size, then blend it in
+                let mut layer = Canvas::filled(self.width, self.height, Color::default());
+                for item in items {
+                    layer.paint_item(item);
+                }
+
+                for (dst, src) in self.pixels.iter_mut().zip(&layer.pixels) {
+                    *dst = blend(*dst, *src, opacity);
+                }
+            }
+            DisplayCommand::Text(..) => {
+                // TODO: rasterize glyphs once there is a font to draw them with
+            }
+            DisplayCommand::RoundedClip(rect, radius, ref items) => {
+                let mut layer = Canvas::filled(self.width, self.height, Color::default());
+                for item in items {
+                    layer.paint_item(item);
+                }
+
+                let (x0, y0, x1, y1) = self.clip(rect);
+                for y in y0..y1 {
+                    for x in x0..x1 {
+                        if in_rounded_rect(x as f32 + 0.5, y as f32 + 0.5, rect, radius) {
+                            let i = y * self.width + x;
+                            self.pixels[i] = blend(self.pixels[i], layer.pixels[i], 1.0);
+                        }
+                    }
+                }
+            }
+            DisplayCommand::LinearGradient(ref stops, rect) => {
+                let (x0, y0, x1, y1) = self.clip(rect);
+                for y in y0..y1 {
+                    // Interpolate at the center of each row
+                    let t = ((y as f32 + 0.5 - rect.y) / rect.height).clamp(0.0, 1.0);
+                    let color = gradient_color(stops, t);
+                    for x in x0..x1 {
+                        let dst = &mut self.pixels[y * self.width + x];
+                        *dst = blend(*dst, color, 1.0);
+                    }
+                }
+            }
+            DisplayCommand::Image(ref image, rect, clip) => {
+                // Only the part of the image inside both rectangles is painted
+                let visible = rect.intersect(clip).unwrap_or_default();
+                let (x0, y0, x1, y1) = self.clip(visible);
+                let (width, height) = image.dimensions();
+                // Sample the nearest source pixel to the center of each canvas pixel
+                let sample = |pos: usize, start: f32, extent: f32, size: u32| {
+                    let offset = (pos as f32 + 0.5 - start) / extent * size as f32;
+                    (offset as u32).min(size - 1)
+                };
+                for y in y0..y1 {
+                    let image_y = sample(y, rect.y, rect.height, height);
+                    for x in x0..x1 {
+                        let image_x = sample(x, rect.x, rect.width, width);
+                        let [r, g, b, a] = image.get_pixel(image_x, image_y).0;
+                        let dst = &mut self.pixels[y * self.width + x];
+                        *dst = blend(*dst, Color { r, g, b, a }, 1.0);
+                    }
+                }
+            }
+            DisplayCommand::BoxShadow(color, rect, radius, blur) => {
+                let bounds = Rect {
+                    x: rect.x - blur,
+                    y: rect.y - blur,
+                    width: rect.width + 2.0 * blur,
+                    height: rect.height + 2.0 * blur,
+                };
+                let (x0, y0, x1, y1) = self.clip(bounds);
+                for y in y0..y1 {
+                    for x in x0..x1 {
+                        let distance =
+                            rounded_rect_distance(x as f32 + 0.5, y as f32 + 0.5, rect, radius);
+                        // Without a blur the edge is sharp: pixel centers inside are covered.
+                        // A blur fades it out linearly from `blur` inside to `blur` outside.
+                        let coverage = if blur > 0.0 {
+                            ((blur - distance) / (2.0 * blur)).clamp(0.0, 1.0)
+                        } else if distance <= 0.0 {
+                            1.0
+                        } else {
+                            0.0
+                        };
+                        if coverage > 0.0 {
+                            let dst = &mut self.pixels[y * self.width + x];
+                            *dst = blend(*dst, color, coverage);
+                        }
+                    }
+                }
+            }
+            DisplayCommand::Transform(transform, ref items) if transform.keeps_orientation() => {
+                for item in items {
+                    self.paint_item(&item.transformed(transform));
+                }
+            }
+            DisplayCommand::Transform(transform, ref items) => {
+                // Rectangles don't stay rectangles, so paint the commands untransformed onto a
+                // layer, then take each pixel from the point of the layer that lands on it
+                let inverse = match transform.inverse() {
+                    Some(inverse) => inverse,
+                    None => return,
+                };
+                let mut layer = Canvas::filled(self.width, self.height, Color::default());
+                for item in items {
+                    layer.paint_item(item);
+                }
+
+                let layer_rect = Rect {
+                    x: 0.0,
+                    y: 0.0,
+                    width: self.width as f32,
+                    height: self.height as f32,
+                };
+                let (x0, y0, x1, y1) = self.clip(transform.apply_rect(layer_rect));
+                for y in y0..y1 {
+                    for x in x0..x1 {
+                        let (layer_x, layer_y) = inverse.apply(x as f32 + 0.5, y as f32 + 0.5);
+                        if layer_rect.contains(layer_x, layer_y) {
+                            let src =
+                                layer.pixels[layer_y as usize * self.width + layer_x as usize];
+                            let dst = &mut self.pixels[y * self.width + x];
+                            *dst = blend(*dst, src, 1.0);
+                        }
+                    }
+                }
+            }
         }
     }
+
+    // Clip a rectangle to the canvas boundaries, returning the pixel range it covers
+    fn clip(&self, rect: Rect) -> (usize, usize, usize, usize) {
+        let x0 = rect.x.clamp(0.0, self.width as f32) as usize;
+        let y0 = rect.y.clamp(0.0, self.height as f32) as usize;
+
+        let x1 = (rect.x + rect.width).clamp(0.0, self.width as f32) as usize;
+        let y1 = (rect.y + rect.height).clamp(0.0, self.height as f32) as usize;
+
+        (x0, y0, x1, y1)
+    }
+}
+
+// Is the point inside `rect` with its corners rounded to `radius`? The radius is limited to
+// half the shorter side, so opposite corners never overlap.
+fn in_rounded_rect(x: f32, y: f32, rect: Rect, radius: f32) -> bool {
+    let radius = radius.min(rect.width / 2.0).min(rect.height / 2.0);
+    // The distance from the point into each corner square, or zero outside them
+    let dx = (rect.x + radius - x)
+        .max(x - (rect.x + rect.width - radius))
+        .max(0.0);
+    let dy = (rect.y + radius - y)
+        .max(y - (rect.y + rect.height - radius))
+        .max(0.0);
+    dx * dx + dy * dy <= radius * radius
+}
+
+// How far the point is outside `rect` with its corners rounded to `radius`, negative when it
+// is inside. As in `in_rounded_rect`, the radius is limited to half the shorter side.
+fn rounded_rect_distance(x: f32, y: f32, rect: Rect, radius: f32) -> f32 {
+    let radius = radius.min(rect.width / 2.0).min(rect.height / 2.0);
+    // The distance from the center, and past the straight part of each edge
+    let dx = (x - (rect.x + rect.width / 2.0)).abs() - (rect.width / 2.0 - radius);
+    let dy = (y - (rect.y + rect.height / 2.0)).abs() - (rect.height / 2.0 - radius);
+    let outside = dx.max(0.0).hypot(dy.max(0.0));
+    let inside = dx.max(dy).min(0.0);
+    outside + inside - radius
 }
 
-trait Clamp {
-    fn clamp(self, lower: Self, upper: Self) -> Self;
+// The color at `t`, from 0 at the first stop to 1 at the last
+fn gradient_color(stops: &[Color], t: f32) -> Color {
+    let position = t * (stops.len() - 1) as f32;
+    let index = (position as usize).min(stops.len() - 2);
+    stops[index].mix(&stops[index + 1], position - index as f32)
+}
+
+// Composite `src` over `dst`, with the alpha of `src` scaled by `opacity`
+fn blend(dst: Color, src: Color, opacity: f32) -> Color {
+    let alpha = src.a as f32 / 255.0 * opacity;
+    let mix = |d: u8, s: u8| (s as f32 * alpha + d as f32 * (1.0 - alpha)).round() as u8;
+
+    Color {
+        r: mix(dst.r, src.r),
+        g: mix(dst.g, src.g),
+        b: mix(dst.b, src.b),
+        a: (alpha * 255.0 + dst.a as f32 * (1.0 - alpha)).round() as u8,
+    }
 }
 
-impl Clamp for f32 {
-    fn clamp(self, lower: Self, upper: Self) -> Self {
-        self.max(lower).min(upper)
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::fixtures::{border_box, pixel, render, with_layout, WHITE};
+
+    const RED: Color = Color {
+        r: 255,
+        g: 0,
+        b: 0,
+        a: 255,
+    };
+
+    const BOX: &str = r#"<div id="box"></div>"#;
+
+    #[test]
+    fn builds_one_solid_color_for_a_styled_box() {
+        let css = "#box { display: block; margin: 5px; width: 20px; height: 10px;
+            background: #ff0000; }";
+        let list = with_layout(BOX, css, (50.0, 50.0), build_display_list);
+        let rect = Rect {
+            x: 5.0,
+            y: 5.0,
+            width: 20.0,
+            height: 10.0,
+        };
+        assert_eq!(list, [DisplayCommand::SolidColor(RED, rect)]);
+    }
+
+    #[test]
+    fn hidden_box_keeps_its_height_but_paints_nothing() {
+        let html = r#"<div><div id="hidden"></div><div id="after"></div></div>"#;
+        let css = "div { display: block; }
+            #hidden { height: 10px; background: #ff0000; visibility: hidden; }
+            #after { height: 10px; background: #0000ff; }";
+        assert_eq!(border_box(html, css, "#hidden").height, 10.0);
+        assert_eq!(border_box(html, css, "#after").y, 10.0);
+        let canvas = render(html, css, 20.0, 20.0);
+        assert_eq!(pixel(&canvas, 5, 5), WHITE);
+        let blue = Color {
+            r: 0,
+            g: 0,
+            b: 255,
+            a: 255,
+        };
+        assert_eq!(pixel(&canvas, 5, 15), blue);
+    }
+
+    #[test]
+    fn paints_visible_children_of_a_hidden_box() {
+        let html = r#"<div><div id="hidden"><div id="shown"></div></div></div>"#;
+        let css = "div { display: block; }
+            #hidden { padding: 5px; background: #0000ff; visibility: hidden; }
+            #shown { height: 10px; background: #ff0000; visibility: visible; }";
+        let canvas = render(html, css, 20.0, 20.0);
+        assert_eq!(pixel(&canvas, 2, 2), WHITE);
+        assert_eq!(pixel(&canvas, 7, 7), RED);
+    }
+
+    #[test]
+    fn opacity_zero_leaves_the_canvas_unchanged() {
+        let html = r#"<div><div id="group"><div id="child"></div></div></div>"#;
+        let css = "div { display: block; } #group { height: 10px; background: #ff0000; }
+            #child { height: 5px; background: #0000ff; }";
+        let blank = render("<div></div>", "div { display: block; }", 20.0, 20.0);
+        let hidden = render(
+            html,
+            &format!("{} #group {{ opacity: 0%; }}", css),
+            20.0,
+            20.0,
+        );
+        assert_eq!(hidden.pixels, blank.pixels);
+
+        let plain = render(html, css, 20.0, 20.0);
+        let opaque = render(
+            html,
+            &format!("{} #group {{ opacity: 100%; }}", css),
+            20.0,
+            20.0,
+        );
+        assert_eq!(opaque.pixels, plain.pixels);
+    }
+
+    #[test]
+    fn blends_an_overlapping_group_once() {
+        let html = r#"<div><div id="group"><div id="child"></div></div></div>"#;
+        let css =
+            "div { display: block; } #group { height: 10px; background: #ff0000; opacity: 50%; }
+            #child { height: 10px; background: #ff0000; }";
+        let canvas = render(html, css, 20.0, 20.0);
+        // Half of red over white, not half of red over half of red over white
+        let color = pixel(&canvas, 5, 5);
+        assert_eq!(color.r, 255);
+        assert!((127..=128).contains(&color.g) && color.g == color.b);
+    }
+
+    #[test]
+    fn higher_z_index_paints_over_a_later_box() {
+        let html = r#"<div><div id="top"></div><div id="bottom"></div></div>"#;
+        let css =
+            "#top, #bottom { position: absolute; left: 0; top: 0; width: 10px; height: 10px; }
+            #top { z-index: 2; background: #ff0000; } #bottom { z-index: 1; background: #0000ff; }";
+        assert_eq!(pixel(&render(html, css, 20.0, 20.0), 5, 5), RED);
+
+        let css = css.replace("z-index: 2", "z-index: 0");
+        let blue = Color {
+            r: 0,
+            g: 0,
+            b: 255,
+            a: 255,
+        };
+        assert_eq!(pixel(&render(html, &css, 20.0, 20.0), 5, 5), blue);
+    }
+
+    #[test]
+    fn current_color_borders_use_the_text_color() {
+        let css = "#box { width: 10px; height: 10px; color: #ff0000;
+            border-width: 2px; border-color: currentColor; }";
+        let canvas = render(BOX, css, 20.0, 20.0);
+        for (x, y) in [(0, 0), (0, 5), (13, 5), (5, 13)] {
+            assert_eq!(pixel(&canvas, x, y), RED, "pixel at {}, {}", x, y);
+        }
+        assert_eq!(pixel(&canvas, 6, 6), WHITE);
+    }
+
+    // Save an image of the given size filled with `color` as a PNG in the temp directory
+    fn temp_png(name: &str, (width, height): (u32, u32), color: Color) -> String {
+        let image = image::RgbaImage::from_pixel(
+            width,
+            height,
+            image::Rgba([color.r, color.g, color.b, color.a]),
+        );
+        let path = std::env::temp_dir().join(format!("{}-{}", std::process::id(), name));
+        image.save(&path).unwrap();
+        path.to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn scales_a_background_image_to_the_content_area() {
+        let path = temp_png("red-1x1.png", (1, 1), RED);
+        let css = format!(
+            "#box {{ padding: 2px; width: 10px; height: 10px; background-image: url({:?}); }}",
+            path
+        );
+        let canvas = render(BOX, &css, 20.0, 20.0);
+        for (x, y) in [(2, 2), (6, 6), (11, 11)] {
+            assert_eq!(pixel(&canvas, x, y), RED, "pixel at {}, {}", x, y);
+        }
+        // The padding is left unpainted
+        assert_eq!(pixel(&canvas, 1, 1), WHITE);
+        assert_eq!(pixel(&canvas, 12, 12), WHITE);
+    }
+
+    #[test]
+    fn clips_a_covering_background_image_to_the_content_area() {
+        let path = temp_png("red-8x4.png", (8, 4), RED);
+        let css = format!(
+            "#box {{ padding: 2px; width: 4px; height: 4px; background-image: url({:?});
+            object-fit: cover; }}",
+            path
+        );
+        let canvas = render(BOX, &css, 20.0, 20.0);
+        assert_eq!(pixel(&canvas, 2, 3), RED);
+        assert_eq!(pixel(&canvas, 5, 3), RED);
+        assert_eq!(pixel(&canvas, 1, 3), WHITE);
+        assert_eq!(pixel(&canvas, 6, 3), WHITE);
+    }
+
+    #[test]
+    fn paints_a_gradient_from_its_first_stop_to_its_last() {
+        let css = "#box { width: 20px; height: 40px; \
+                   background-image: linear-gradient(#fff, #000) }";
+        let canvas = render(BOX, css, 20.0, 40.0);
+        let (top, bottom) = (pixel(&canvas, 10, 0), pixel(&canvas, 10, 39));
+        assert!(top.r > bottom.r);
+        assert!(top.r > 240 && bottom.r < 15);
+    }
+
+    #[test]
+    fn leaves_the_corners_of_a_rounded_box_unpainted() {
+        let css = "#box { width: 50px; height: 50px; background: #ff0000; border-radius: 10px }";
+        let canvas = render(&format!("<div>{}</div>", BOX), css, 50.0, 50.0);
+        for (x, y) in [(0, 0), (49, 0), (0, 49), (49, 49), (1, 1)] {
+            assert_eq!(pixel(&canvas, x, y), WHITE, "corner pixel at {}, {}", x, y);
+        }
+        for (x, y) in [(25, 25), (25, 0), (0, 25), (5, 5)] {
+            assert_eq!(pixel(&canvas, x, y), RED, "pixel at {}, {}", x, y);
+        }
+    }
+
+    #[test]
+    fn paints_the_outline_just_outside_the_border_box() {
+        let css = "#box { margin: 10px; width: 20px; height: 20px; outline: 2px solid #f00 }";
+        let canvas = render(BOX, css, 50.0, 50.0);
+        assert_eq!(pixel(&canvas, 8, 8), RED);
+        assert_eq!(pixel(&canvas, 9, 20), RED);
+        assert_eq!(pixel(&canvas, 30, 31), RED);
+        assert_eq!(pixel(&canvas, 7, 7), WHITE);
+        assert_eq!(pixel(&canvas, 10, 10), WHITE);
+        assert_eq!(pixel(&canvas, 20, 20), WHITE);
+
+        // The outline takes no space, so the box stays where it was
+        let without = "#box { margin: 10px; width: 20px; height: 20px }";
+        assert_eq!(
+            border_box(BOX, css, "#box"),
+            border_box(BOX, without, "#box")
+        );
+    }
+
+    #[test]
+    fn paints_no_outline_without_an_outline_style() {
+        for css in [
+            "#box { margin: 10px; width: 20px; height: 20px; outline: 2px #f00 }",
+            "#box { margin: 10px; width: 20px; height: 20px; outline-color: #f00 }",
+            "#box { margin: 10px; width: 20px; height: 20px; outline: 2px none #f00 }",
+        ] {
+            let canvas = render(BOX, css, 50.0, 50.0);
+            assert!(canvas.pixels.iter().all(|&color| color == WHITE), "{}", css);
+        }
+    }
+
+    #[test]
+    fn paints_a_box_shadow_offset_below_the_box() {
+        let css = "#box { margin: 10px; width: 20px; height: 20px; background: #ff0000;
+            box-shadow: 4px 6px 0px #0000ff }";
+        let canvas = render(&format!("<div>{}</div>", BOX), css, 50.0, 50.0);
+        let blue = Color::from_rgba(0, 0, 255, 255);
+        // The box covers its own shadow, which shows to the right of and below it
+        assert_eq!(pixel(&canvas, 20, 20), RED);
+        assert_eq!(pixel(&canvas, 32, 20), blue);
+        assert_eq!(pixel(&canvas, 20, 34), blue);
+        assert_eq!(pixel(&canvas, 12, 12), RED);
+        assert_eq!(pixel(&canvas, 11, 31), WHITE);
+        assert_eq!(pixel(&canvas, 35, 20), WHITE);
+    }
+
+    #[test]
+    fn blurs_the_edges_of_a_box_shadow() {
+        let css =
+            "#box { margin: 10px; width: 20px; height: 20px; box-shadow: 0px 0px 4px #000000 }";
+        let canvas = render(&format!("<div>{}</div>", BOX), css, 50.0, 50.0);
+        let inside = pixel(&canvas, 20, 31);
+        let outside = pixel(&canvas, 20, 33);
+        assert!(
+            inside.r < outside.r && outside.r < 255,
+            "{:?} {:?}",
+            inside,
+            outside
+        );
+    }
+
+    #[test]
+    fn paints_a_disc_marker_in_front_of_a_list_item() {
+        let html = r#"<div><div id="item"></div></div>"#;
+        let css = "#item { display: list-item; height: 20px; color: #ff0000 }";
+        let list = with_layout(html, css, (50.0, 50.0), build_display_list);
+        let disc = list.iter().find_map(|command| match *command {
+            DisplayCommand::RoundedClip(rect, _, ref inner) => Some((rect, inner.clone())),
+            _ => None,
+        });
+        let (rect, inner) = disc.expect("a disc marker");
+        assert!(rect.x + rect.width <= 16.0);
+        assert_eq!(inner, [DisplayCommand::SolidColor(RED, rect)]);
+
+        let css = format!("{} #item {{ list-style-type: none }}", css);
+        assert!(with_layout(html, &css, (50.0, 50.0), build_display_list).is_empty());
+    }
+
+    #[test]
+    fn hides_the_background_of_an_empty_cell_only_with_empty_cells_hide() {
+        let html = r#"<table><tr><td id="empty"></td><td>x</td></tr></table>"#;
+        let css = "table { width: 40px } td { height: 20px; background: #ff0000 }";
+        assert_eq!(pixel(&render(html, css, 40.0, 20.0), 5, 5), RED);
+        let css = format!("{} #empty {{ empty-cells: hide }}", css);
+        let canvas = render(html, &css, 40.0, 20.0);
+        assert_eq!(pixel(&canvas, 5, 5), WHITE);
+        assert_eq!(pixel(&canvas, 35, 15), RED);
+    }
+
+    #[test]
+    fn contain_letterboxes_a_wide_image_and_cover_fills_the_box() {
+        let path = temp_png("red-20x10.png", (20, 10), RED);
+        let html = r#"<div><div id="img"></div></div>"#;
+        let blue = Color::from_rgba(0, 0, 255, 255);
+        let css = format!(
+            "#img {{ width: 20px; height: 20px; background-color: #0000ff; \
+             background-image: url({:?}) }}",
+            path
+        );
+
+        let contain = format!("{} #img {{ object-fit: contain }}", css);
+        let canvas = render(html, &contain, 20.0, 20.0);
+        assert_eq!(pixel(&canvas, 10, 2), blue);
+        assert_eq!(pixel(&canvas, 10, 10), RED);
+        assert_eq!(pixel(&canvas, 10, 17), blue);
+
+        let cover = format!("{} #img {{ object-fit: cover }}", css);
+        let canvas = render(html, &cover, 20.0, 20.0);
+        for (x, y) in [(0, 0), (10, 2), (10, 17), (19, 19)] {
+            assert_eq!(pixel(&canvas, x, y), RED, "pixel at {}, {}", x, y);
+        }
+    }
+
+    #[test]
+    fn painting_into_a_reused_canvas_matches_a_fresh_one() {
+        let html = r#"<div><div id="box"></div></div>"#;
+        let css = "#box { margin: 3px; width: 10px; height: 5px; background: #ff0000 }";
+        let bounds = Rect {
+            x: 0.0,
+            y: 0.0,
+            width: 20.0,
+            height: 20.0,
+        };
+        let fresh = with_layout(html, css, (20.0, 20.0), |root| paint(root, bounds, WHITE));
+
+        // Start from a canvas of another size and color, then paint the tree into it twice
+        let mut canvas = Canvas::filled(30, 10, RED);
+        with_layout(html, css, (20.0, 20.0), |root| {
+            for _ in 0..2 {
+                paint_into(&mut canvas, root, bounds, WHITE);
+                assert_eq!((canvas.width, canvas.height), (20, 20));
+                assert_eq!(canvas.pixels, fresh.pixels);
+            }
+        });
+    }
+
+    #[test]
+    fn converts_a_canvas_to_an_image_of_the_same_size() {
+        let mut canvas = Canvas::new(3, 2);
+        // The last pixel of the second row
+        canvas.pixels[5] = RED;
+        let image = canvas.to_rgba_image();
+        assert_eq!(image.dimensions(), (3, 2));
+        assert_eq!(*image.get_pixel(2, 1), image::Rgba([255, 0, 0, 255]));
+        assert_eq!(*image.get_pixel(0, 0), image::Rgba([255, 255, 255, 255]));
+
+        let path = std::env::temp_dir().join(format!("{}-canvas.png", std::process::id()));
+        canvas.save_png(&path).unwrap();
+        assert_eq!(image::open(&path).unwrap().to_rgba8(), image);
+    }
+
+    #[test]
+    fn translate_shifts_the_painted_box_but_not_its_layout() {
+        let html = r#"<div><div id="box"></div></div>"#;
+        let css = "#box { width: 10px; height: 10px; background: #ff0000 }";
+        let moved = format!("{} #box {{ transform: translate(10px, 0) }}", css);
+        assert_eq!(
+            border_box(html, &moved, "#box"),
+            border_box(html, css, "#box")
+        );
+
+        let canvas = render(html, &moved, 30.0, 20.0);
+        for x in [0, 9] {
+            assert_eq!(pixel(&canvas, x, 5), WHITE, "pixel at {}, 5", x);
+        }
+        for x in [10, 19] {
+            assert_eq!(pixel(&canvas, x, 5), RED, "pixel at {}, 5", x);
+        }
+        assert_eq!(pixel(&canvas, 20, 5), WHITE);
+    }
+
+    #[test]
+    fn scale_grows_the_painted_box_about_its_center() {
+        let html = r#"<div><div id="box"></div></div>"#;
+        let css = "#box { margin: 10px; width: 10px; height: 10px; background: #ff0000;
+            transform: scale(2) }";
+        let canvas = render(html, css, 40.0, 40.0);
+        assert_eq!(pixel(&canvas, 5, 5), RED);
+        assert_eq!(pixel(&canvas, 24, 24), RED);
+        assert_eq!(pixel(&canvas, 4, 4), WHITE);
+        assert_eq!(pixel(&canvas, 25, 25), WHITE);
+    }
+
+    #[test]
+    fn rotate_paints_a_box_outside_its_own_bounds() {
+        let html = r#"<div><div id="box"></div></div>"#;
+        let css = "#box { margin: 10px; width: 20px; height: 20px; background: #ff0000 }";
+        let rotated = format!("{} #box {{ transform: rotate(45deg) }}", css);
+        let canvas = render(html, &rotated, 40.0, 40.0);
+        // The corners of the box turn onto the middles of its sides, beyond its bounds, and
+        // its own corners are left empty
+        assert_eq!(pixel(&canvas, 20, 7), RED);
+        assert_eq!(pixel(&canvas, 7, 20), RED);
+        assert_eq!(pixel(&canvas, 11, 11), WHITE);
+        assert_eq!(pixel(&canvas, 20, 20), RED);
+
+        // Radians turn it just as far
+        let radians = format!("{} #box {{ transform: rotate(0.7853982rad) }}", css);
+        assert_eq!(render(html, &radians, 40.0, 40.0).pixels, canvas.pixels);
     }
 }
@@ -1,17 +1,49 @@
 // Code for applying CSS styles to the DOM
 
-use std::collections::{HashMap, HashSet};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashSet};
+use std::fmt::Write;
 
 use crate::{
-    css::{Rule, Selector, SimpleSelector, Specificity, StyleSheet, Value},
-    dom::{ElementData, Node, NodeType},
+    css::{
+        self, Combinator, Declaration, Origin, PseudoClass, PseudoElement, Rule, Selector,
+        SimpleSelector, Specificity, StyleSheet, TransformFunction, Unit, Value, Viewport,
+    },
+    dom::{self, ElementData, Node, NodeType},
+    text,
 };
 
-// Map from css associated style data
-type PropertyMap = HashMap<String, Value>;
+// Map from css associated style data. It is sorted by property name, so that iterating over
+// it is repeatable.
+pub type PropertyMap = BTreeMap<String, Value>;
+
+// Properties that a node takes from its parent when they aren't specified on it
+const INHERITED_PROPERTIES: &[&str] = &[
+    "color",
+    "direction",
+    "empty-cells",
+    "font-family",
+    "font-size",
+    "font-style",
+    "letter-spacing",
+    "line-height",
+    "list-style-type",
+    "overflow-wrap",
+    "tab-size",
+    "text-transform",
+    "visibility",
+    "white-space",
+    "word-spacing",
+    "writing-mode",
+];
+
+// How much room a list item's marker takes in front of its content, as a multiple of the
+// font size
+const MARKER_WIDTH: f32 = 1.0;
 
 pub struct StyledNode<'a> {
-    pub node: &'a Node, // pointer to a DOM node
+    // pointer to a DOM node, or a node generated for a pseudo-element
+    pub node: Cow<'a, Node>,
     pub specified_values: PropertyMap,
     pub children: Vec<StyledNode<'a>>,
 }
@@ -19,21 +51,189 @@ pub struct StyledNode<'a> {
 #[derive(PartialEq)]
 pub enum Display {
     Inline,
+    // Laid out as a block, but placed on a line like inline content
+    InlineBlock,
     Block,
+    // A block with a marker, like a bullet, in front of its content
+    ListItem,
+    Flex,
+    Table,
+    // Groups of rows in a table, like `<tbody>`
+    TableRowGroup,
+    TableRow,
+    TableCell,
+    None,
+}
+
+#[derive(PartialEq, Clone, Copy)]
+pub enum Float {
+    Left,
+    Right,
+    None,
+}
+
+#[derive(PartialEq, Clone, Copy)]
+pub enum Clear {
+    Left,
+    Right,
+    Both,
     None,
 }
 
+#[derive(PartialEq, Clone, Copy)]
+pub enum Position {
+    Static,
+    Relative,
+    Absolute,
+}
+
+#[derive(PartialEq, Clone, Copy)]
+pub enum Visibility {
+    Visible,
+    Hidden,
+}
+
+#[derive(PartialEq, Clone, Copy)]
+pub enum WhiteSpace {
+    Normal,
+    // Collapses whitespace like normal, but never wraps
+    NoWrap,
+    Pre,
+}
+
+// Whether words that don't fit on a line on their own may be broken
+#[derive(PartialEq, Clone, Copy)]
+pub enum OverflowWrap {
+    Normal,
+    // Between any two characters, when there is nowhere else to break
+    BreakWord,
+}
+
+// How the letters of text are cased when it is laid out
+#[derive(PartialEq, Clone, Copy)]
+pub enum TextTransform {
+    None,
+    Uppercase,
+    Lowercase,
+    Capitalize,
+}
+
+// Where an inline-block sits within the height of its line. There are no baselines yet, so
+// `baseline` puts boxes at the top of the line like `top`.
+#[derive(PartialEq, Clone, Copy)]
+pub enum VerticalAlign {
+    Baseline,
+    Top,
+    Middle,
+    Bottom,
+}
+
+// The direction inline content runs in. Right-to-left lines start at the right edge of
+// their block.
+#[derive(PartialEq, Clone, Copy)]
+pub enum Direction {
+    Ltr,
+    Rtl,
+}
+
+// The direction blocks are stacked in. Only block boxes follow it so far: text is still set
+// horizontally.
+#[derive(PartialEq, Clone, Copy)]
+pub enum WritingMode {
+    // Blocks are stacked from top to bottom
+    HorizontalTb,
+    // Blocks are stacked from right to left
+    VerticalRl,
+}
+
+// Whether the background and borders of table cells without content are painted
+#[derive(PartialEq, Clone, Copy)]
+pub enum EmptyCells {
+    Show,
+    Hide,
+}
+
+// How a background image is scaled to its box
+#[derive(PartialEq, Clone, Copy)]
+pub enum ObjectFit {
+    // Stretched to fill the box
+    Fill,
+    // Scaled evenly to fit inside the box, leaving the rest of it uncovered
+    Contain,
+    // Scaled evenly to cover the whole box, cropping what falls outside it
+    Cover,
+}
+
+#[derive(PartialEq, Clone, Copy)]
+pub enum FontStyle {
+    Normal,
+    Italic,
+}
+
+// The marker drawn in front of a list item
+#[derive(PartialEq, Clone, Copy)]
+pub enum ListStyleType {
+    Disc,
+    Square,
+    None,
+}
+
+#[derive(PartialEq, Clone, Copy)]
+pub enum FlexDirection {
+    Row,
+    Column,
+}
+
+#[derive(PartialEq, Clone, Copy)]
+pub enum FlexWrap {
+    NoWrap,
+    Wrap,
+}
+
+#[derive(PartialEq, Clone, Copy)]
+pub enum JustifyContent {
+    FlexStart,
+    Center,
+    SpaceBetween,
+}
+
+#[derive(PartialEq, Clone, Copy)]
+pub enum Overflow {
+    Visible,
+    Hidden,
+    Scroll,
+    Auto,
+}
+
+// An outer shadow painted beneath a box, from `box-shadow`
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct BoxShadow {
+    pub offset_x: f32,
+    pub offset_y: f32,
+    pub blur: f32,
+    pub spread: f32,
+    pub color: css::Color,
+}
+
 impl<'a> StyledNode<'a> {
     // rteurn the specified value of a property if it exists. otherwise NOne
     pub fn value(&self, name: &str) -> Option<Value> {
         self.specified_values.get(name).cloned()
     }
 
-    /// Return the specified value of property `name`, or property `fallback_name` if that doesn't
-    /// exist, or value `default` if neither does.
-    pub fn lookup(&self, name: &str, fallback_name: &str, default: &Value) -> Value {
-        self.value(name)
-            .unwrap_or_else(|| self.value(fallback_name).unwrap_or_else(|| default.clone()))
+    /// Return the specified value of property `name`, or value `default` if it doesn't exist.
+    /// Shorthands are expanded when they are parsed, so `name` should be a longhand.
+    pub fn lookup(&self, name: &str, default: &Value) -> Value {
+        self.value(name).unwrap_or_else(|| default.clone())
+    }
+
+    // The color of a color-valued property, or None if it isn't set to a color.
+    // `currentColor` has already been replaced by the node's `color` when computing values.
+    pub fn computed_color(&self, name: &str) -> Option<css::Color> {
+        match self.value(name) {
+            Some(Value::ColorValue(color)) => Some(color),
+            _ => None,
+        }
     }
 
     // The value of the `display` property (defaults to inline).
@@ -41,41 +241,672 @@ impl<'a> StyledNode<'a> {
         match self.value("display") {
             Some(Value::Keyword(s)) => match &*s {
                 "block" => Display::Block,
+                "inline-block" => Display::InlineBlock,
+                "list-item" => Display::ListItem,
+                "flex" => Display::Flex,
+                "table" => Display::Table,
+                "table-row-group" | "table-header-group" | "table-footer-group" => {
+                    Display::TableRowGroup
+                }
+                "table-row" => Display::TableRow,
+                "table-cell" => Display::TableCell,
                 "none" => Display::None,
                 _ => Display::Inline,
             },
             _ => Display::Inline,
         }
     }
+
+    // The value of the `float` property (defaults to none).
+    pub fn float(&self) -> Float {
+        match self.value("float") {
+            Some(Value::Keyword(s)) => match &*s {
+                "left" => Float::Left,
+                "right" => Float::Right,
+                _ => Float::None,
+            },
+            _ => Float::None,
+        }
+    }
+
+    // The value of the `clear` property (defaults to none).
+    pub fn clear(&self) -> Clear {
+        match self.value("clear") {
+            Some(Value::Keyword(s)) => match &*s {
+                "left" => Clear::Left,
+                "right" => Clear::Right,
+                "both" => Clear::Both,
+                _ => Clear::None,
+            },
+            _ => Clear::None,
+        }
+    }
+
+    // The value of the `position` property (defaults to static).
+    pub fn position(&self) -> Position {
+        match self.value("position") {
+            Some(Value::Keyword(s)) => match &*s {
+                "relative" => Position::Relative,
+                "absolute" => Position::Absolute,
+                _ => Position::Static,
+            },
+            _ => Position::Static,
+        }
+    }
+
+    // The value of the `z-index` property, or None if it is auto. It only applies to
+    // positioned elements.
+    pub fn z_index(&self) -> Option<i32> {
+        match (self.position(), self.value("z-index")) {
+            (Position::Static, _) => None,
+            (_, Some(Value::Number(z))) => Some(z as i32),
+            _ => None,
+        }
+    }
+
+    // The value of the `visibility` property (defaults to visible). `collapse` only differs
+    // from `hidden` for table parts, so it is treated the same way.
+    pub fn visibility(&self) -> Visibility {
+        match self.value("visibility") {
+            Some(Value::Keyword(s)) => match &*s {
+                "hidden" | "collapse" => Visibility::Hidden,
+                _ => Visibility::Visible,
+            },
+            _ => Visibility::Visible,
+        }
+    }
+
+    // The value of the `opacity` property, clamped to [0, 1] (defaults to 1).
+    pub fn opacity(&self) -> f32 {
+        match self.value("opacity") {
+            Some(Value::Number(opacity)) => opacity.clamp(0.0, 1.0),
+            Some(Value::Length(percent, Unit::Percent)) => (percent / 100.0).clamp(0.0, 1.0),
+            _ => 1.0,
+        }
+    }
+
+    // The functions of the `transform` property, applied to the box from last to first
+    // (defaults to none). A value the engine doesn't fully understand transforms nothing.
+    pub fn transform(&self) -> Vec<TransformFunction> {
+        let functions = match self.value("transform") {
+            Some(Value::List(functions)) => functions,
+            Some(function) => vec![function],
+            None => return Vec::new(),
+        };
+        functions
+            .into_iter()
+            .map(|function| match function {
+                Value::Transform(function) => Some(function),
+                _ => None,
+            })
+            .collect::<Option<_>>()
+            .unwrap_or_default()
+    }
+
+    // The point the `transform` applies around, as an offset from the top left corner of a
+    // border box of the given size. It is a px length, a percentage of the size or one of the
+    // keywords `left`, `center`, `right`, `top` and `bottom` for each axis, and defaults to
+    // the center.
+    pub fn transform_origin(&self, width: f32, height: f32) -> (f32, f32) {
+        let components = match self.value("transform-origin") {
+            Some(Value::List(components)) => components,
+            Some(component) => vec![component],
+            None => Vec::new(),
+        };
+        let is_vertical = |value: &Value| {
+            matches!(value, Value::Keyword(k) if k.eq_ignore_ascii_case("top")
+                || k.eq_ignore_ascii_case("bottom"))
+        };
+        let is_horizontal = |value: &Value| {
+            matches!(value, Value::Keyword(k) if k.eq_ignore_ascii_case("left")
+                || k.eq_ignore_ascii_case("right"))
+        };
+        let center = Value::Keyword("center".to_string());
+        let (x, y) = match &components[..] {
+            [y] if is_vertical(y) => (&center, y),
+            [x] => (x, &center),
+            // Keywords may come in either order, like `top left`
+            [y, x] if is_vertical(y) || is_horizontal(x) => (x, y),
+            [x, y] => (x, y),
+            _ => (&center, &center),
+        };
+        let offset = |value: &Value, size: f32| match value {
+            Value::Length(length, Unit::Px) => *length,
+            Value::Length(percent, Unit::Percent) => percent * size / 100.0,
+            Value::Number(0.0) => 0.0,
+            Value::Keyword(k)
+                if k.eq_ignore_ascii_case("left") || k.eq_ignore_ascii_case("top") =>
+            {
+                0.0
+            }
+            Value::Keyword(k)
+                if k.eq_ignore_ascii_case("right") || k.eq_ignore_ascii_case("bottom") =>
+            {
+                size
+            }
+            _ => size / 2.0,
+        };
+        (offset(x, width), offset(y, height))
+    }
+
+    // The `border-radius` of all four corners in px (defaults to 0).
+    pub fn border_radius(&self) -> f32 {
+        self.value("border-radius")
+            .map_or(0.0, |radius| radius.to_px().max(0.0))
+    }
+
+    // The width and color of the box's outline, if it has one: only an `outline-style` other
+    // than `none` or `hidden` gives it one. The width defaults to 3px and the color to
+    // `color`.
+    pub fn outline(&self) -> Option<(f32, css::Color)> {
+        let width = self.value("outline-width");
+        let color = self.value("outline-color");
+
+        // The style is `none` unless it is set, and then there is no outline
+        match self.value("outline-style") {
+            Some(Value::Keyword(ref style)) if !matches!(&**style, "none" | "hidden") => {}
+            _ => return None,
+        }
+        let width = width.map_or(3.0, |width| width.to_px());
+        let color = match color.or_else(|| self.value("color")) {
+            Some(Value::ColorValue(color)) => color,
+            _ => css::Color {
+                r: 0,
+                g: 0,
+                b: 0,
+                a: 255,
+            },
+        };
+        (width > 0.0).then_some((width, color))
+    }
+
+    // The box's shadow, from a `box-shadow` of two to four lengths (the offsets, blur radius
+    // and spread) and an optional color, which defaults to `color`. Inset shadows and lists
+    // of several shadows aren't supported, and give no shadow.
+    pub fn box_shadow(&self) -> Option<BoxShadow> {
+        let components = match self.value("box-shadow")? {
+            Value::List(components) => components,
+            _ => return None,
+        };
+
+        let mut lengths = Vec::new();
+        let mut color = None;
+        for component in components {
+            match component {
+                Value::Length(..) | Value::Number(0.0) => lengths.push(component.to_px()),
+                Value::ColorValue(c) if color.is_none() => color = Some(c),
+                _ => return None,
+            }
+        }
+        if !(2..=4).contains(&lengths.len()) {
+            return None;
+        }
+        let color = color
+            .or_else(|| self.computed_color("color"))
+            .unwrap_or(css::Color {
+                r: 0,
+                g: 0,
+                b: 0,
+                a: 255,
+            });
+
+        Some(BoxShadow {
+            offset_x: lengths[0],
+            offset_y: lengths[1],
+            blur: lengths.get(2).map_or(0.0, |blur| blur.max(0.0)),
+            spread: lengths.get(3).copied().unwrap_or(0.0),
+            color,
+        })
+    }
+
+    // The value of the `list-style-type` property (defaults to disc). Types without a
+    // marker of their own, like `decimal`, are drawn as discs.
+    pub fn list_style_type(&self) -> ListStyleType {
+        match self.value("list-style-type") {
+            Some(Value::Keyword(s)) => match &*s {
+                "square" => ListStyleType::Square,
+                "none" => ListStyleType::None,
+                _ => ListStyleType::Disc,
+            },
+            _ => ListStyleType::Disc,
+        }
+    }
+
+    // The width in px reserved in front of a list item's content for its marker, or 0 for
+    // other boxes and list items without a marker
+    pub fn marker_width(&self) -> f32 {
+        if self.display() == Display::ListItem && self.list_style_type() != ListStyleType::None {
+            self.font_size() * MARKER_WIDTH
+        } else {
+            0.0
+        }
+    }
+
+    // The value of the `empty-cells` property (defaults to show).
+    pub fn empty_cells(&self) -> EmptyCells {
+        match self.value("empty-cells") {
+            Some(Value::Keyword(s)) => match &*s {
+                "hide" => EmptyCells::Hide,
+                _ => EmptyCells::Show,
+            },
+            _ => EmptyCells::Show,
+        }
+    }
+
+    // The value of the `aspect-ratio` property as a width divided by a height, or None for
+    // `auto` (the default) and ratios with a zero or negative side.
+    pub fn aspect_ratio(&self) -> Option<f32> {
+        match self.value("aspect-ratio") {
+            Some(Value::Ratio(width, height)) if width > 0.0 && height > 0.0 => {
+                Some(width / height)
+            }
+            _ => None,
+        }
+    }
+
+    // The value of the `object-fit` property (defaults to fill).
+    pub fn object_fit(&self) -> ObjectFit {
+        match self.value("object-fit") {
+            Some(Value::Keyword(s)) => match &*s {
+                "contain" => ObjectFit::Contain,
+                "cover" => ObjectFit::Cover,
+                _ => ObjectFit::Fill,
+            },
+            _ => ObjectFit::Fill,
+        }
+    }
+
+    // The value of the `white-space` property (defaults to normal).
+    pub fn white_space(&self) -> WhiteSpace {
+        match self.value("white-space") {
+            Some(Value::Keyword(s)) => match &*s {
+                "pre" => WhiteSpace::Pre,
+                "nowrap" => WhiteSpace::NoWrap,
+                _ => WhiteSpace::Normal,
+            },
+            _ => WhiteSpace::Normal,
+        }
+    }
+
+    // The value of the `overflow-wrap` property (defaults to normal). `anywhere` breaks words
+    // in the same places as `break-word`.
+    pub fn overflow_wrap(&self) -> OverflowWrap {
+        match self.value("overflow-wrap") {
+            Some(Value::Keyword(s)) => match &*s {
+                "break-word" | "anywhere" => OverflowWrap::BreakWord,
+                _ => OverflowWrap::Normal,
+            },
+            _ => OverflowWrap::Normal,
+        }
+    }
+
+    // The value of the `vertical-align` property (defaults to baseline).
+    pub fn vertical_align(&self) -> VerticalAlign {
+        match self.value("vertical-align") {
+            Some(Value::Keyword(s)) => match &*s {
+                "top" => VerticalAlign::Top,
+                "middle" => VerticalAlign::Middle,
+                "bottom" => VerticalAlign::Bottom,
+                _ => VerticalAlign::Baseline,
+            },
+            _ => VerticalAlign::Baseline,
+        }
+    }
+
+    // The used font size in px
+    pub fn font_size(&self) -> f32 {
+        match self.value("font-size") {
+            Some(Value::Length(size, Unit::Px)) => size,
+            _ => text::DEFAULT_FONT_SIZE,
+        }
+    }
+
+    // The properties that text in this node is measured with. `normal` letter and word
+    // spacing add nothing.
+    pub fn text_style(&self) -> text::TextStyle {
+        let spacing = |name| self.value(name).map_or(0.0, |value| value.to_px());
+        text::TextStyle {
+            font_size: self.font_size(),
+            letter_spacing: spacing("letter-spacing"),
+            word_spacing: spacing("word-spacing"),
+        }
+    }
+
+    // The value of the `text-transform` property (defaults to none).
+    pub fn text_transform(&self) -> TextTransform {
+        match self.value("text-transform") {
+            Some(Value::Keyword(s)) => match &*s {
+                "uppercase" => TextTransform::Uppercase,
+                "lowercase" => TextTransform::Lowercase,
+                "capitalize" => TextTransform::Capitalize,
+                _ => TextTransform::None,
+            },
+            _ => TextTransform::None,
+        }
+    }
+
+    // Text as it is laid out and painted in this node, with `text-transform` applied. The
+    // text in the DOM stays as it was written.
+    pub fn transform_text(&self, text: &str) -> String {
+        match self.text_transform() {
+            TextTransform::None => text.to_string(),
+            TextTransform::Uppercase => text::uppercase(text),
+            TextTransform::Lowercase => text::lowercase(text),
+            TextTransform::Capitalize => text::capitalize(text),
+        }
+    }
+
+    // How many columns apart tab stops are in preformatted text (defaults to 8)
+    pub fn tab_size(&self) -> usize {
+        match self.value("tab-size") {
+            Some(Value::Number(size)) if size >= 0.0 => size as usize,
+            _ => text::DEFAULT_TAB_SIZE,
+        }
+    }
+
+    // The families listed in `font-family`, in order of preference
+    pub fn font_family(&self) -> Vec<String> {
+        match self.value("font-family") {
+            Some(Value::FontFamilies(families)) => families,
+            _ => Vec::new(),
+        }
+    }
+
+    // The font that text is set in: the first available family in `font-family`, in an
+    // italic face for `font-style: italic`
+    pub fn font(&self) -> text::Font {
+        text::select_font(&self.font_family(), self.font_style() == FontStyle::Italic)
+    }
+
+    // The value of the `font-style` property (defaults to normal). No face is slanted
+    // artificially, so `oblique` is the same as `italic`.
+    pub fn font_style(&self) -> FontStyle {
+        match self.value("font-style") {
+            Some(Value::Keyword(s)) => match &*s {
+                "italic" | "oblique" => FontStyle::Italic,
+                _ => FontStyle::Normal,
+            },
+            _ => FontStyle::Normal,
+        }
+    }
+
+    // The vertical metrics of the font that text is set in, at its font size
+    pub fn font_metrics(&self) -> text::FontMetrics {
+        self.font().metrics(self.font_size())
+    }
+
+    // The height of a line of text in px. A number is a multiple of the font size, and
+    // `normal` is the height the font asks for.
+    pub fn line_height(&self) -> f32 {
+        match self.value("line-height") {
+            Some(Value::Length(height, Unit::Px)) => height,
+            Some(Value::Number(factor)) => self.font_size() * factor,
+            _ => self.font_metrics().line_height(),
+        }
+    }
+
+    // How far the top of a line of text is above its baseline: the font's ascent, plus half
+    // of the leading, which is whatever the line height leaves around the glyphs
+    pub fn line_ascent(&self) -> f32 {
+        let metrics = self.font_metrics();
+        let leading = self.line_height() - metrics.ascent - metrics.descent;
+        metrics.ascent + leading / 2.0
+    }
+
+    // The value of the `flex-direction` property (defaults to row).
+    pub fn flex_direction(&self) -> FlexDirection {
+        match self.value("flex-direction") {
+            Some(Value::Keyword(s)) => match &*s {
+                "column" => FlexDirection::Column,
+                _ => FlexDirection::Row,
+            },
+            _ => FlexDirection::Row,
+        }
+    }
+
+    // The value of the `direction` property (defaults to ltr).
+    pub fn direction(&self) -> Direction {
+        match self.value("direction") {
+            Some(Value::Keyword(s)) => match &*s {
+                "rtl" => Direction::Rtl,
+                _ => Direction::Ltr,
+            },
+            _ => Direction::Ltr,
+        }
+    }
+
+    // The value of the `writing-mode` property (defaults to horizontal-tb).
+    pub fn writing_mode(&self) -> WritingMode {
+        match self.value("writing-mode") {
+            Some(Value::Keyword(s)) => match &*s {
+                "vertical-rl" => WritingMode::VerticalRl,
+                _ => WritingMode::HorizontalTb,
+            },
+            _ => WritingMode::HorizontalTb,
+        }
+    }
+
+    // The value of the `flex-wrap` property (defaults to nowrap).
+    pub fn flex_wrap(&self) -> FlexWrap {
+        match self.value("flex-wrap") {
+            Some(Value::Keyword(s)) => match &*s {
+                "wrap" => FlexWrap::Wrap,
+                _ => FlexWrap::NoWrap,
+            },
+            _ => FlexWrap::NoWrap,
+        }
+    }
+
+    // The value of the `flex-grow` property (defaults to 0).
+    pub fn flex_grow(&self) -> f32 {
+        match self.value("flex-grow") {
+            Some(Value::Number(grow)) => grow.max(0.0),
+            _ => 0.0,
+        }
+    }
+
+    // The value of `row-gap` or `column-gap` in px, with percentages of `reference`
+    // (defaults to 0 for `normal`).
+    pub fn gap(&self, name: &str, reference: f32, viewport: Viewport) -> f32 {
+        match self.value(name) {
+            Some(gap @ (Value::Length(..) | Value::Calc(_))) => {
+                gap.resolve(reference, viewport).max(0.0)
+            }
+            _ => 0.0,
+        }
+    }
+
+    // The value of the `justify-content` property (defaults to flex-start).
+    pub fn justify_content(&self) -> JustifyContent {
+        match self.value("justify-content") {
+            Some(Value::Keyword(s)) => match &*s {
+                "center" => JustifyContent::Center,
+                "space-between" => JustifyContent::SpaceBetween,
+                _ => JustifyContent::FlexStart,
+            },
+            _ => JustifyContent::FlexStart,
+        }
+    }
+
+    // The value of the `overflow` property (defaults to visible).
+    pub fn overflow(&self) -> Overflow {
+        match self.value("overflow") {
+            Some(Value::Keyword(s)) => match &*s {
+                "hidden" => Overflow::Hidden,
+                "scroll" => Overflow::Scroll,
+                "auto" => Overflow::Auto,
+                _ => Overflow::Visible,
+            },
+            _ => Overflow::Visible,
+        }
+    }
+
+    // The element this node styles, or None for a text node
+    fn element(&self) -> Option<&ElementData> {
+        match self.node.node_type {
+            NodeType::Element(ref elem) => Some(elem),
+            NodeType::Text(_) => None,
+        }
+    }
+
+    // The tag name of the element, or None for a text node. Pseudo-elements are named like
+    // `::before`.
+    pub fn tag_name(&self) -> Option<&str> {
+        self.element().map(|elem| &*elem.tag_name)
+    }
+
+    // The `id` attribute of the element, if it has one
+    pub fn id(&self) -> Option<&str> {
+        self.element().and_then(ElementData::id)
+    }
+
+    // The classes in the element's `class` attribute, empty for a text node
+    pub fn classes(&self) -> HashSet<&str> {
+        self.element().map(ElementData::classes).unwrap_or_default()
+    }
+
+    // A listing of the specified values of this element and every element below it, for
+    // `--dump-style`. Each element is named by its tag, id and classes, like `p#intro.note`,
+    // and followed by its properties in name order, one per line.
+    pub fn dump_style(&self) -> String {
+        let mut dump = String::new();
+        for node in self.iter() {
+            if let NodeType::Element(ref elem) = node.node.node_type {
+                dump.push_str(&elem.tag_name);
+                if let Some(id) = elem.id() {
+                    write!(dump, "#{}", id).unwrap();
+                }
+                for class in elem.get_attribute("class").unwrap_or("").split_whitespace() {
+                    write!(dump, ".{}", class).unwrap();
+                }
+                dump.push('\n');
+                for (name, value) in &node.specified_values {
+                    writeln!(dump, "  {}: {}", name, value).unwrap();
+                }
+            }
+        }
+        dump
+    }
+
+    // This node and every node below it in depth-first pre-order: each node comes before its
+    // children, and its children in document order
+    pub fn iter(&self) -> impl Iterator<Item = &StyledNode<'a>> {
+        let mut stack = vec![self];
+        std::iter::from_fn(move || {
+            let node = stack.pop()?;
+            stack.extend(node.children.iter().rev());
+            Some(node)
+        })
+    }
 }
 
-fn matches(elem: &ElementData, selector: &Selector) -> bool {
+// Where an element sits in its parent, for matching structural selectors
+#[derive(Clone, Copy)]
+pub struct SiblingContext<'a> {
+    // All children of the element's parent, including text nodes
+    pub siblings: &'a [Node],
+    // Position of the element within `siblings`
+    pub index: usize,
+}
+
+impl<'a> SiblingContext<'a> {
+    // Context for a node with no parent, which is its own only sibling
+    pub fn root(node: &'a Node) -> SiblingContext<'a> {
+        SiblingContext {
+            siblings: std::slice::from_ref(node),
+            index: 0,
+        }
+    }
+
+    // Is this the first element among its siblings? Text nodes don't count.
+    pub fn is_first_child(&self) -> bool {
+        !self.siblings[..self.index].iter().any(is_element)
+    }
+
+    // Is this the last element among its siblings? Text nodes don't count.
+    pub fn is_last_child(&self) -> bool {
+        !self.siblings[self.index + 1..].iter().any(is_element)
+    }
+
+    // The element sibling immediately before this one, with its own context
+    pub fn previous_element(&self) -> Option<(&'a ElementData, SiblingContext<'a>)> {
+        let index = self.siblings[..self.index].iter().rposition(is_element)?;
+        let context = SiblingContext {
+            siblings: self.siblings,
+            index,
+        };
+        match self.siblings[index].node_type {
+            NodeType::Element(ref elem) => Some((elem, context)),
+            NodeType::Text(_) => None,
+        }
+    }
+
+    // The 1-based position of this element among its element siblings
+    pub fn element_index(&self) -> usize {
+        self.siblings[..=self.index]
+            .iter()
+            .filter(|node| is_element(node))
+            .count()
+    }
+}
+
+fn is_element(node: &Node) -> bool {
+    matches!(node.node_type, NodeType::Element(_))
+}
+
+// Does `selector` match the element at `context`?
+pub fn matches(elem: &ElementData, context: SiblingContext, selector: &Selector) -> bool {
     match *selector {
-        Selector::Simple(ref simple_selector) => matches_simple_selector(elem, simple_selector),
+        Selector::Simple(ref simple_selector) => {
+            matches_simple_selector(elem, context, simple_selector)
+        }
+        Selector::Combined(ref sibling, Combinator::NextSibling, ref simple_selector) => {
+            matches_simple_selector(elem, context, simple_selector)
+                && context
+                    .previous_element()
+                    .is_some_and(|(previous, context)| matches(previous, context, sibling))
+        }
+        Selector::Combined(ref sibling, Combinator::SubsequentSibling, ref simple_selector) => {
+            if !matches_simple_selector(elem, context, simple_selector) {
+                return false;
+            }
+            let mut previous = context.previous_element();
+            while let Some((elem, context)) = previous {
+                if matches(elem, context, sibling) {
+                    return true;
+                }
+                previous = context.previous_element();
+            }
+            false
+        }
     }
 }
 
 impl ElementData {
-    pub fn id(&self) -> Option<&String> {
-        self.attributes.get("id")
+    pub fn id(&self) -> Option<&str> {
+        self.get_attribute("id")
     }
 
     pub fn classes(&self) -> HashSet<&str> {
-        match self.attributes.get("class") {
-            Some(classlist) => classlist.split(' ').collect(),
+        match self.get_attribute("class") {
+            Some(classlist) => classlist.split_whitespace().collect(),
             None => HashSet::new(),
         }
     }
 }
 
-fn matches_simple_selector(elem: &ElementData, selector: &SimpleSelector) -> bool {
+fn matches_simple_selector(
+    elem: &ElementData,
+    context: SiblingContext,
+    selector: &SimpleSelector,
+) -> bool {
     // Check type selector
     if selector.tag_name.iter().any(|name| elem.tag_name != *name) {
         return false;
     }
 
     // Check ID selector
-    if selector.id.iter().any(|id| elem.id() != Some(id)) {
+    if selector.id.iter().any(|id| elem.id() != Some(id.as_str())) {
         return false;
     }
 
@@ -89,59 +920,786 @@ fn matches_simple_selector(elem: &ElementData, selector: &SimpleSelector) -> boo
         return false;
     }
 
+    // Check pseudo-classes
+    if selector
+        .pseudo
+        .iter()
+        .any(|pseudo| !matches_pseudo_class(elem, context, pseudo))
+    {
+        return false;
+    }
+
     // We did'nt find any non-matching selector components
-    return true;
+    true
 }
 
-type MatchedRule<'a> = (Specificity, &'a Rule);
+fn matches_pseudo_class(elem: &ElementData, context: SiblingContext, pseudo: &PseudoClass) -> bool {
+    match *pseudo {
+        PseudoClass::FirstChild => context.is_first_child(),
+        PseudoClass::LastChild => context.is_last_child(),
+        PseudoClass::OnlyChild => context.is_first_child() && context.is_last_child(),
+        PseudoClass::NthChild(a, b) => {
+            // Matches if `index == a*n + b` for some integer n >= 0. The arithmetic is done
+            // in i64, where arguments at the ends of the i32 range can't overflow.
+            let index = context.element_index() as i64;
+            let (a, b) = (a as i64, b as i64);
+            if a == 0 {
+                index == b
+            } else {
+                let offset = index - b;
+                offset % a == 0 && offset / a >= 0
+            }
+        }
+        PseudoClass::Not(ref argument) => !matches_simple_selector(elem, context, argument),
+    }
+}
+
+// A rule that matches an element, with the specificity of its most specific matching selector
+pub type MatchedRule<'a> = (Specificity, &'a Rule);
 
-// If `rule` matches `elem`, return a `MatchRule`. Otherwise return `None`
-fn match_rule<'a>(elem: &ElementData, rule: &'a Rule) -> Option<MatchedRule<'a>> {
-    // find the first (highest-specificity) matching selector
+// If `rule` matches `elem`, return a `MatchRule`. Otherwise return `None`. Selectors with a
+// pseudo-element only match when styling that pseudo-element of `elem`.
+fn match_rule<'a>(
+    elem: &ElementData,
+    context: SiblingContext,
+    pseudo_element: Option<PseudoElement>,
+    rule: &'a Rule,
+) -> Option<MatchedRule<'a>> {
+    // The declarations of a selector list apply with the specificity of the most specific
+    // selector that matches this element, which can differ between elements. The parser
+    // sorts selectors by specificity, but rules built by hand may not be.
     rule.selectors
         .iter()
-        .find(|selector| matches(elem, *selector))
-        .map(|selector| (selector.specificity(), rule))
+        .filter(|selector| {
+            selector.pseudo_element() == pseudo_element && matches(elem, context, selector)
+        })
+        .map(Selector::specificity)
+        .max()
+        .map(|specificity| (specificity, rule))
 }
 
-// find all CSS rules that match the given element.
-fn matching_rules<'a>(elem: &ElementData, stylesheet: &'a StyleSheet) -> Vec<MatchedRule<'a>> {
+// find all CSS rules that match the given element, skipping those in `@media` blocks that
+// don't match the viewport
+fn matching_rules<'a>(
+    elem: &ElementData,
+    context: SiblingContext,
+    pseudo_element: Option<PseudoElement>,
+    stylesheet: &'a StyleSheet,
+    viewport: Viewport,
+) -> Vec<MatchedRule<'a>> {
     stylesheet
         .rules
         .iter()
-        .filter_map(|rule| match_rule(elem, rule))
+        .filter(|rule| rule.matches_media(viewport))
+        .filter_map(|rule| match_rule(elem, context, pseudo_element, rule))
         .collect()
 }
 
-// Apply styles to a single element, returning the specified values/
-fn specified_values(elem: &ElementData, stylesheet: &StyleSheet) -> PropertyMap {
-    let mut values = HashMap::new();
-    let mut rules = matching_rules(elem, stylesheet);
+// Apply styles to a single element, or to one of its pseudo-elements, returning its values
+// given those of its parent
+fn specified_values(
+    elem: &ElementData,
+    context: SiblingContext,
+    pseudo_element: Option<PseudoElement>,
+    stylesheets: &[&StyleSheet],
+    viewport: Viewport,
+    parent_values: &PropertyMap,
+) -> PropertyMap {
+    let mut matched = Vec::new();
+    for stylesheet in stylesheets {
+        matched.extend(matching_rules(
+            elem,
+            context,
+            pseudo_element,
+            stylesheet,
+            viewport,
+        ));
+    }
+
+    // The `style` attribute applies to the element itself, not its pseudo-elements. An
+    // attribute that doesn't parse is ignored.
+    let inline_style = Rule {
+        selectors: Vec::new(),
+        declarations: elem
+            .get_attribute("style")
+            .filter(|_| pseudo_element.is_none())
+            .and_then(|style| css::parse_inline_style(style.to_string()).ok())
+            .unwrap_or_default(),
+        media: Vec::new(),
+        origin: Origin::Inline,
+    };
+    matched.push(((0, 0, 0), &inline_style));
 
-    // Go through the rules from lowest to highest specificity.
-    rules.sort_by(|&(a, _), &(b, _)| a.cmp(&b));
+    cascade(matched, parent_values)
+}
 
-    for (_, rule) in rules {
+// Work out the values of an element from the rules that match it, in source order across
+// the stylesheets, and the values of its parent. Declarations override each other by
+// importance, origin, specificity and then order, and inherited properties the element
+// doesn't set come from `inherited`.
+pub fn cascade(matched: Vec<MatchedRule>, inherited: &PropertyMap) -> PropertyMap {
+    // Every matching declaration, in source order
+    let mut declarations: Vec<(CascadeKey, &Declaration)> = Vec::new();
+    for (specificity, rule) in matched {
         for declaration in &rule.declarations {
-            values.insert(declaration.name.clone(), declaration.value.clone());
+            let key = cascade_key(declaration, rule.origin, specificity, declarations.len());
+            declarations.push((key, declaration));
         }
     }
 
-    return values;
+    // Apply the declarations from lowest to highest precedence
+    declarations.sort_by_key(|&(key, _)| key);
+    let mut values = PropertyMap::new();
+    for (_, declaration) in declarations {
+        values.insert(declaration.name.clone(), declaration.value.clone());
+    }
+    compute_values(&mut values, inherited);
+
+    values
+}
+
+// The precedence of a declaration in the cascade: whether it is `!important`, then its
+// origin, then the specificity of its selector, then its position in the source
+type CascadeKey = (bool, usize, Specificity, usize);
+
+fn cascade_key(
+    declaration: &Declaration,
+    origin: Origin,
+    specificity: Specificity,
+    index: usize,
+) -> CascadeKey {
+    // Important declarations override normal ones, and the user agent's override the
+    // author's. The `style` attribute overrides stylesheets of the same importance, so from
+    // lowest to highest: user agent, author, `style`, `!important` author, `!important`
+    // `style`, `!important` user agent. Specificity only decides within one of these.
+    let origin_rank = match (declaration.important, origin) {
+        (true, Origin::UserAgent) => 3,
+        (_, origin) => origin as usize,
+    };
+    (declaration.important, origin_rank, specificity, index)
 }
 
-// Apply a stylesheet to an entire DOM tree, returning a StyledNode tree
-pub fn style_tree<'a>(root: &'a Node, stylesheet: &'a StyleSheet) -> StyledNode<'a> {
+// Apply stylesheets to an entire DOM tree, returning a StyledNode tree. The stylesheets
+// are given in cascade order: a user agent stylesheet first, then the author's. Media
+// queries are evaluated against `viewport`.
+pub fn style_tree<'a>(
+    root: &'a Node,
+    stylesheets: &[&StyleSheet],
+    viewport: Viewport,
+) -> StyledNode<'a> {
+    style_node(
+        root,
+        SiblingContext::root(root),
+        stylesheets,
+        viewport,
+        false,
+        &PropertyMap::new(),
+    )
+}
+
+// Like `style_tree`, but the descendants of `display: none` elements aren't styled, as they
+// are never laid out. The hidden elements themselves are kept, without children.
+pub fn style_tree_pruned<'a>(
+    root: &'a Node,
+    stylesheets: &[&StyleSheet],
+    viewport: Viewport,
+) -> StyledNode<'a> {
+    style_node(
+        root,
+        SiblingContext::root(root),
+        stylesheets,
+        viewport,
+        true,
+        &PropertyMap::new(),
+    )
+}
+
+// Style a node and its descendants, given its position among its siblings and the
+// values of its parent. With `prune`, the descendants of hidden elements are skipped.
+fn style_node<'a>(
+    node: &'a Node,
+    context: SiblingContext<'a>,
+    stylesheets: &[&StyleSheet],
+    viewport: Viewport,
+    prune: bool,
+    parent_values: &PropertyMap,
+) -> StyledNode<'a> {
+    let values = match node.node_type {
+        NodeType::Element(ref elem) => {
+            specified_values(elem, context, None, stylesheets, viewport, parent_values)
+        }
+        NodeType::Text(_) => cascade(Vec::new(), parent_values),
+    };
+
+    let hidden =
+        matches!(values.get("display"), Some(Value::Keyword(display)) if display == "none");
+    if prune && hidden {
+        return StyledNode {
+            node: Cow::Borrowed(node),
+            specified_values: values,
+            children: Vec::new(),
+        };
+    }
+
+    let mut children: Vec<StyledNode> = node
+        .children
+        .iter()
+        .enumerate()
+        .map(|(index, child)| {
+            let context = SiblingContext {
+                siblings: &node.children,
+                index,
+            };
+            style_node(child, context, stylesheets, viewport, prune, &values)
+        })
+        .collect();
+
+    // Generated content goes around the element's own children
+    if let NodeType::Element(ref elem) = node.node_type {
+        let generated = |pseudo_element| {
+            pseudo_element_node(
+                elem,
+                context,
+                pseudo_element,
+                stylesheets,
+                viewport,
+                &values,
+            )
+        };
+        if let Some(before) = generated(PseudoElement::Before) {
+            children.insert(0, before);
+        }
+        if let Some(after) = generated(PseudoElement::After) {
+            children.push(after);
+        }
+    }
+
     StyledNode {
-        node: root,
-        specified_values: match root.node_type {
-            NodeType::Element(ref elem) => specified_values(elem, stylesheet),
-            NodeType::Text(_) => HashMap::new(),
-        },
-        children: root
-            .children
+        node: Cow::Borrowed(node),
+        specified_values: values,
+        children,
+    }
+}
+
+// Style the `::before` or `::after` pseudo-element of `elem`, if a rule gives it `content`.
+// It is styled like an element named after the pseudo-element, holding the content as text.
+fn pseudo_element_node<'a>(
+    elem: &ElementData,
+    context: SiblingContext,
+    pseudo_element: PseudoElement,
+    stylesheets: &[&StyleSheet],
+    viewport: Viewport,
+    parent_values: &PropertyMap,
+) -> Option<StyledNode<'a>> {
+    let values = specified_values(
+        elem,
+        context,
+        Some(pseudo_element),
+        stylesheets,
+        viewport,
+        parent_values,
+    );
+    let content = match values.get("content") {
+        Some(Value::String(content)) => content.clone(),
+        _ => return None,
+    };
+
+    let mut text_values = PropertyMap::new();
+    inherit(&mut text_values, &values);
+    let text = StyledNode {
+        node: Cow::Owned(dom::text(content)),
+        specified_values: text_values,
+        children: Vec::new(),
+    };
+
+    let name = match pseudo_element {
+        PseudoElement::Before => "::before",
+        PseudoElement::After => "::after",
+    };
+    Some(StyledNode {
+        node: Cow::Owned(dom::elem(name.to_string(), dom::AttrMap::new(), Vec::new())),
+        specified_values: values,
+        children: vec![text],
+    })
+}
+
+// Compute the values of a node from its specified values and those of its parent
+fn compute_values(values: &mut PropertyMap, parent_values: &PropertyMap) {
+    resolve_variables(values, parent_values);
+
+    // `color: currentColor` is the parent's color, which the node inherits anyway
+    if values.get("color").is_some_and(is_current_color) {
+        values.remove("color");
+    }
+    inherit(values, parent_values);
+
+    // Other properties set to `currentColor` take the node's own color
+    let color = values
+        .get("color")
+        .cloned()
+        .unwrap_or(Value::ColorValue(css::Color {
+            r: 0,
+            g: 0,
+            b: 0,
+            a: 255,
+        }));
+    for value in values.values_mut() {
+        resolve_current_color(value, &color);
+    }
+}
+
+// Replace `currentColor` in a value, or in the components of a list, with `color`
+fn resolve_current_color(value: &mut Value, color: &Value) {
+    match value {
+        Value::List(components) | Value::CommaList(components) => {
+            for component in components {
+                resolve_current_color(component, color);
+            }
+        }
+        _ if is_current_color(value) => *value = color.clone(),
+        _ => {}
+    }
+}
+
+fn is_current_color(value: &Value) -> bool {
+    matches!(value, Value::Keyword(keyword) if keyword.eq_ignore_ascii_case("currentcolor"))
+}
+
+// Replace `var()` references with the values of the custom properties they name, which
+// come from the node itself or are inherited from its parent. A property whose reference
+// can't be resolved, and has no fallback, is dropped as if it wasn't specified.
+fn resolve_variables(values: &mut PropertyMap, parent_values: &PropertyMap) {
+    let custom: PropertyMap = values
+        .iter()
+        .filter(|(name, _)| css::is_custom_property(name))
+        .map(|(name, value)| (name.clone(), value.clone()))
+        .collect();
+    values.retain(|name, value| {
+        let resolved = match value {
+            // Longhands of a shorthand holding `var()` take their part of its substituted value
+            Value::PendingSubstitution(shorthand, shorthand_value) => {
+                substitute(shorthand_value, &custom, parent_values, &mut Vec::new())
+                    .and_then(|resolved| css::expand_substituted(shorthand, resolved, name))
+            }
+            _ => substitute(value, &custom, parent_values, &mut Vec::new()),
+        };
+        match resolved {
+            Some(resolved) => {
+                *value = resolved;
+                true
+            }
+            None => false,
+        }
+    });
+}
+
+// `value` with its `var()` references replaced. `seen` holds the custom properties being
+// resolved, so that properties referring to each other in a cycle fail to resolve.
+fn substitute(
+    value: &Value,
+    custom: &PropertyMap,
+    parent_values: &PropertyMap,
+    seen: &mut Vec<String>,
+) -> Option<Value> {
+    match value {
+        Value::Var(name, fallback) => {
+            let resolved = if seen.contains(name) {
+                None
+            } else if let Some(own) = custom.get(name) {
+                seen.push(name.clone());
+                let resolved = substitute(own, custom, parent_values, seen);
+                seen.pop();
+                resolved
+            } else {
+                // The parent's values have already been resolved
+                parent_values.get(name).cloned()
+            };
+            resolved.or_else(|| {
+                fallback
+                    .as_ref()
+                    .and_then(|fallback| substitute(fallback, custom, parent_values, seen))
+            })
+        }
+        Value::List(components) => {
+            // A reference to a list of values splices them into the list
+            let mut resolved = Vec::with_capacity(components.len());
+            for component in components {
+                match substitute(component, custom, parent_values, seen)? {
+                    Value::List(inner) => resolved.extend(inner),
+                    component => resolved.push(component),
+                }
+            }
+            Some(Value::List(resolved))
+        }
+        Value::CommaList(values) => values
+            .iter()
+            .map(|value| substitute(value, custom, parent_values, seen))
+            .collect::<Option<_>>()
+            .map(Value::CommaList),
+        value => Some(value.clone()),
+    }
+}
+
+// Copy the inherited properties that aren't specified in `values` from the parent's values.
+// Custom properties are always inherited.
+fn inherit(values: &mut PropertyMap, parent_values: &PropertyMap) {
+    for &name in INHERITED_PROPERTIES {
+        if let (false, Some(value)) = (values.contains_key(name), parent_values.get(name)) {
+            values.insert(name.to_string(), value.clone());
+        }
+    }
+    for (name, value) in parent_values {
+        if css::is_custom_property(name) && !values.contains_key(name) {
+            values.insert(name.clone(), value.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LIST: &str =
+        r#"<ul><li id="a"></li><li id="b"></li><li id="c"></li><li id="d"></li></ul>"#;
+
+    // The ids of the children of the root element of `html` that `selector` matches
+    fn matching(html: &str, selector: &str) -> Vec<String> {
+        let root = crate::html::parse(html.to_string()).unwrap();
+        let selector = css::parse_selector(selector).unwrap();
+        let siblings = &root.children;
+        siblings
             .iter()
-            .map(|child| style_tree(child, stylesheet))
-            .collect(),
+            .enumerate()
+            .filter_map(|(index, child)| match child.node_type {
+                NodeType::Element(ref elem)
+                    if matches(elem, SiblingContext { siblings, index }, &selector) =>
+                {
+                    elem.id().map(String::from)
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn first_child_matches_only_the_first_item() {
+        assert_eq!(matching(LIST, "li:first-child"), ["a"]);
+        let specificity = |selector| css::parse_selector(selector).unwrap().specificity();
+        assert_eq!(specificity("li:first-child"), specificity("li.item"));
+    }
+
+    #[test]
+    fn nth_child_odd_matches_the_first_and_third_rows() {
+        let table = r#"<tbody><tr id="a"></tr><tr id="b"></tr><tr id="c"></tr></tbody>"#;
+        assert_eq!(matching(table, "tr:nth-child(odd)"), ["a", "c"]);
+    }
+
+    #[test]
+    fn nth_child_matches_a_n_plus_b() {
+        assert_eq!(matching(LIST, "li:nth-child(2n+1)"), ["a", "c"]);
+        assert_eq!(matching(LIST, "li:nth-child(-n+3)"), ["a", "b", "c"]);
+        assert_eq!(matching(LIST, "li:nth-child(3)"), ["c"]);
+    }
+
+    #[test]
+    fn nth_child_with_extreme_arguments_doesnt_overflow() {
+        assert_eq!(matching(LIST, "li:nth-child(2n-2147483648)"), ["b", "d"]);
+        assert_eq!(
+            matching(LIST, "li:nth-child(-n+2147483647)"),
+            ["a", "b", "c", "d"]
+        );
+        assert!(matching(LIST, "li:nth-child(-2147483648n-2147483648)").is_empty());
+        assert!(matching(LIST, "li:nth-child(-2147483648)").is_empty());
+    }
+
+    #[test]
+    fn not_matches_the_items_without_the_class() {
+        let list = r#"<ul><li id="a"></li><li id="b" class="active"></li><li id="c"></li></ul>"#;
+        assert_eq!(matching(list, "li:not(.active)"), ["a", "c"]);
+        assert!(matching(list, "li:not(li)").is_empty());
+        let specificity = |selector| css::parse_selector(selector).unwrap().specificity();
+        assert_eq!(specificity("li:not(#b)"), specificity("li#b"));
+    }
+
+    #[test]
+    fn user_agent_stylesheet_makes_divs_blocks() {
+        let root = crate::html::parse("<div></div>".to_string()).unwrap();
+        let user_agent = css::user_agent_stylesheet();
+        let author = StyleSheet { rules: Vec::new() };
+        let viewport = Viewport::default();
+        assert!(style_tree(&root, &[&user_agent, &author], viewport).display() == Display::Block);
+        assert!(style_tree(&root, &[&author], viewport).display() == Display::Inline);
+    }
+
+    #[test]
+    fn applies_a_media_block_only_when_the_viewport_matches() {
+        let root = crate::html::parse("<div></div>".to_string()).unwrap();
+        let stylesheet = css::parse(
+            "div { width: 100px } @media (max-width: 500px) { div { width: 50px } }".to_string(),
+        )
+        .unwrap();
+        let width = |width| {
+            let viewport = Viewport {
+                width,
+                height: 600.0,
+            };
+            style_tree(&root, &[&stylesheet], viewport).value("width")
+        };
+        assert_eq!(width(400.0), Some(Value::Length(50.0, Unit::Px)));
+        assert_eq!(width(800.0), Some(Value::Length(100.0, Unit::Px)));
+    }
+
+    #[test]
+    fn pruned_tree_skips_the_children_of_hidden_elements() {
+        let root =
+            crate::html::parse("<div><p><a></a><b></b></p><i></i></div>".to_string()).unwrap();
+        let stylesheet = css::parse("p { display: none }".to_string()).unwrap();
+        let viewport = Viewport::default();
+        let full = style_tree(&root, &[&stylesheet], viewport);
+        let pruned = style_tree_pruned(&root, &[&stylesheet], viewport);
+        assert_eq!(full.iter().count(), 5);
+        assert_eq!(pruned.iter().count(), 3);
+        assert!(pruned.children[0].children.is_empty());
+    }
+
+    #[test]
+    fn next_sibling_matches_only_the_paragraph_right_after_a_heading() {
+        let html = r#"<div><p id="a"></p><h1></h1> text <p id="b"></p><p id="c"></p></div>"#;
+        assert_eq!(matching(html, "h1 + p"), ["b"]);
+    }
+
+    #[test]
+    fn subsequent_sibling_matches_every_later_paragraph() {
+        let html = r#"<div><p id="a"></p><h1></h1><p id="b"></p><span></span><p id="c"></p></div>"#;
+        assert_eq!(matching(html, "h1 ~ p"), ["b", "c"]);
+    }
+
+    #[test]
+    fn author_rules_beat_user_agent_rules_of_equal_specificity() {
+        let root = crate::html::parse("<div></div>".to_string()).unwrap();
+        let sheet = |source: &str, origin| {
+            let mut stylesheet = css::parse(source.to_string()).unwrap();
+            for rule in &mut stylesheet.rules {
+                rule.origin = origin;
+            }
+            stylesheet
+        };
+        let user_agent = sheet(
+            "div { color: #ff0000 } div { width: 1px !important }",
+            Origin::UserAgent,
+        );
+        let author = sheet(
+            "div { color: #0000ff } div { width: 2px !important }",
+            Origin::Author,
+        );
+        let viewport = Viewport::default();
+
+        // Whichever order the stylesheets are given in
+        for stylesheets in [[&user_agent, &author], [&author, &user_agent]] {
+            let styled = style_tree(&root, &stylesheets, viewport);
+            let blue = css::Color::from_hex("#0000ff").unwrap();
+            assert_eq!(styled.value("color"), Some(Value::ColorValue(blue)));
+            assert_eq!(styled.value("width"), Some(Value::Length(1.0, Unit::Px)));
+        }
+
+        // Among rules of one origin and specificity, the last one wins
+        let author = sheet(
+            "div { color: #0000ff } div { color: #00ff00 }",
+            Origin::Author,
+        );
+        let green = css::Color::from_hex("#00ff00").unwrap();
+        let styled = style_tree(&root, &[&author], viewport);
+        assert_eq!(styled.value("color"), Some(Value::ColorValue(green)));
+    }
+
+    // The specified `color` of the root element of `html`, styled with `css` alone
+    fn root_color(html: &str, css: &str) -> Option<Value> {
+        let root = crate::html::parse(html.to_string()).unwrap();
+        let stylesheet = css::parse(css.to_string()).unwrap();
+        let viewport = Viewport {
+            width: 800.0,
+            height: 600.0,
+        };
+        style_tree(&root, &[&stylesheet], viewport).value("color")
+    }
+
+    #[test]
+    fn important_style_attribute_beats_an_important_rule() {
+        let html = r#"<div id="x" style="color: #0000ff !important"></div>"#;
+        let css = "#x { color: #ff0000 !important }";
+        let blue = css::Color::from_hex("#0000ff").unwrap();
+        assert_eq!(root_color(html, css), Some(Value::ColorValue(blue)));
+    }
+
+    #[test]
+    fn important_rule_beats_a_normal_style_attribute() {
+        let html = r#"<div id="x" style="color: #0000ff"></div>"#;
+        let css = "#x { color: #ff0000 !important }";
+        let red = css::Color::from_hex("#ff0000").unwrap();
+        assert_eq!(root_color(html, css), Some(Value::ColorValue(red)));
+    }
+
+    #[test]
+    fn important_style_attribute_beats_a_normal_one() {
+        let html = r#"<div style="color: #0000ff !important; color: #ff0000"></div>"#;
+        let blue = css::Color::from_hex("#0000ff").unwrap();
+        assert_eq!(root_color(html, ""), Some(Value::ColorValue(blue)));
+    }
+
+    #[test]
+    fn substitutes_variables_inherited_from_the_parent() {
+        let root = crate::html::parse("<div><p></p><span></span></div>".to_string()).unwrap();
+        let stylesheet = css::parse(
+            "div { --brand: #ff0000 } p { color: var(--brand) }
+            span { color: var(--missing, #0000ff); width: var(--missing) }"
+                .to_string(),
+        )
+        .unwrap();
+        let styled = style_tree(&root, &[&stylesheet], Viewport::default());
+        let red = css::Color::from_hex("#ff0000").unwrap();
+        let blue = css::Color::from_hex("#0000ff").unwrap();
+        assert_eq!(
+            styled.children[0].value("color"),
+            Some(Value::ColorValue(red))
+        );
+        assert_eq!(
+            styled.children[1].value("color"),
+            Some(Value::ColorValue(blue))
+        );
+        assert_eq!(styled.children[1].value("width"), None);
+    }
+
+    #[test]
+    fn computed_color_returns_only_color_values() {
+        let root = crate::html::parse("<div></div>".to_string()).unwrap();
+        let stylesheet = css::parse(
+            "div { background-color: #102030; width: 10px;
+            color: #ff0000; border-color: currentColor }"
+                .to_string(),
+        )
+        .unwrap();
+        let styled = style_tree(&root, &[&stylesheet], Viewport::default());
+        let color = |hex| css::Color::from_hex(hex).unwrap();
+        assert_eq!(
+            styled.computed_color("background-color"),
+            Some(color("#102030"))
+        );
+        assert_eq!(
+            styled.computed_color("border-color"),
+            Some(color("#ff0000"))
+        );
+        assert_eq!(styled.computed_color("width"), None);
+        assert_eq!(styled.computed_color("outline-color"), None);
+    }
+
+    #[test]
+    fn a_selector_list_cascades_with_the_specificity_of_the_selector_that_matched() {
+        let root = crate::html::parse(
+            r#"<div><h1 class="other"></h1><p class="cls other"></p></div>"#.to_string(),
+        )
+        .unwrap();
+        let stylesheet =
+            css::parse(".other { color: #0000ff } h1, .cls { color: #ff0000 }".to_string())
+                .unwrap();
+        let styled = style_tree(&root, &[&stylesheet], Viewport::default());
+        let red = css::Color::from_hex("#ff0000").unwrap();
+        let blue = css::Color::from_hex("#0000ff").unwrap();
+        // `h1` is less specific than `.other`, so the earlier rule wins
+        assert_eq!(styled.children[0].computed_color("color"), Some(blue));
+        // `.cls` is as specific as `.other`, so the later rule wins
+        assert_eq!(styled.children[1].computed_color("color"), Some(red));
+    }
+
+    #[test]
+    fn upper_case_tags_match_lower_case_type_selectors() {
+        let red = Some(Value::ColorValue(css::Color::from_hex("#ff0000").unwrap()));
+        assert_eq!(root_color("<DIV></DIV>", "div { color: #ff0000 }"), red);
+        assert_eq!(root_color("<Div></dIV>", "DIV { color: #ff0000 }"), red);
+    }
+
+    #[test]
+    fn cascades_hand_built_matched_rules() {
+        let px = |px| Value::Length(px, Unit::Px);
+        let rule = |origin, declarations: &[(&str, Value, bool)]| Rule {
+            selectors: Vec::new(),
+            declarations: declarations
+                .iter()
+                .map(|(name, value, important)| Declaration {
+                    name: name.to_string(),
+                    value: value.clone(),
+                    important: *important,
+                })
+                .collect(),
+            media: Vec::new(),
+            origin,
+        };
+        let class = rule(
+            Origin::Author,
+            &[("width", px(10.0), false), ("height", px(5.0), false)],
+        );
+        let tag = rule(Origin::Author, &[("width", px(20.0), false)]);
+        let user_agent = rule(Origin::UserAgent, &[("height", px(7.0), true)]);
+        let matched = vec![
+            ((0, 0, 0), &user_agent),
+            ((0, 1, 0), &class),
+            ((0, 0, 1), &tag),
+        ];
+
+        let red = Value::ColorValue(css::Color::from_hex("#ff0000").unwrap());
+        let inherited = PropertyMap::from([
+            ("color".to_string(), red.clone()),
+            ("margin-top".to_string(), px(3.0)),
+        ]);
+        let values = cascade(matched, &inherited);
+        // The more specific rule wins over the later one, an important declaration wins
+        // over a normal one from a later origin, and only inherited properties are inherited
+        let expected = PropertyMap::from([
+            ("color".to_string(), red),
+            ("height".to_string(), px(7.0)),
+            ("width".to_string(), px(10.0)),
+        ]);
+        assert_eq!(values, expected);
+    }
+
+    #[test]
+    fn last_child_and_only_child_ignore_text_nodes() {
+        let list = r#"<ul> <li id="a"></li> <li id="b"></li> text </ul>"#;
+        assert_eq!(matching(list, "li:last-child"), ["b"]);
+        assert!(matching(list, "li:only-child").is_empty());
+
+        let single = r#"<ul> <li id="a"></li> </ul>"#;
+        assert_eq!(matching(single, "li:only-child"), ["a"]);
+        assert_eq!(matching(single, "li:first-child:last-child"), ["a"]);
+    }
+
+    #[test]
+    fn element_accessors_delegate_to_the_element() {
+        let root =
+            crate::html::parse(r#"<p id="intro" class="note big">Hi</p>"#.to_string()).unwrap();
+        let styled = style_tree(&root, &[], Viewport::default());
+        assert_eq!(styled.tag_name(), Some("p"));
+        assert_eq!(styled.id(), Some("intro"));
+        assert_eq!(styled.classes(), HashSet::from(["note", "big"]));
+
+        let text = &styled.children[0];
+        assert_eq!(text.tag_name(), None);
+        assert_eq!(text.id(), None);
+        assert!(text.classes().is_empty());
+    }
+
+    #[test]
+    fn iterates_over_the_tree_in_pre_order() {
+        let root = crate::html::parse(
+            r#"<div id="a"><p id="b"><span id="c"></span></p><p id="d"></p></div>"#.to_string(),
+        )
+        .unwrap();
+        let styled = style_tree(&root, &[], Viewport::default());
+        let ids: Vec<_> = styled.iter().filter_map(StyledNode::id).collect();
+        assert_eq!(ids, ["a", "b", "c", "d"]);
+
+        let root = crate::html::parse("<p>text<b></b></p>".to_string()).unwrap();
+        let styled = style_tree(&root, &[], Viewport::default());
+        let nodes: Vec<_> = styled.iter().map(|node| node.tag_name()).collect();
+        assert_eq!(nodes, [Some("p"), None, Some("b")]);
     }
 }
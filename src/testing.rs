@@ -0,0 +1,199 @@
+// An end-to-end check of the whole pipeline, for `--selftest`.
+// A bundled reference document is parsed, styled, laid out and painted, and the result is
+// compared pixel by pixel with the image it rendered to when the reference was last updated.
+
+use image::RgbaImage;
+
+use crate::css::Color;
+use crate::layout::Dimensions;
+use crate::{css, html, painting};
+
+const REFERENCE_HTML: &str = include_str!("testing/reference.html");
+const REFERENCE_CSS: &str = include_str!("testing/reference.css");
+const REFERENCE_PNG: &[u8] = include_bytes!("testing/reference.png");
+
+// The size of the viewport the reference is rendered into
+const REFERENCE_WIDTH: f32 = 400.0;
+const REFERENCE_HEIGHT: f32 = 200.0;
+
+// How far a channel may be off before a pixel counts as different
+const TOLERANCE: u8 = 2;
+
+// How different two images are, from 0 for the same pixels to 1 for black against white
+// everywhere. Each pixel differs by its largest channel difference, or not at all if that is
+// within `tolerance`, and the result is the mean over all pixels. Images of different sizes
+// are completely different.
+pub fn compare_images(a: &RgbaImage, b: &RgbaImage, tolerance: u8) -> f32 {
+    if a.dimensions() != b.dimensions() {
+        return 1.0;
+    }
+    let pixels = (a.width() * a.height()) as f32;
+    if pixels == 0.0 {
+        return 0.0;
+    }
+
+    let total: f32 = a
+        .pixels()
+        .zip(b.pixels())
+        .map(|(a, b)| {
+            let difference =
+                a.0.iter()
+                    .zip(b.0.iter())
+                    .map(|(a, b)| a.abs_diff(*b))
+                    .max()
+                    .unwrap_or(0);
+            if difference > tolerance {
+                difference as f32 / 255.0
+            } else {
+                0.0
+            }
+        })
+        .sum();
+    total / pixels
+}
+
+// Render the reference document with the user agent stylesheet, on a white background
+pub fn render_reference() -> RgbaImage {
+    let root = html::parse(REFERENCE_HTML.to_string()).expect("reference document parses");
+    let user_agent = css::user_agent_stylesheet();
+    let stylesheet = css::parse(REFERENCE_CSS.to_string()).expect("reference stylesheet parses");
+
+    let mut viewport: Dimensions = Default::default();
+    viewport.content.width = REFERENCE_WIDTH;
+    viewport.content.height = REFERENCE_HEIGHT;
+    let canvas = crate::restyle_and_layout(&root, &[&user_agent, &stylesheet], viewport, |root| {
+        painting::paint(root, viewport.content, Color::from_rgba(255, 255, 255, 255))
+    });
+    canvas.to_rgba_image()
+}
+
+// Render the reference document and compare it with the expected image. Returns the rendered
+// image and how different it is, as given by `compare_images`.
+pub fn selftest() -> (RgbaImage, f32) {
+    let expected = image::load_from_memory(REFERENCE_PNG)
+        .expect("expected reference image decodes")
+        .to_rgba8();
+    let actual = render_reference();
+    let difference = compare_images(&actual, &expected, TOLERANCE);
+    (actual, difference)
+}
+
+// Shortcuts for the unit tests of other modules, which render small documents with the user
+// agent stylesheet
+#[cfg(test)]
+pub(crate) mod fixtures {
+    use crate::css::{self, Color};
+    use crate::html;
+    use crate::layout::{self, Dimensions, LayoutBox, Rect};
+    use crate::painting::{self, Canvas};
+
+    pub const WHITE: Color = Color {
+        r: 255,
+        g: 255,
+        b: 255,
+        a: 255,
+    };
+
+    pub fn viewport(width: f32, height: f32) -> Dimensions {
+        let mut viewport: Dimensions = Default::default();
+        viewport.content.width = width;
+        viewport.content.height = height;
+        viewport
+    }
+
+    // Style and lay out `html` with `css` in a viewport of the given size, handing the layout
+    // tree to `f`
+    pub fn with_layout<T>(
+        html: &str,
+        css: &str,
+        (width, height): (f32, f32),
+        f: impl FnOnce(&LayoutBox) -> T,
+    ) -> T {
+        let root = html::parse(html.to_string()).expect("test document parses");
+        let user_agent = css::user_agent_stylesheet();
+        let stylesheet = css::parse(css.to_string()).expect("test stylesheet parses");
+        crate::restyle_and_layout(
+            &root,
+            &[&user_agent, &stylesheet],
+            viewport(width, height),
+            f,
+        )
+    }
+
+    // The text of every fragment in the layout tree, in tree order, with where it was placed
+    pub fn fragments(layout_box: &LayoutBox) -> Vec<(String, Rect)> {
+        let mut fragments: Vec<_> = layout_box
+            .fragments
+            .iter()
+            .map(|fragment| (fragment.text.clone(), fragment.rect))
+            .collect();
+        for child in &layout_box.children {
+            fragments.extend(self::fragments(child));
+        }
+        fragments
+    }
+
+    // The border box of the first element matching `selector`, in an 800x600 viewport
+    pub fn border_box(html: &str, css: &str, selector: &str) -> Rect {
+        with_layout(html, css, (800.0, 600.0), |root| {
+            layout::find_box_by_selector(root, selector).expect("selector matches a box")
+        })
+    }
+
+    // Paint `html` styled with `css` onto a white canvas of the given size
+    pub fn render(html: &str, css: &str, width: f32, height: f32) -> Canvas {
+        with_layout(html, css, (width, height), |root| {
+            painting::paint(root, viewport(width, height).content, WHITE)
+        })
+    }
+
+    pub fn pixel(canvas: &Canvas, x: usize, y: usize) -> Color {
+        canvas.pixels[y * canvas.width + x]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    const BLACK: Rgba<u8> = Rgba([0, 0, 0, 255]);
+    const WHITE: Rgba<u8> = Rgba([255, 255, 255, 255]);
+
+    #[test]
+    fn identical_images_dont_differ() {
+        let image = RgbaImage::from_pixel(4, 4, BLACK);
+        assert_eq!(compare_images(&image, &image.clone(), 0), 0.0);
+    }
+
+    #[test]
+    fn measures_the_mean_difference_beyond_the_tolerance() {
+        let black = RgbaImage::from_pixel(2, 2, BLACK);
+        assert_eq!(
+            compare_images(&black, &RgbaImage::from_pixel(2, 2, WHITE), 0),
+            1.0
+        );
+
+        // One pixel in four is white instead of black
+        let mut spotted = black.clone();
+        spotted.put_pixel(1, 1, WHITE);
+        assert_eq!(compare_images(&black, &spotted, 0), 0.25);
+
+        let nearly_black = RgbaImage::from_pixel(2, 2, Rgba([2, 1, 0, 255]));
+        assert_eq!(compare_images(&black, &nearly_black, 2), 0.0);
+        assert_eq!(compare_images(&black, &nearly_black, 1), 2.0 / 255.0);
+    }
+
+    #[test]
+    fn images_of_different_sizes_differ_completely() {
+        let a = RgbaImage::from_pixel(2, 2, BLACK);
+        let b = RgbaImage::from_pixel(2, 3, BLACK);
+        assert_eq!(compare_images(&a, &b, 255), 1.0);
+    }
+
+    #[test]
+    fn reference_renders_as_expected() {
+        let (_, difference) = selftest();
+        assert_eq!(difference, 0.0);
+    }
+}
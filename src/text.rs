@@ -0,0 +1,214 @@
+// Text measurement and whitespace handling.
+// There is no font rasterizer yet, so every glyph is given the same advance: a fixed
+// fraction of the font size.
+
+mod font;
+
+pub use self::font::{load_font_dir, loaded_fonts, select_font, Font, FontMetrics};
+
+// Initial value of `font-size`, in px
+pub const DEFAULT_FONT_SIZE: f32 = 16.0;
+
+// `line-height: normal`, as a multiple of the font size
+pub const NORMAL_LINE_HEIGHT: f32 = 1.2;
+
+// Horizontal advance of every glyph, as a multiple of the font size
+const GLYPH_ADVANCE: f32 = 0.5;
+
+// The properties that text measurement depends on
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TextStyle {
+    pub font_size: f32,
+    // Extra space between adjacent glyphs, in px
+    pub letter_spacing: f32,
+    // Extra space added to each space character, in px
+    pub word_spacing: f32,
+}
+
+// The vertical metrics of the built-in font at `font_size`, for text in a generic family or
+// with no font available
+pub fn font_metrics(font_size: f32) -> FontMetrics {
+    Font::builtin().metrics(font_size)
+}
+
+// The width of a run of text set at `font_size`
+pub fn text_width(text: &str, font_size: f32) -> f32 {
+    text.chars().count() as f32 * font_size * GLYPH_ADVANCE
+}
+
+// The width of a run of text, including letter and word spacing
+pub fn measure_text(text: &str, style: TextStyle) -> f32 {
+    let glyphs = text.chars().count();
+    let spaces = text.chars().filter(|&c| c == ' ').count();
+    text_width(text, style.font_size)
+        + glyphs.saturating_sub(1) as f32 * style.letter_spacing
+        + spaces as f32 * style.word_spacing
+}
+
+// Split a word between characters into pieces that each fit in `width`, for
+// `overflow-wrap: break-word`. Every piece has at least one character, even if that is
+// wider on its own.
+pub fn break_word(word: &str, width: f32, style: TextStyle) -> Vec<&str> {
+    let mut pieces = Vec::new();
+    let mut start = 0;
+    for (i, c) in word.char_indices() {
+        if i > start && measure_text(&word[start..i + c.len_utf8()], style) > width {
+            pieces.push(&word[start..i]);
+            start = i;
+        }
+    }
+    pieces.push(&word[start..]);
+    pieces
+}
+
+// Initial value of `tab-size`, in spaces
+pub const DEFAULT_TAB_SIZE: usize = 8;
+
+// Replace each tab in a line of preformatted text with the spaces that take it to the
+// next multiple of `tab_size` columns. The text starts `column` characters into the line.
+pub fn expand_tabs(line: &str, tab_size: usize, column: usize) -> String {
+    let mut result = String::with_capacity(line.len());
+    let mut column = column;
+
+    for c in line.chars() {
+        if c == '\t' {
+            let spaces = if tab_size == 0 {
+                0
+            } else {
+                tab_size - column % tab_size
+            };
+            result.extend(std::iter::repeat_n(' ', spaces));
+            column += spaces;
+        } else {
+            result.push(c);
+            column += 1;
+        }
+    }
+
+    result
+}
+
+// Uppercase every letter, for `text-transform: uppercase`. Letters whose uppercase form is
+// more than one character, like `ß`, are kept as they are.
+pub fn uppercase(text: &str) -> String {
+    text.chars().map(simple_uppercase).collect()
+}
+
+// Lowercase every letter, for `text-transform: lowercase`
+pub fn lowercase(text: &str) -> String {
+    text.chars()
+        .map(|c| {
+            let mut lower = c.to_lowercase();
+            match (lower.next(), lower.next()) {
+                (Some(lower), None) => lower,
+                _ => c,
+            }
+        })
+        .collect()
+}
+
+// Uppercase the first letter or digit of each word, for `text-transform: capitalize`. Words
+// start after whitespace, so punctuation in front of a word doesn't stop it from being
+// capitalized.
+pub fn capitalize(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut word_start = true;
+
+    for c in text.chars() {
+        if c.is_whitespace() {
+            word_start = true;
+            result.push(c);
+        } else if word_start && c.is_alphanumeric() {
+            word_start = false;
+            result.push(simple_uppercase(c));
+        } else {
+            result.push(c);
+        }
+    }
+
+    result
+}
+
+// The uppercase form of a character, if it is a single character
+fn simple_uppercase(c: char) -> char {
+    let mut upper = c.to_uppercase();
+    match (upper.next(), upper.next()) {
+        (Some(upper), None) => upper,
+        _ => c,
+    }
+}
+
+// Collapse every run of whitespace into a single space, as in normal (non-`pre`) text.
+// Leading and trailing whitespace collapse too, but are kept so that the caller can
+// decide whether they are significant at the surrounding inline boundaries.
+pub fn collapse_whitespace(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut in_whitespace = false;
+
+    for c in text.chars() {
+        if c.is_whitespace() {
+            if !in_whitespace {
+                result.push(' ');
+            }
+            in_whitespace = true;
+        } else {
+            result.push(c);
+            in_whitespace = false;
+        }
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expands_tabs_to_the_next_tab_stop() {
+        assert_eq!(expand_tabs("\tx", 4, 0), "    x");
+        assert_eq!(expand_tabs("ab\tx", 4, 0), "ab  x");
+        assert_eq!(expand_tabs("\tx", 0, 0), "x");
+    }
+
+    #[test]
+    fn counts_tab_stops_from_the_given_column() {
+        assert_eq!(expand_tabs("\tx", 4, 2), "  x");
+        assert_eq!(expand_tabs("\tx", 4, 4), "    x");
+    }
+
+    #[test]
+    fn adds_letter_spacing_between_glyphs_and_word_spacing_to_spaces() {
+        let plain = TextStyle {
+            font_size: 16.0,
+            letter_spacing: 0.0,
+            word_spacing: 0.0,
+        };
+        let spaced = TextStyle {
+            letter_spacing: 2.0,
+            ..plain
+        };
+        assert_eq!(measure_text("hello", plain), 40.0);
+        assert_eq!(measure_text("hello", spaced), 40.0 + 4.0 * 2.0);
+
+        let words = TextStyle {
+            word_spacing: 4.0,
+            ..plain
+        };
+        assert_eq!(
+            measure_text("a b c", words),
+            measure_text("a b c", plain) + 2.0 * 4.0
+        );
+    }
+
+    #[test]
+    fn font_metrics_scale_with_the_font_size() {
+        let metrics = font_metrics(20.0);
+        assert!(metrics.ascent > 0.0);
+        assert!((metrics.ascent + metrics.descent - 20.0).abs() < 1.0);
+        // With the line gap they make up a normal line
+        let line = metrics.ascent + metrics.descent + metrics.line_gap;
+        assert!((line - 20.0 * NORMAL_LINE_HEIGHT).abs() < 0.01);
+        assert_eq!(font_metrics(40.0).ascent, metrics.ascent * 2.0);
+    }
+}
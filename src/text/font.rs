@@ -0,0 +1,343 @@
+// Font discovery and selection.
+// Fonts are found in a directory of TrueType/OpenType files, and are identified by the family
+// name in their `name` table and whether they are italic. Their vertical metrics are read to
+// place lines and baselines, but there is no rasterizer yet, so every font's glyphs are as
+// wide as the built-in one's.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+
+use super::NORMAL_LINE_HEIGHT;
+use crate::log;
+
+// Generic families, which always resolve to the built-in font
+const GENERIC_FAMILIES: &[&str] = &["serif", "sans-serif", "monospace", "cursive", "fantasy"];
+
+// The family name given to the built-in font
+const BUILTIN_FAMILY: &str = "sans-serif";
+
+// The fonts loaded so far, in the order they were found
+static FONTS: RwLock<Vec<Font>> = RwLock::new(Vec::new());
+
+// The vertical extent of the built-in font's glyphs, as multiples of the font size. With its
+// line gap they add up to `NORMAL_LINE_HEIGHT`.
+const BUILTIN_ASCENT: f32 = 0.8;
+const BUILTIN_DESCENT: f32 = 0.2;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Font {
+    pub family: String,
+    // The file the font was loaded from, or None for the built-in font
+    pub path: Option<PathBuf>,
+    // Whether this is an italic or oblique face of its family
+    pub italic: bool,
+    // How far the glyphs reach above and below the baseline, and the extra space the font
+    // asks for between lines, as multiples of the font size
+    pub ascent: f32,
+    pub descent: f32,
+    pub line_gap: f32,
+}
+
+// The vertical metrics of a font at a particular size, in px
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FontMetrics {
+    // Distance from the baseline up to the top of the tallest glyphs
+    pub ascent: f32,
+    // Distance from the baseline down to the bottom of the lowest glyphs, as a positive number
+    pub descent: f32,
+    // Extra space between one line's descent and the next line's ascent
+    pub line_gap: f32,
+}
+
+impl Font {
+    // The font used for generic families, and when no listed family is available
+    pub fn builtin() -> Font {
+        Font {
+            family: BUILTIN_FAMILY.to_string(),
+            path: None,
+            italic: false,
+            ascent: BUILTIN_ASCENT,
+            descent: BUILTIN_DESCENT,
+            line_gap: NORMAL_LINE_HEIGHT - BUILTIN_ASCENT - BUILTIN_DESCENT,
+        }
+    }
+
+    // The font's vertical metrics when it is set at `font_size`
+    pub fn metrics(&self, font_size: f32) -> FontMetrics {
+        FontMetrics {
+            ascent: self.ascent * font_size,
+            descent: self.descent * font_size,
+            line_gap: self.line_gap * font_size,
+        }
+    }
+}
+
+impl FontMetrics {
+    // The height of a line of text for `line-height: normal`
+    pub fn line_height(&self) -> f32 {
+        self.ascent + self.descent + self.line_gap
+    }
+}
+
+// Load every `.ttf` and `.otf` file in `dir`, returning how many were loaded. Files that
+// can't be read as fonts are skipped with a warning.
+pub fn load_font_dir(dir: &Path) -> io::Result<usize> {
+    let mut paths: Vec<PathBuf> = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.extension()
+                .and_then(|extension| extension.to_str())
+                .is_some_and(|extension| {
+                    extension.eq_ignore_ascii_case("ttf") || extension.eq_ignore_ascii_case("otf")
+                })
+        })
+        .collect();
+    // Directory order is arbitrary, so sort to make selection between duplicates repeatable
+    paths.sort();
+
+    let mut loaded = Vec::new();
+    for path in paths {
+        match fs::read(&path).map(|data| (family_name(&data), data)) {
+            Ok((Some(family), data)) => {
+                let builtin = Font::builtin();
+                let (ascent, descent, line_gap) = vertical_metrics(&data).unwrap_or((
+                    builtin.ascent,
+                    builtin.descent,
+                    builtin.line_gap,
+                ));
+                loaded.push(Font {
+                    family,
+                    path: Some(path),
+                    italic: is_italic(&data),
+                    ascent,
+                    descent,
+                    line_gap,
+                })
+            }
+            Ok((None, _)) => log::warn(&format!(
+                "{} isn't a font with a family name",
+                path.display()
+            )),
+            Err(err) => log::warn(&format!("can't read font {}: {}", path.display(), err)),
+        }
+    }
+
+    let count = loaded.len();
+    FONTS.write().unwrap().extend(loaded);
+    Ok(count)
+}
+
+// All fonts loaded with `load_font_dir`
+pub fn loaded_fonts() -> Vec<Font> {
+    FONTS.read().unwrap().clone()
+}
+
+// Pick the first family in `families` that names a loaded font or a generic family. Family
+// names are matched case-insensitively, and the built-in font is used if none match. Within
+// a family, the face that is `italic` or upright as asked is preferred, falling back to
+// another face of the family. The built-in font has no italic face.
+pub fn select_font(families: &[String], italic: bool) -> Font {
+    let fonts = FONTS.read().unwrap();
+    for family in families {
+        let mut faces = fonts
+            .iter()
+            .filter(|font| font.family.eq_ignore_ascii_case(family));
+        if let Some(font) = faces.clone().find(|font| font.italic == italic) {
+            return font.clone();
+        }
+        if let Some(font) = faces.next() {
+            if italic {
+                log::note(&format!("font family {:?} has no italic face", family));
+            }
+            return font.clone();
+        }
+        if GENERIC_FAMILIES
+            .iter()
+            .any(|generic| generic.eq_ignore_ascii_case(family))
+        {
+            return Font::builtin();
+        }
+        log::note(&format!("font family {:?} isn't available", family));
+    }
+    // Without a generic family to fall back on, the text may look nothing like what was
+    // asked for
+    if !families.is_empty() {
+        log::warn(&format!(
+            "no font for font-family {:?}, falling back to the built-in {} font",
+            families.join(", "),
+            BUILTIN_FAMILY
+        ));
+    }
+    Font::builtin()
+}
+
+// Read the family name from the `name` table of a TrueType or OpenType font, preferring the
+// typographic family (name ID 16) over the legacy family (name ID 1)
+fn family_name(data: &[u8]) -> Option<String> {
+    let u16_at = |offset: usize| u16_at(data, offset);
+    let name_table = find_table(data, b"name")?;
+
+    let count = u16_at(name_table + 2)? as usize;
+    let strings = name_table + u16_at(name_table + 4)? as usize;
+    let mut family = None;
+    for i in 0..count {
+        let record = name_table + 6 + i * 12;
+        let platform = u16_at(record)?;
+        let name_id = u16_at(record + 6)?;
+        if name_id != 1 && name_id != 16 {
+            continue;
+        }
+        let length = u16_at(record + 8)? as usize;
+        let start = strings + u16_at(record + 10)? as usize;
+        let bytes = data.get(start..start + length)?;
+        let name = match platform {
+            // Unicode and Windows names are UTF-16BE
+            0 | 3 => {
+                let units: Vec<u16> = bytes
+                    .chunks_exact(2)
+                    .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+                    .collect();
+                String::from_utf16_lossy(&units)
+            }
+            // Mac names are Mac Roman, which matches ASCII
+            _ => bytes.iter().map(|&b| b as char).collect(),
+        };
+        if name.is_empty() {
+            continue;
+        }
+        if name_id == 16 {
+            return Some(name);
+        }
+        family.get_or_insert(name);
+    }
+    family
+}
+
+// Is the font an italic or oblique face? The `OS/2` table's `fsSelection` has a bit for
+// each, and fonts without that table can set the italic bit of the `head` table's
+// `macStyle`.
+fn is_italic(data: &[u8]) -> bool {
+    const ITALIC: u16 = 1 << 0;
+    const OBLIQUE: u16 = 1 << 9;
+    const MAC_ITALIC: u16 = 1 << 1;
+
+    if let Some(os2) = find_table(data, b"OS/2") {
+        if let Some(selection) = u16_at(data, os2 + 62) {
+            return selection & (ITALIC | OBLIQUE) != 0;
+        }
+    }
+    find_table(data, b"head")
+        .and_then(|head| u16_at(data, head + 44))
+        .is_some_and(|style| style & MAC_ITALIC != 0)
+}
+
+// The ascent, descent and line gap from the `hhea` table, as multiples of the em size given
+// by the `head` table's `unitsPerEm`. The descent is made positive.
+fn vertical_metrics(data: &[u8]) -> Option<(f32, f32, f32)> {
+    let units_per_em = find_table(data, b"head").and_then(|head| u16_at(data, head + 18))?;
+    if units_per_em == 0 {
+        return None;
+    }
+    let hhea = find_table(data, b"hhea")?;
+    let metric = |offset: usize| {
+        u16_at(data, hhea + offset).map(|value| value as i16 as f32 / units_per_em as f32)
+    };
+    Some((metric(4)?, -metric(6)?, metric(8)?.max(0.0)))
+}
+
+// The offset of the table with the given tag, from the table directory that follows the
+// 12-byte offset table
+fn find_table(data: &[u8], tag: &[u8; 4]) -> Option<usize> {
+    let num_tables = u16_at(data, 4)? as usize;
+    (0..num_tables)
+        .map(|i| 12 + i * 16)
+        .find(|&record| data.get(record..record + 4) == Some(tag))
+        .and_then(|record| u32_at(data, record + 8))
+        .map(|offset| offset as usize)
+}
+
+fn u16_at(data: &[u8], offset: usize) -> Option<u16> {
+    let bytes = data.get(offset..offset + 2)?;
+    Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+}
+
+fn u32_at(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset + 4)?;
+    Some(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A TrueType font with nothing in it but the family name and, for an italic face, an
+    // `OS/2` table with its italic bit set
+    fn font_data(family: &str, italic: bool) -> Vec<u8> {
+        let name: Vec<u8> = family.encode_utf16().flat_map(u16::to_be_bytes).collect();
+        let name_length = 18 + name.len();
+        let mut tables = vec![(b"name", name_length)];
+        if italic {
+            tables.push((b"OS/2", 64));
+        }
+
+        let mut data = vec![0, 1, 0, 0];
+        data.extend((tables.len() as u16).to_be_bytes());
+        data.extend([0; 6]);
+        let mut offset = 12 + tables.len() * 16;
+        for &(tag, length) in &tables {
+            data.extend(tag);
+            data.extend(0u32.to_be_bytes());
+            data.extend((offset as u32).to_be_bytes());
+            data.extend((length as u32).to_be_bytes());
+            offset += length;
+        }
+        // One name record, for name ID 1 in Windows UTF-16, then its string
+        for field in [0, 1, 18, 3, 1, 0x409, 1, name.len() as u16, 0] {
+            data.extend(field.to_be_bytes());
+        }
+        data.extend(name);
+        if italic {
+            data.extend([0; 62]);
+            data.extend(1u16.to_be_bytes());
+        }
+        data
+    }
+
+    #[test]
+    fn selects_the_italic_face_of_a_family() {
+        let dir =
+            std::env::temp_dir().join(format!("browser-engine-{}-italic", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("upright.ttf"), font_data("Italic Test", false)).unwrap();
+        fs::write(dir.join("slanted.ttf"), font_data("Italic Test", true)).unwrap();
+        fs::write(dir.join("plain.ttf"), font_data("Upright Test", false)).unwrap();
+        assert_eq!(load_font_dir(&dir).unwrap(), 3);
+
+        let families = ["italic test".to_string()];
+        assert_eq!(
+            select_font(&families, true).path,
+            Some(dir.join("slanted.ttf"))
+        );
+        assert_eq!(
+            select_font(&families, false).path,
+            Some(dir.join("upright.ttf"))
+        );
+
+        // Without an italic face, the upright one is used
+        let families = ["Upright Test".to_string()];
+        let font = select_font(&families, true);
+        assert_eq!(
+            (font.path, font.italic),
+            (Some(dir.join("plain.ttf")), false)
+        );
+    }
+
+    #[test]
+    fn falls_back_to_the_builtin_font_for_generic_families() {
+        let families = ["No Such Font".to_string(), "serif".to_string()];
+        assert_eq!(select_font(&families, false), Font::builtin());
+        assert_eq!(select_font(&[], true), Font::builtin());
+    }
+}
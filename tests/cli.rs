@@ -0,0 +1,196 @@
+// Runs the browser-engine binary the way a user would and checks what it prints
+
+use std::path::PathBuf;
+use std::process::{Command, Output};
+use std::{env, fs};
+
+// Write `contents` to a file in the temp directory, named after the test that uses it
+fn temp_file(name: &str, contents: &str) -> PathBuf {
+    let path = env::temp_dir().join(format!("browser-engine-{}-{}", std::process::id(), name));
+    fs::write(&path, contents).unwrap();
+    path
+}
+
+// A TrueType font with nothing in it but the family name, as a Windows UTF-16 name record
+fn font_named(family: &str) -> Vec<u8> {
+    let name: Vec<u8> = family.encode_utf16().flat_map(u16::to_be_bytes).collect();
+    let be16 = |n: usize| (n as u16).to_be_bytes();
+    let be32 = |n: usize| (n as u32).to_be_bytes();
+
+    // The offset table and a directory with the `name` table, which follows it at 28
+    let mut font = vec![0, 1, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0];
+    font.extend(b"name");
+    font.extend(be32(0));
+    font.extend(be32(28));
+    font.extend(be32(18 + name.len()));
+    // The `name` table: one record, for name ID 1, then its string
+    for field in [0, 1, 18, 3, 1, 0x409, 1, name.len(), 0] {
+        font.extend(be16(field));
+    }
+    font.extend(name);
+    font
+}
+
+fn run(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_browser-engine"))
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+// Render `html` with `args` and no stylesheet arguments into output.png in a directory of its
+// own, and load the image. What the binary printed is returned with it.
+fn render(name: &str, html: &str, args: &[&str]) -> (image::RgbaImage, Output) {
+    let html = temp_file(name, html);
+    let dir = html.with_extension("out");
+    fs::create_dir_all(&dir).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_browser-engine"))
+        .args(args)
+        .args(["-h", html.to_str().unwrap()])
+        .current_dir(&dir)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let image = image::open(dir.join("output.png")).unwrap().to_rgba8();
+    (image, output)
+}
+
+#[test]
+fn applies_style_elements_without_a_stylesheet_argument() {
+    let html = "<html><head><style>div { height: 20px; background: #ff0000; }</style></head>\
+                <body><div></div></body></html>";
+    let (image, _) = render("style-element.html", html, &["--quiet"]);
+    assert_eq!(image.get_pixel(400, 15).0, [255, 0, 0, 255]);
+
+    let html = "<html><body><div></div></body></html>";
+    let (image, _) = render("no-style-element.html", html, &["--quiet"]);
+    assert_eq!(image.get_pixel(400, 15).0, [255, 255, 255, 255]);
+}
+
+#[test]
+fn skips_a_linked_stylesheet_that_is_missing() {
+    let html = r#"<html><head><link rel="stylesheet" href="missing.css">
+        <style>div { height: 20px; background: #ff0000; }</style></head>
+        <body><div></div></body></html>"#;
+    let (image, output) = render("missing-link.html", html, &[]);
+    assert_eq!(image.get_pixel(400, 15).0, [255, 0, 0, 255]);
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("can't load stylesheet"), "{}", stderr);
+    assert!(stderr.contains("missing.css"), "{}", stderr);
+}
+
+#[test]
+fn timing_reports_each_phase_on_stderr() {
+    let (_, output) = render("timing.html", "<p>Hi</p>", &["--timing"]);
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    let report: Vec<(&str, &str)> = stderr
+        .lines()
+        .filter_map(|line| line.split_once(": "))
+        .collect();
+    let phases: Vec<&str> = report.iter().map(|(phase, _)| phase.trim()).collect();
+    assert_eq!(
+        phases,
+        [
+            "html parse",
+            "css parse",
+            "style",
+            "layout",
+            "paint",
+            "total"
+        ]
+    );
+    assert!(report.iter().all(|(_, time)| time.ends_with(" ms")));
+}
+
+#[test]
+fn background_fills_the_page_where_nothing_is_painted() {
+    let html = r#"<div><div style="height: 10px; background: #ff0000"></div></div>"#;
+    let (image, _) = render(
+        "background.html",
+        html,
+        &["--quiet", "--background", "#000000"],
+    );
+    assert_eq!(image.get_pixel(5, 5).0, [255, 0, 0, 255]);
+    assert_eq!(image.get_pixel(5, 300).0, [0, 0, 0, 255]);
+}
+
+#[test]
+fn verbose_reports_skipped_at_rules_and_quiet_reports_nothing() {
+    let css = temp_file(
+        "verbose.css",
+        "@font-face { font-family: X } p { width: 1px }",
+    );
+    let css = css.to_str().unwrap();
+    let (_, output) = render("verbose.html", "<p></p>", &["--verbose", "-c", css]);
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains("skipping unsupported at-rule @font-face"),
+        "{}",
+        stderr
+    );
+
+    let (_, output) = render("default.html", "<p></p>", &["-c", css]);
+    assert!(output.stderr.is_empty());
+    assert_eq!(output.stdout, b"Save output as output.png\n");
+
+    let (_, output) = render("quiet.html", "<p></p>", &["--quiet", "-c", css]);
+    assert!(output.stderr.is_empty() && output.stdout.is_empty());
+}
+
+#[test]
+fn list_fonts_prints_the_families_in_the_font_dir() {
+    let dir = env::temp_dir().join(format!("browser-engine-{}-fonts", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let font = dir.join("test-sans.ttf");
+    fs::write(&font, font_named("Test Sans")).unwrap();
+
+    let output = run(&["--list-fonts", "--font-dir", dir.to_str().unwrap()]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let expected = format!("Test Sans\t{}\nsans-serif\tbuilt-in\n", font.display());
+    assert_eq!(stdout, expected);
+}
+
+#[test]
+fn dump_style_prints_each_elements_values_under_it() {
+    let html = temp_file(
+        "dump-style.html",
+        r#"<div><p id="intro" class="lead big">Hi</p><p>There</p></div>"#,
+    );
+    let css = temp_file("dump-style.css", "#intro { color: #ff0000 }");
+    let output = run(&[
+        "--no-default-style",
+        "--dump-style",
+        "-h",
+        html.to_str().unwrap(),
+        "-c",
+        css.to_str().unwrap(),
+    ]);
+    assert!(output.status.success());
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines, ["div", "p#intro.lead.big", "  color: #ff0000", "p"]);
+}
+
+#[test]
+fn max_depth_rejects_a_deeper_document() {
+    let html = temp_file("deep.html", &("<div>".repeat(20) + &"</div>".repeat(20)));
+    let output = run(&["--max-depth", "10", "-h", html.to_str().unwrap()]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("nested more than 10 deep"), "{}", stderr);
+
+    let output = run(&["--max-depth", "ten", "-h", html.to_str().unwrap()]);
+    assert!(!output.status.success());
+
+    // Limits deeper than styling and layout can handle are refused,
+    let output = run(&["--max-depth", "9000", "-h", html.to_str().unwrap()]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("at most 1024"), "{}", stderr);
+
+    // and a document as deep as the largest limit renders
+    let deep = "<div>".repeat(1024) + "x" + &"</div>".repeat(1024);
+    render("deepest.html", &deep, &["--quiet", "--max-depth", "1024"]);
+}